@@ -0,0 +1,236 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Benchmarks of the signature-heavy paths of a transfer:
+//! its initiation at the Actor, its validation by the Elders of a section,
+//! the aggregation of their validations into a proof, and the synch of an
+//! Actor from a long history.
+//!
+//! The sections are built with the simulator, so the Elder counts and
+//! thresholds are those of a network of that size.
+//! Run with `cargo bench --features simulator`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use sn_data_types::{
+    ActorHistory, Credit, CreditAgreementProof, Keypair, PublicKey, SectionElders, Signature,
+    SignedCredit, Token, TransferValidated,
+};
+use sn_transfers::{ActorEvent, Message, Simulator, TransferActor, TransferInitiated};
+use std::iter;
+use threshold_crypto::SecretKeySet;
+
+/// The seed of the simulations and of the generated keys.
+const SEED: u64 = 0x5afe;
+/// The Elder counts of the sections validating a transfer.
+const ELDER_COUNTS: &[usize] = &[4, 7, 13, 22];
+/// The numbers of credits in the history an Actor is synched from.
+const HISTORY_LENGTHS: &[usize] = &[1_000, 2_000];
+
+fn transfer_initiation(c: &mut Criterion) {
+    let (simulator, sender, recipient) = network(ELDER_COUNTS[0]);
+    let actor = simulator.actor(&sender).expect("sender");
+    let _ = c.bench_function("transfer_initiation", |b| {
+        b.iter(|| {
+            actor
+                .transfer(Token::from_nano(10), recipient, "bench".to_string())
+                .expect("transfer")
+                .expect("transfer initiated")
+        })
+    });
+}
+
+fn validation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("validation");
+    for &elders in ELDER_COUNTS {
+        let (mut simulator, sender, recipient) = network(elders);
+        let transfer = initiated(&mut simulator, &sender, recipient);
+        let section = &simulator.sections()[0];
+        let _ = group.throughput(Throughput::Elements(elders as u64));
+        let _ = group.bench_function(BenchmarkId::from_parameter(elders), |b| {
+            b.iter(|| {
+                for elder in section.elders() {
+                    let _ = elder
+                        .store()
+                        .validate_and_sign(
+                            &transfer.signed_debit,
+                            &transfer.signed_credit,
+                            elder.signing(),
+                        )
+                        .expect("validation")
+                        .expect("validated");
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn quorum_aggregation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quorum_aggregation");
+    for &elders in ELDER_COUNTS {
+        let (mut simulator, sender, recipient) = network(elders);
+        let _ = initiated(&mut simulator, &sender, recipient);
+        for _ in 0..elders {
+            let _ = simulator.step();
+        }
+        let mut validations: Vec<TransferValidated> = simulator
+            .pending()
+            .filter_map(|message| match message {
+                Message::Validation { validation, .. } => Some(validation.clone()),
+                _ => None,
+            })
+            .collect();
+        let threshold = simulator.sections()[0].key_set().threshold();
+        validations.truncate(threshold + 1);
+        let last = validations.pop().expect("a quorum of validations");
+
+        // the Actor holds all but the last validation of the quorum,
+        // which completes the proof when received
+        let mut actor = simulator.actor(&sender).expect("sender").clone();
+        for validation in validations {
+            let received = actor
+                .receive(validation)
+                .expect("validation")
+                .expect("validation received");
+            actor
+                .apply(ActorEvent::TransferValidationReceived(received))
+                .expect("validation applied");
+        }
+        let _ = group.bench_function(BenchmarkId::from_parameter(elders), |b| {
+            b.iter(|| {
+                let received = actor
+                    .receive(last.clone())
+                    .expect("validation")
+                    .expect("validation received");
+                assert!(received.proof.is_some());
+                received
+            })
+        });
+    }
+    group.finish();
+}
+
+fn from_history(c: &mut Criterion) {
+    let mut group = c.benchmark_group("from_history");
+    let _ = group.sample_size(10);
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let keypair = Keypair::new_ed25519(&mut rng);
+    let key_set = SecretKeySet::random(0, &mut rng);
+    let actor = TransferActor::new(
+        keypair.clone(),
+        SectionElders {
+            prefix: Default::default(),
+            names: Default::default(),
+            key_set: key_set.public_keys(),
+        },
+    );
+    for &length in HISTORY_LENGTHS {
+        let history = ActorHistory {
+            credits: (0..length)
+                .map(|_| credit_proof(&mut rng, &key_set, keypair.public_key()))
+                .collect(),
+            debits: vec![],
+        };
+
+        // all of the credits are valid, and are applied
+        let mut synched = actor.clone();
+        synched
+            .apply(ActorEvent::TransfersSynched(
+                actor
+                    .from_history(history.clone())
+                    .expect("history")
+                    .expect("transfers synched"),
+            ))
+            .expect("transfers applied");
+        assert_eq!(synched.balance(), Token::from_nano(length as u64));
+
+        let _ = group.throughput(Throughput::Elements(length as u64));
+        let _ = group.bench_function(BenchmarkId::from_parameter(length), |b| {
+            b.iter_batched(
+                || history.clone(),
+                |history| actor.from_history(history).expect("history"),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// A section of the Elders, with a client credited with a balance
+/// and a client without, the keys of which are returned along with it.
+fn network(elders: usize) -> (Simulator, PublicKey, PublicKey) {
+    let mut simulator = Simulator::new(SEED, 1, elders).expect("simulator");
+    let sender = simulator
+        .add_client(0, Token::from_nano(1_000_000))
+        .expect("sender");
+    let recipient = simulator.add_client(0, Token::zero()).expect("recipient");
+    (simulator, sender, recipient)
+}
+
+/// Initiates a transfer from the sender to the recipient,
+/// and returns it as sent to the Elders for validation.
+fn initiated(
+    simulator: &mut Simulator,
+    sender: &PublicKey,
+    recipient: PublicKey,
+) -> TransferInitiated {
+    let _ = simulator
+        .transfer(sender, recipient, Token::from_nano(100))
+        .expect("transfer");
+    simulator
+        .pending()
+        .find_map(|message| match message {
+            Message::Validate { transfer, .. } => Some(transfer.clone()),
+            _ => None,
+        })
+        .expect("a transfer sent for validation")
+}
+
+/// A credit to the recipient, agreed by the key set.
+fn credit_proof(
+    rng: &mut StdRng,
+    key_set: &SecretKeySet,
+    recipient: PublicKey,
+) -> CreditAgreementProof {
+    let credit = Credit {
+        id: rng.gen(),
+        amount: Token::from_nano(1),
+        recipient,
+        msg: "bench".to_string(),
+    };
+    let signed_credit = SignedCredit {
+        actor_signature: sign(key_set, &credit),
+        credit,
+    };
+    CreditAgreementProof {
+        debiting_replicas_sig: sign(key_set, &signed_credit),
+        signed_credit,
+        debiting_replicas_keys: key_set.public_keys(),
+    }
+}
+
+fn sign<T: Serialize>(key_set: &SecretKeySet, item: &T) -> Signature {
+    let data = bincode::serialize(item).expect("serialisable");
+    let share = key_set.secret_key_share(0).sign(data);
+    key_set
+        .public_keys()
+        .combine_signatures(iter::once((0, &share)))
+        .map(Signature::Bls)
+        .expect("a signature of the key set")
+}
+
+criterion_group!(
+    benches,
+    transfer_initiation,
+    validation,
+    quorum_aggregation,
+    from_history
+);
+criterion_main!(benches);
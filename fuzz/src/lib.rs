@@ -0,0 +1,335 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! The state the fuzz targets feed their inputs to, and the seed corpus of valid inputs.
+//!
+//! Both are built from the same seeded simulation, so that the messages of the
+//! seed corpus are valid for the state: a transfer from a client in one section
+//! to a client in another, initiated but not yet sent for validation.
+
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+use sn_data_types::{
+    ActorHistory, Credit, CreditAgreementProof, Keypair, OwnerType, PublicKey, ReplicaEvent,
+    SignedCredit, SignedDebit, SignedTransferShare, Token, TransferAgreementProof,
+    TransferPropagated, TransferValidated,
+};
+use sn_transfers::{
+    ActorEvent, Error, InMemoryKnownSections, Message, MultisigActor, Simulator, TransferActor,
+    TransferReplicaStore, Wallet, WalletReplica,
+};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+use threshold_crypto::SecretKeySet;
+
+/// The seed of the simulation.
+const SEED: u64 = 0x5afe;
+/// The number of Elders of each section, of which two must sign a validation.
+const ELDERS: usize = 4;
+
+/// The state the fuzz targets feed their inputs to.
+pub struct Fixture {
+    /// The sender, with its transfer pending validation.
+    pub actor: TransferActor<Keypair>,
+    /// The wallets of an Elder of the section of the sender,
+    /// including a multisig wallet, for proposals by its co-owners.
+    pub sender_store: TransferReplicaStore,
+    /// The wallets of an Elder of the section of the recipient.
+    pub recipient_store: TransferReplicaStore,
+    /// The keys of the sections, and of the mint of the initial balances.
+    pub known_sections: InMemoryKnownSections,
+}
+
+/// The state the fuzz targets feed their inputs to, built once.
+pub fn fixture() -> &'static Fixture {
+    static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+    FIXTURE.get_or_init(|| {
+        let (simulator, sender, _, multisig) = network().expect("network");
+        let actor = simulator.actor(&sender).expect("sender").clone();
+        let mut sender_store = simulator.sections()[0].elders()[0].store().clone();
+        sender_store.insert(multisig.1).expect("multisig wallet");
+        Fixture {
+            actor,
+            sender_store,
+            recipient_store: simulator.sections()[1].elders()[0].store().clone(),
+            known_sections: simulator.known_sections().clone(),
+        }
+    })
+}
+
+/// Decodes a validation, and feeds it to `Actor::receive`.
+/// An accepted validation applies, and does not change the balance.
+/// Returns whether the input was accepted.
+pub fn actor_receive(data: &[u8]) -> bool {
+    let fixture = fixture();
+    let validation: TransferValidated = match bincode::deserialize(data) {
+        Ok(validation) => validation,
+        Err(_) => return false,
+    };
+    match fixture.actor.receive(validation) {
+        Ok(Some(received)) => {
+            let mut actor = fixture.actor.clone();
+            actor
+                .apply(ActorEvent::TransferValidationReceived(received))
+                .expect("an accepted validation applies");
+            assert_eq!(actor.balance(), fixture.actor.balance());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Decodes a history, and feeds it to `Actor::from_history`.
+/// An accepted history applies, and does not credit more than its credits.
+/// Returns whether the input was accepted.
+pub fn actor_from_history(data: &[u8]) -> bool {
+    let fixture = fixture();
+    let history: ActorHistory = match bincode::deserialize(data) {
+        Ok(history) => history,
+        Err(_) => return false,
+    };
+    let credited = history
+        .credits
+        .iter()
+        .try_fold(fixture.actor.balance(), |total, credit| {
+            total.checked_add(credit.amount())
+        });
+    match fixture.actor.from_history(history) {
+        Ok(Some(synched)) => {
+            let mut actor = fixture.actor.clone();
+            actor
+                .apply(ActorEvent::TransfersSynched(synched))
+                .expect("an accepted history applies");
+            if let Some(credited) = credited {
+                assert!(actor.balance() <= credited);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Decodes a debit and credit, and feeds them to `WalletReplica::validate`.
+/// An accepted debit corresponds to the credit, and is covered by the balance.
+/// Returns whether the input was accepted.
+pub fn replica_validate(data: &[u8]) -> bool {
+    let fixture = fixture();
+    let (signed_debit, signed_credit): (SignedDebit, SignedCredit) =
+        match bincode::deserialize(data) {
+            Ok(transfer) => transfer,
+            Err(_) => return false,
+        };
+    match fixture.sender_store.validate(&signed_debit, &signed_credit) {
+        Ok(Some(())) => {
+            assert_eq!(signed_debit.credit_id().ok(), Some(*signed_credit.id()));
+            assert_eq!(signed_debit.amount(), signed_credit.amount());
+            let balance = fixture
+                .sender_store
+                .balance(&signed_debit.sender())
+                .expect("the wallet of an accepted debit");
+            assert!(signed_debit.amount() <= balance);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Decodes a transfer share, and feeds it to `WalletReplica::propose_validation`.
+/// An accepted proposal applies, and does not change the balance.
+/// Returns whether the input was accepted.
+pub fn replica_propose_validation(data: &[u8]) -> bool {
+    let fixture = fixture();
+    let share: SignedTransferShare = match bincode::deserialize(data) {
+        Ok(share) => share,
+        Err(_) => return false,
+    };
+    match fixture.sender_store.propose_validation(&share) {
+        Ok(Some(proposed)) => {
+            let sender = proposed.sender();
+            let mut store = fixture.sender_store.clone();
+            store
+                .apply(ReplicaEvent::TransferValidationProposed(proposed))
+                .expect("an accepted proposal applies");
+            assert_eq!(
+                store.balance(&sender).ok(),
+                fixture.sender_store.balance(&sender).ok()
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Decodes a proof of agreement, and feeds it to `WalletReplica::register`.
+/// An accepted proof applies, and debits exactly its amount from the sender.
+/// Returns whether the input was accepted.
+pub fn replica_register(data: &[u8]) -> bool {
+    let fixture = fixture();
+    let proof: TransferAgreementProof = match bincode::deserialize(data) {
+        Ok(proof) => proof,
+        Err(_) => return false,
+    };
+    match fixture.sender_store.register(&proof) {
+        Ok(Some(registered)) => {
+            let sender = proof.sender();
+            let before = fixture.sender_store.balance(&sender).expect("sender");
+            let mut store = fixture.sender_store.clone();
+            store
+                .apply(ReplicaEvent::TransferRegistered(registered))
+                .expect("an accepted proof applies");
+            assert_eq!(
+                store.balance(&sender).ok(),
+                before.checked_sub(proof.amount())
+            );
+            assert!(store.total_supply().ok() <= fixture.sender_store.total_supply().ok());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Decodes a proof of a credit, and feeds it to `WalletReplica::receive_propagated`.
+/// An accepted credit applies, and credits exactly its amount to the recipient.
+/// Returns whether the input was accepted.
+pub fn replica_receive_propagated(data: &[u8]) -> bool {
+    let fixture = fixture();
+    let credit_proof: CreditAgreementProof = match bincode::deserialize(data) {
+        Ok(credit_proof) => credit_proof,
+        Err(_) => return false,
+    };
+    match fixture
+        .recipient_store
+        .receive_propagated(&credit_proof, &fixture.known_sections)
+    {
+        Ok(Some(())) => {
+            let recipient = credit_proof.recipient();
+            let before = fixture
+                .recipient_store
+                .balance(&recipient)
+                .unwrap_or_else(|_| Token::zero());
+            let mut store = fixture.recipient_store.clone();
+            store
+                .apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+                    credit_proof: credit_proof.clone(),
+                }))
+                .expect("an accepted credit applies");
+            assert_eq!(
+                store.balance(&recipient).ok(),
+                before.checked_add(credit_proof.amount())
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Writes the seed corpus of each fuzz target, to its directory under the root.
+pub fn write_seed_corpus(root: &Path) -> io::Result<()> {
+    let (mut simulator, sender, _, multisig) = network().map_err(other)?;
+    let (co_owner, _) = multisig;
+    let mut seeds = Seeds::default();
+
+    let share = co_owner
+        .propose(Token::from_nano(7), sender, "seed".to_string())
+        .map_err(other)?
+        .ok_or_else(|| other("no proposal"))?;
+    seeds.add("replica_propose_validation", &share.signed_transfer)?;
+
+    while let Some(message) = simulator.step() {
+        match message {
+            Message::Validate { transfer, .. } => seeds.add(
+                "replica_validate",
+                &(transfer.signed_debit, transfer.signed_credit),
+            )?,
+            Message::Validation { validation, .. } => seeds.add("actor_receive", &validation)?,
+            Message::Register { proof, .. } => seeds.add("replica_register", &proof)?,
+            Message::Propagate { credit_proof, .. } => {
+                seeds.add("replica_receive_propagated", &credit_proof)?
+            }
+        }
+    }
+    let history = simulator
+        .history(&sender)
+        .ok_or_else(|| other("no history"))?;
+    seeds.add("actor_from_history", &history)?;
+
+    seeds.write(root)
+}
+
+/// The seeded network of two sections, with a client in each, and a transfer
+/// initiated by the first, along with a co-owner of a multisig wallet of its section.
+fn network() -> Result<
+    (
+        Simulator,
+        PublicKey,
+        PublicKey,
+        (MultisigActor<Keypair>, WalletReplica),
+    ),
+    Error,
+> {
+    let mut simulator = Simulator::new(SEED, 2, ELDERS)?;
+    let sender = simulator.add_client(0, Token::from_nano(100))?;
+    let recipient = simulator.add_client(1, Token::from_nano(10))?;
+    let _ = simulator.transfer(&sender, recipient, Token::from_nano(30))?;
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let owners = SecretKeySet::random(1, &mut rng);
+    let owner = OwnerType::Multi(owners.public_keys());
+    let mut wallet = Wallet::new(owner.clone());
+    wallet.apply_credit(Credit {
+        id: Default::default(),
+        recipient: owner.public_key(),
+        amount: Token::from_nano(50),
+        msg: "seed".to_string(),
+    })?;
+    let key_set = simulator.sections()[0].key_set().clone();
+    let replica = WalletReplica::from_snapshot(
+        owner,
+        key_set.public_key_share(0),
+        0,
+        key_set,
+        wallet.clone(),
+        Default::default(),
+        None,
+    );
+    let keypair = Keypair::new_bls_share(0, owners.secret_key_share(0), owners.public_keys());
+    let co_owner = MultisigActor::new(keypair, 3, wallet)?;
+    Ok((simulator, sender, recipient, (co_owner, replica)))
+}
+
+/// The serialised seeds, by fuzz target.
+#[derive(Default)]
+struct Seeds(Vec<(&'static str, Vec<u8>)>);
+
+impl Seeds {
+    fn add<T: Serialize>(&mut self, target: &'static str, item: &T) -> io::Result<()> {
+        let bytes = bincode::serialize(item).map_err(other)?;
+        if !self.0.iter().any(|(t, b)| *t == target && *b == bytes) {
+            self.0.push((target, bytes));
+        }
+        Ok(())
+    }
+
+    fn write(self, root: &Path) -> io::Result<()> {
+        let mut counts = std::collections::BTreeMap::new();
+        for (target, bytes) in self.0 {
+            let dir = root.join(target);
+            fs::create_dir_all(&dir)?;
+            let count = counts.entry(target).or_insert(0);
+            fs::write(dir.join(format!("seed-{:03}", count)), bytes)?;
+            *count += 1;
+        }
+        Ok(())
+    }
+}
+
+fn other<E: ToString>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error.to_string())
+}
@@ -9,21 +9,39 @@
 use crate::StateSynched;
 
 use super::{
-    wallet::Wallet, ActorEvent, Error, Outcome, Result, TernaryResult, TransferInitiated,
-    TransferRegistrationSent, TransferValidated, TransferValidationReceived, TransfersSynched,
+    actor_snapshot::ActorSnapshot,
+    actor_store::InFlight,
+    batch::{
+        batch_credit_id, BatchTransferAgreementProof, BatchTransferInitiated,
+        BatchTransferRegistrationSent, BatchTransferValidated, BatchTransferValidationReceived,
+    },
+    escrow::{escrow_refund, is_conditional, CreditCondition, LockedCredit},
+    fees::FeePolicy,
+    history::{HistoryEntry, HistoryIndex, HistoryPage, HistoryQuery, StatementLine},
+    htlc::{hash_lock, CreditClaimed, CreditRefunded, HtlcClaimProof},
+    receipt::Receipt,
+    subscribers::{Notification, Subscribers},
+    wallet::{Wallet, WalletSnapshot},
+    ActorEvent, Error, Outcome, ReplicasUpdated, Result, SectionKeyLink, TernaryResult,
+    TransferAbandoned, TransferInitiated, TransferRegistrationSent, TransferValidated,
+    TransferValidationReceived, TransfersSynched,
 };
 use crdts::Dot;
-use itertools::Itertools;
+use itertools::{Either, Itertools};
 use log::debug;
 use sn_data_types::{
     ActorHistory, Credit, CreditAgreementProof, CreditId, Debit, DebitId, OwnerType, PublicKey,
-    SectionElders, SignatureShare, SignedCredit, SignedDebit, Signing, Token,
+    SectionElders, Signature, SignatureShare, SignedCredit, SignedDebit, Signing, Token,
     TransferAgreementProof, WalletHistory,
 };
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use std::sync::mpsc::Receiver;
 use threshold_crypto::PublicKeySet;
 
+/// By default, a transfer must be registered before the next can be initiated.
+const DEFAULT_MAX_PENDING_DEBITS: u64 = 1;
+
 /// The Actor is the part of an AT2 system
 /// that initiates transfers, by requesting Replicas
 /// to validate them, and then receive the proof of agreement.
@@ -39,13 +57,42 @@ pub struct Actor<S: Signing> {
     /// Ensures that the actor's transfer
     /// initiations (ValidateTransfer cmd) are sequential.
     next_expected_debit: u64,
-    /// When a transfer is initiated, validations are accumulated here.
-    /// After quorum is reached and proof produced, the set is cleared.
-    accumulating_validations: HashMap<DebitId, HashMap<usize, TransferValidated>>,
+    /// The max number of initiated transfers
+    /// that can await registration at the same time.
+    max_pending_debits: u64,
+    /// Transfers initiated but not yet registered, by debit counter.
+    pending_transfers: BTreeMap<u64, TransferInitiated>,
+    /// When a transfer is initiated, validations are accumulated here,
+    /// by replicas key and key share index.
+    /// After the transfer is registered, its set is cleared.
+    accumulating_validations:
+        HashMap<DebitId, HashMap<(threshold_crypto::PublicKey, usize), TransferValidated>>,
+    /// The proofs of agreed transfers not yet registered, by debit counter.
+    agreed_transfers: BTreeMap<u64, TransferAgreementProof>,
+    /// Batch transfers initiated but not yet registered, by debit counter.
+    /// They share the debit counters, and the window, with single transfers.
+    pending_batches: BTreeMap<u64, BatchTransferInitiated>,
+    /// Validations of initiated batch transfers, accumulated
+    /// the same way as those of single transfers.
+    accumulating_batch_validations:
+        HashMap<DebitId, HashMap<(threshold_crypto::PublicKey, usize), BatchTransferValidated>>,
+    /// The proofs of agreed batch transfers not yet registered, by debit counter.
+    agreed_batches: BTreeMap<u64, BatchTransferAgreementProof>,
+    /// The fee policy of our Replicas, which is included in the debit amounts.
+    fee_policy: FeePolicy,
     /// The PK Set of the Replicas
     replicas: SectionElders,
+    /// All the PK Sets our Replicas have had, oldest first.
+    /// The last one is the current.
+    replicas_chain: Vec<PublicKeySet>,
     /// A log of applied events.
     history: ActorHistory,
+    /// The registered batch transfers, which the log of applied events cannot hold.
+    batch_history: Vec<BatchTransferAgreementProof>,
+    /// The applied credits and debits, indexed for queries.
+    history_index: HistoryIndex,
+    /// The subscribers to notifications of changes to the wallet.
+    subscribers: Subscribers,
 }
 
 impl<S: Signing> Actor<S> {
@@ -56,14 +103,26 @@ impl<S: Signing> Actor<S> {
     pub fn new(signing: S, replicas: SectionElders) -> Actor<S> {
         let id = signing.id();
         let wallet = Wallet::new(id.clone());
+        let replicas_chain = vec![replicas.key_set.clone()];
         Actor {
             id,
             signing,
             replicas,
+            replicas_chain,
             wallet,
             next_expected_debit: 0,
+            max_pending_debits: DEFAULT_MAX_PENDING_DEBITS,
+            pending_transfers: Default::default(),
             accumulating_validations: Default::default(),
+            agreed_transfers: Default::default(),
+            pending_batches: Default::default(),
+            accumulating_batch_validations: Default::default(),
+            agreed_batches: Default::default(),
+            fee_policy: Default::default(),
             history: ActorHistory::empty(),
+            batch_history: vec![],
+            history_index: Default::default(),
+            subscribers: Default::default(),
         }
     }
 
@@ -85,17 +144,67 @@ impl<S: Signing> Actor<S> {
     /// Temp, for test purposes
     pub fn from_snapshot(wallet: Wallet, signing: S, replicas: SectionElders) -> Actor<S> {
         let id = wallet.id().clone();
+        let next_expected_debit = wallet.next_debit();
+        let replicas_chain = vec![replicas.key_set.clone()];
+        // the statement opens with the balance of the wallet
+        let history_index = HistoryIndex::opening(wallet.balance());
         Actor {
             id,
             signing,
             replicas,
+            replicas_chain,
             wallet,
-            next_expected_debit: 0,
+            next_expected_debit,
+            max_pending_debits: DEFAULT_MAX_PENDING_DEBITS,
+            pending_transfers: Default::default(),
             accumulating_validations: Default::default(),
+            agreed_transfers: Default::default(),
+            pending_batches: Default::default(),
+            accumulating_batch_validations: Default::default(),
+            agreed_batches: Default::default(),
+            fee_policy: Default::default(),
             history: ActorHistory::empty(),
+            batch_history: vec![],
+            history_index,
+            subscribers: Default::default(),
         }
     }
 
+    /// An Actor restored from a full snapshot of its state, and its signing.
+    /// The configuration is not part of the snapshot, and is to be set again.
+    pub fn from_actor_snapshot(signing: S, snapshot: ActorSnapshot) -> Result<Actor<S>> {
+        let id: OwnerType = snapshot.id.into();
+        if signing.id().public_key() != id.public_key() {
+            return Err(Error::InvalidOwner);
+        }
+        Ok(Actor {
+            wallet: Wallet::from(
+                id.clone(),
+                snapshot.balance,
+                snapshot.debit_version,
+                snapshot.credit_ids,
+            )
+            .with_locked(snapshot.locked),
+            id,
+            signing,
+            replicas: snapshot.replicas,
+            replicas_chain: snapshot.replicas_chain,
+            next_expected_debit: snapshot.next_expected_debit,
+            max_pending_debits: DEFAULT_MAX_PENDING_DEBITS,
+            pending_transfers: snapshot.pending_transfers,
+            accumulating_validations: snapshot.accumulating_validations,
+            agreed_transfers: snapshot.agreed_transfers,
+            pending_batches: snapshot.pending_batches,
+            accumulating_batch_validations: snapshot.accumulating_batch_validations,
+            agreed_batches: snapshot.agreed_batches,
+            fee_policy: Default::default(),
+            history: snapshot.history,
+            batch_history: snapshot.batch_history,
+            history_index: snapshot.history_index,
+            subscribers: Default::default(),
+        })
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Queries ----------------------------------
     /// -----------------------------------------------------------------
@@ -110,6 +219,11 @@ impl<S: Signing> Actor<S> {
         &self.id
     }
 
+    /// The signing of the Actor, for storing it along with the state.
+    pub(crate) fn signing(&self) -> &S {
+        &self.signing
+    }
+
     /// Query for the balance of the Actor.
     pub fn balance(&self) -> Token {
         self.wallet.balance()
@@ -125,44 +239,219 @@ impl<S: Signing> Actor<S> {
         self.replicas.clone()
     }
 
+    /// All the PK Sets our Replicas have had, oldest first.
+    pub fn replicas_chain(&self) -> &[PublicKeySet] {
+        &self.replicas_chain
+    }
+
     /// History of credits and debits
     pub fn history(&self) -> ActorHistory {
         self.history.clone()
     }
 
+    /// History of batch debits
+    pub fn batch_history(&self) -> &[BatchTransferAgreementProof] {
+        &self.batch_history
+    }
+
+    /// The credits and debits of the history matching the query, a page at a time.
+    pub fn query_history(
+        &self,
+        query: &HistoryQuery,
+        offset: usize,
+        limit: usize,
+    ) -> HistoryPage<HistoryEntry> {
+        let page = self.history_index.query(query, offset, limit);
+        HistoryPage {
+            items: page.items.into_iter().map(|line| line.entry).collect(),
+            total: page.total,
+        }
+    }
+
+    /// The statement of the wallet, a page at a time,
+    /// with the balance after each credit and debit of the history.
+    pub fn statement(&self, offset: usize, limit: usize) -> HistoryPage<StatementLine> {
+        self.history_index
+            .query(&HistoryQuery::default(), offset, limit)
+    }
+
+    /// The receipt of a registered transfer, for the recipient.
+    pub fn receipt(&self, id: &DebitId) -> Option<Receipt> {
+        self.history
+            .debits
+            .iter()
+            .find(|proof| &proof.id() == id)
+            .map(Receipt::from)
+    }
+
+    /// The receipts of a registered batch transfer, one for each of the recipients.
+    pub fn batch_receipts(&self, id: &DebitId) -> Vec<Receipt> {
+        self.batch_history
+            .iter()
+            .find(|proof| &proof.id() == id)
+            .map(Receipt::of_batch)
+            .unwrap_or_default()
+    }
+
+    /// The ids of the initiated transfers not yet registered, in the order they must be registered.
+    pub fn pending_debits(&self) -> Vec<DebitId> {
+        self.pending_transfers
+            .values()
+            .map(|t| t.id())
+            .chain(self.pending_batches.values().map(|b| b.id()))
+            .sorted_by_key(|id| id.counter)
+            .collect()
+    }
+
+    /// The transfers initiated but not yet registered, in the order they must be registered,
+    /// along with what remains to be done to complete them.
+    pub fn in_flight(&self) -> Vec<InFlight> {
+        let transfers = self.pending_transfers.iter().map(|(counter, transfer)| {
+            let in_flight = match self.agreed_transfers.get(counter) {
+                Some(proof) => InFlight::Registration(proof.clone()),
+                None => InFlight::Validation(transfer.clone()),
+            };
+            (*counter, in_flight)
+        });
+        let batches = self.pending_batches.iter().map(|(counter, batch)| {
+            let in_flight = match self.agreed_batches.get(counter) {
+                Some(proof) => InFlight::BatchRegistration(proof.clone()),
+                None => InFlight::BatchValidation(batch.clone()),
+            };
+            (*counter, in_flight)
+        });
+        transfers
+            .chain(batches)
+            .sorted_by_key(|(counter, _)| *counter)
+            .map(|(_, in_flight)| in_flight)
+            .collect()
+    }
+
+    /// The full state of the Actor, from which it can be restored
+    /// with [from_actor_snapshot](Actor::from_actor_snapshot).
+    pub fn snapshot(&self) -> ActorSnapshot {
+        let wallet: WalletSnapshot = self.wallet.to_owned().into();
+        ActorSnapshot {
+            id: self.id.clone().into(),
+            balance: wallet.balance,
+            debit_version: wallet.debit_version,
+            credit_ids: wallet.credit_ids,
+            locked: wallet.locked,
+            next_expected_debit: self.next_expected_debit,
+            pending_transfers: self.pending_transfers.clone(),
+            accumulating_validations: self.accumulating_validations.clone(),
+            agreed_transfers: self.agreed_transfers.clone(),
+            pending_batches: self.pending_batches.clone(),
+            accumulating_batch_validations: self.accumulating_batch_validations.clone(),
+            agreed_batches: self.agreed_batches.clone(),
+            replicas: self.replicas.clone(),
+            replicas_chain: self.replicas_chain.clone(),
+            history: self.history.clone(),
+            history_index: self.history_index.clone(),
+            batch_history: self.batch_history.clone(),
+        }
+    }
+
+    /// The max number of transfers that can be in flight at the same time.
+    pub fn max_pending_debits(&self) -> u64 {
+        self.max_pending_debits
+    }
+
+    /// The fee policy of our Replicas.
+    pub fn fee_policy(&self) -> FeePolicy {
+        self.fee_policy
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Config -----------------------------------
+    /// -----------------------------------------------------------------
+
+    /// Sets the max number of initiated transfers that can await registration at the same time.
+    /// Transfers initiated within this window are validated independently,
+    /// but they are still registered strictly in the order of their debit counters.
+    pub fn set_max_pending_debits(&mut self, max: u64) -> Result<()> {
+        if max == 0 {
+            return Err(Error::InvalidOperation);
+        }
+        self.max_pending_debits = max;
+        Ok(())
+    }
+
+    /// Sets the fee policy of our Replicas.
+    /// The fees are then added to the amounts debited by new transfers.
+    pub fn set_fee_policy(&mut self, fee_policy: FeePolicy) {
+        self.fee_policy = fee_policy;
+    }
+
+    /// Subscribes to notifications of incoming credits, completed debits,
+    /// balance changes and rejected validations. Drop the receiver to unsubscribe.
+    pub fn subscribe(&mut self) -> Receiver<Notification> {
+        self.subscribers.subscribe()
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Cmds -------------------------------------
     /// -----------------------------------------------------------------
 
     /// Step 1. Build a valid cmd for validation of a debit.
+    /// The msg cannot be that of a conditional credit (see [transfer_conditional]).
+    ///
+    /// [transfer_conditional]: TransferActor::transfer_conditional
     pub fn transfer(
         &self,
         amount: Token,
         recipient: PublicKey,
         msg: String,
     ) -> Outcome<TransferInitiated> {
-        if recipient == self.id() {
-            return Outcome::rejected(Error::SameSenderAndRecipient);
+        if is_conditional(&msg) {
+            return Outcome::rejected(Error::InvalidOperation);
         }
+        self.initiate(amount, recipient, msg)
+    }
 
-        let id = Dot::new(self.id(), self.wallet.next_debit());
+    /// Step 1. Build a valid cmd for validation of a debit, the credit of which
+    /// cannot be debited by the recipient until the condition is met.
+    /// The condition is signed along with the msg to the recipient.
+    pub fn transfer_conditional(
+        &self,
+        amount: Token,
+        recipient: PublicKey,
+        condition: CreditCondition,
+        msg: String,
+    ) -> Outcome<TransferInitiated> {
+        self.initiate(amount, recipient, condition.encode(&msg)?)
+    }
 
-        // ensures one debit is completed at a time
-        if self.next_expected_debit != self.wallet.next_debit() {
-            return Outcome::rejected(Error::DebitPending);
-        }
-        if self.next_expected_debit != id.counter {
-            return Outcome::rejected(Error::DebitProposed);
+    fn initiate(
+        &self,
+        amount: Token,
+        recipient: PublicKey,
+        msg: String,
+    ) -> Outcome<TransferInitiated> {
+        if recipient == self.id() {
+            return Outcome::rejected(Error::SameSenderAndRecipient);
         }
-        if amount > self.balance() {
+
+        self.validate_pending_window()?;
+        // the debit includes the fee
+        let debit_amount = match self.fee_policy.debit_amount(vec![(amount, msg.as_str())]) {
+            Some(debit_amount) => debit_amount,
+            None => return Outcome::rejected(Error::InsufficientBalance),
+        };
+        if debit_amount > self.available_balance() {
             return Outcome::rejected(Error::InsufficientBalance);
         }
 
+        let id = Dot::new(self.id(), self.next_expected_debit);
+
         if amount == Token::from_nano(0) {
             return Outcome::rejected(Error::ZeroValueTransfer);
         }
 
-        let debit = Debit { id, amount };
+        let debit = Debit {
+            id,
+            amount: debit_amount,
+        };
         let credit = Credit {
             id: debit.credit_id()?,
             recipient,
@@ -187,6 +476,95 @@ impl<S: Signing> Actor<S> {
         })
     }
 
+    /// Step 1. Build a valid cmd for validation of a batch debit,
+    /// paying each of the recipients its amount, with its msg.
+    /// The batch is validated and registered as a whole, using a single debit counter.
+    pub fn transfer_batch(
+        &self,
+        payments: Vec<(Token, PublicKey, String)>,
+    ) -> Outcome<BatchTransferInitiated> {
+        if payments.is_empty() {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        if payments
+            .iter()
+            .any(|(_, recipient, _)| recipient == &self.id())
+        {
+            return Outcome::rejected(Error::SameSenderAndRecipient);
+        }
+        if payments
+            .iter()
+            .any(|(amount, _, _)| amount == &Token::zero())
+        {
+            return Outcome::rejected(Error::ZeroValueTransfer);
+        }
+        if payments.iter().any(|(_, _, msg)| is_conditional(msg)) {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+
+        self.validate_pending_window()?;
+        // the debit includes the fee of each credit
+        let amount = match self.fee_policy.debit_amount(
+            payments
+                .iter()
+                .map(|(amount, _, msg)| (*amount, msg.as_str())),
+        ) {
+            Some(amount) => amount,
+            None => return Outcome::rejected(Error::InsufficientBalance),
+        };
+        if amount > self.available_balance() {
+            return Outcome::rejected(Error::InsufficientBalance);
+        }
+
+        let id = Dot::new(self.id(), self.next_expected_debit);
+        let debit = Debit { id, amount };
+        let actor_signature = self.signing.sign(&debit)?;
+        let signed_debit = SignedDebit {
+            debit,
+            actor_signature,
+        };
+
+        let mut signed_credits = vec![];
+        for (index, (amount, recipient, msg)) in payments.into_iter().enumerate() {
+            let credit = Credit {
+                id: batch_credit_id(&id, index)?,
+                recipient,
+                amount,
+                msg,
+            };
+            let actor_signature = self.signing.sign(&credit)?;
+            signed_credits.push(SignedCredit {
+                credit,
+                actor_signature,
+            });
+        }
+
+        Outcome::success(BatchTransferInitiated {
+            signed_debit,
+            signed_credits,
+        })
+    }
+
+    /// Step 1. Build a valid cmd for validation of a hash-time-locked debit.
+    /// The credit can be claimed by the recipient, by revealing the preimage
+    /// of the hash before the deadline epoch, and is otherwise refundable to us.
+    /// The rest of the transfer is the same as that of any other.
+    pub fn transfer_htlc(
+        &self,
+        amount: Token,
+        recipient: PublicKey,
+        hash: [u8; 32],
+        deadline_epoch: u64,
+        msg: String,
+    ) -> Outcome<TransferInitiated> {
+        let condition = CreditCondition::HashLock {
+            hash,
+            refund_to: self.id(),
+            deadline_epoch,
+        };
+        self.transfer_conditional(amount, recipient, condition, msg)
+    }
+
     /// Step 2. Receive validations from Replicas, aggregate the signatures.
     pub fn receive(&self, validation: TransferValidated) -> Outcome<TransferValidationReceived> {
         // Always verify signature first! (as to not leak any information).
@@ -208,15 +586,20 @@ impl<S: Signing> Actor<S> {
             return Err(Error::WrongValidationActor);
         }
         // check if expected this validation
-        if self.next_expected_debit != signed_debit.id().counter + 1 {
+        let counter = signed_debit.id().counter;
+        if counter < self.wallet.next_debit() || counter >= self.next_expected_debit {
             return Err(Error::OperationOutOfOrder(
                 signed_debit.id().counter,
                 self.next_expected_debit,
             ));
         }
+        // check if the validation is from the current key set of our Replicas
+        let replicas = &validation.replicas;
+        self.verify_current_replicas(replicas)?;
         // check if already received
+        let key = (replicas.public_key(), validation.replica_debit_sig.index);
         if let Some(map) = self.accumulating_validations.get(&validation.id()) {
-            if map.contains_key(&validation.replica_debit_sig.index) {
+            if map.contains_key(&key) {
                 return Err(Error::ValidatedAlready);
             }
         } else {
@@ -225,19 +608,24 @@ impl<S: Signing> Actor<S> {
 
         debug!("Actor receive stepped passed all checks");
 
-        // TODO: Cover scenario where replica keys might have changed during an ongoing transfer.
+        // If the replica keys changed during an ongoing transfer,
+        // only the validations by the current key set are combined.
         let map = self
             .accumulating_validations
             .get(&validation.id())
             .ok_or_else(|| Error::NoSetForTransferId(validation.id()))?;
+        let validations: Vec<_> = map
+            .values()
+            .filter(|v| &v.replicas == replicas)
+            .chain(vec![&validation])
+            .collect();
 
         let mut proof = None;
 
         // If the previous count of accumulated + current validation coming in here,
         // is greater than the threshold, then we have reached the numbers needed
         // to build the proof ( = threshold + 1).
-        let agreed = map.len() + 1 > self.replicas.key_set.threshold()
-            && self.replicas.key_set == validation.replicas;
+        let agreed = validations.len() > replicas.threshold();
         if agreed {
             let debit_bytes = match bincode::serialize(&signed_debit) {
                 Err(_) => return Err(Error::Serialisation("Serialization Error".to_string())),
@@ -249,52 +637,38 @@ impl<S: Signing> Actor<S> {
             };
 
             // collect sig shares
-            let debit_sig_shares: BTreeMap<_, _> = map
-                .values()
-                .chain(vec![&validation])
+            let debit_sig_shares: BTreeMap<_, _> = validations
+                .iter()
                 .map(|v| v.replica_debit_sig.clone())
                 .map(|s| (s.index, s.share))
                 .collect();
             // collect sig shares
-            let credit_sig_shares: BTreeMap<_, _> = map
-                .values()
-                .chain(vec![&validation])
+            let credit_sig_shares: BTreeMap<_, _> = validations
+                .iter()
                 .map(|v| v.replica_credit_sig.clone())
                 .map(|s| (s.index, s.share))
                 .collect();
 
             // Combine shares to produce the main signature.
-            let debit_sig = self
-                .replicas
-                .key_set
+            let debit_sig = replicas
                 .combine_signatures(&debit_sig_shares)
                 .map_err(|_| Error::CannotAggregate)?;
             // Combine shares to produce the main signature.
-            let credit_sig = self
-                .replicas
-                .key_set
+            let credit_sig = replicas
                 .combine_signatures(&credit_sig_shares)
                 .map_err(|_| Error::CannotAggregate)?;
 
-            let valid_debit = self
-                .replicas
-                .key_set
-                .public_key()
-                .verify(&debit_sig, debit_bytes);
-            let valid_credit = self
-                .replicas
-                .key_set
-                .public_key()
-                .verify(&credit_sig, credit_bytes);
+            let valid_debit = replicas.public_key().verify(&debit_sig, debit_bytes);
+            let valid_credit = replicas.public_key().verify(&credit_sig, credit_bytes);
 
             // Validate the combined signatures. If the shares were valid, this can't fail.
             if valid_debit && valid_credit {
                 proof = Some(TransferAgreementProof {
                     signed_debit: signed_debit.clone(),
-                    debit_sig: sn_data_types::Signature::Bls(debit_sig),
+                    debit_sig: Signature::Bls(debit_sig),
                     signed_credit: signed_credit.clone(),
-                    credit_sig: sn_data_types::Signature::Bls(credit_sig),
-                    debiting_replicas_keys: self.replicas.key_set.clone(),
+                    credit_sig: Signature::Bls(credit_sig),
+                    debiting_replicas_keys: replicas.clone(),
                 });
             } // else, we have some corrupt data. (todo: Do we need to act on that fact?)
         }
@@ -302,6 +676,102 @@ impl<S: Signing> Actor<S> {
         Outcome::success(TransferValidationReceived { validation, proof })
     }
 
+    /// Step 2. Receipt of the rejection, by one of our Replicas, of the validation
+    /// of a debit we initiated. Subscribers are notified of it, unless the debit
+    /// is not one of ours awaiting validations.
+    pub fn receive_rejection(&self, debit_id: DebitId, error: Error) {
+        let pending = self.accumulating_validations.contains_key(&debit_id)
+            || self.accumulating_batch_validations.contains_key(&debit_id);
+        if debit_id.actor == self.id() && pending {
+            self.subscribers.notify(Notification::ValidationRejected {
+                wallet: self.id(),
+                debit_id,
+                error,
+            });
+        }
+    }
+
+    /// Step 2. Receive validations of a batch from Replicas, aggregate the signatures.
+    pub fn receive_batch(
+        &self,
+        validation: BatchTransferValidated,
+    ) -> Outcome<BatchTransferValidationReceived> {
+        // Always verify signature first! (as to not leak any information).
+        if self.verify_batch(&validation).is_err() {
+            debug!("Invalid signature in batch transfer/actor receive step.");
+            return Err(Error::InvalidSignature);
+        }
+
+        let signed_debit = &validation.signed_debit;
+        let signed_credits = &validation.signed_credits;
+
+        // check if validation was initiated by this actor
+        if self.id() != signed_debit.sender() {
+            return Err(Error::WrongValidationActor);
+        }
+        // check if expected this validation
+        let counter = signed_debit.id().counter;
+        if counter < self.wallet.next_debit() || counter >= self.next_expected_debit {
+            return Err(Error::OperationOutOfOrder(
+                counter,
+                self.next_expected_debit,
+            ));
+        }
+        // check if the validation is from the current key set of our Replicas
+        let replicas = &validation.replicas;
+        self.verify_current_replicas(replicas)?;
+        // check if already received
+        let key = (replicas.public_key(), validation.replica_debit_sig.index);
+        let map = match self.accumulating_batch_validations.get(&validation.id()) {
+            Some(map) if map.contains_key(&key) => return Err(Error::ValidatedAlready),
+            Some(map) => map,
+            None => return Err(Error::NoSetForDebitId(validation.id())),
+        };
+
+        // only the validations by the same key set are accumulated
+        let validations: Vec<_> = map
+            .values()
+            .filter(|v| &v.replicas == replicas && &v.signed_credits == signed_credits)
+            .chain(vec![&validation])
+            .collect();
+
+        let mut proof = None;
+        if validations.len() > replicas.threshold() {
+            let combine = |shares: Vec<&SignatureShare>| {
+                let shares: BTreeMap<_, _> =
+                    shares.into_iter().map(|s| (s.index, &s.share)).collect();
+                replicas
+                    .combine_signatures(shares)
+                    .map(Signature::Bls)
+                    .map_err(|_| Error::CannotAggregate)
+            };
+            let debit_sig = combine(validations.iter().map(|v| &v.replica_debit_sig).collect())?;
+            let credit_sigs = (0..signed_credits.len())
+                .map(|i| {
+                    combine(
+                        validations
+                            .iter()
+                            .map(|v| &v.replica_credit_sigs[i])
+                            .collect(),
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let batch_proof = BatchTransferAgreementProof {
+                signed_debit: signed_debit.clone(),
+                signed_credits: signed_credits.clone(),
+                debit_sig,
+                credit_sigs,
+                debiting_replicas_keys: replicas.clone(),
+            };
+            // Validate the combined signatures. If the shares were valid, this can't fail.
+            if self.verify_batch_proof(&batch_proof).is_ok() {
+                proof = Some(batch_proof);
+            }
+        }
+
+        Outcome::success(BatchTransferValidationReceived { validation, proof })
+    }
+
     /// Step 3. Registration of an agreed transfer.
     /// (The actual sending of the registration over the wire is done by upper layer,
     /// only after that, the event is applied to the actor instance.)
@@ -323,6 +793,189 @@ impl<S: Signing> Actor<S> {
         }
     }
 
+    /// Step 3. Registration of an agreed batch transfer.
+    /// (The actual sending of the registration over the wire is done by upper layer,
+    /// only after that, the event is applied to the actor instance.)
+    pub fn register_batch(
+        &self,
+        transfer_proof: BatchTransferAgreementProof,
+    ) -> Outcome<BatchTransferRegistrationSent> {
+        // Always verify signature first! (as to not leak any information).
+        if self.verify_batch_proof(&transfer_proof).is_err() {
+            return Err(Error::InvalidSignature);
+        }
+        if self.wallet.next_debit() == transfer_proof.id().counter {
+            Outcome::success(BatchTransferRegistrationSent { transfer_proof })
+        } else {
+            Err(Error::OperationOutOfOrder(
+                transfer_proof.id().counter,
+                self.wallet.next_debit(),
+            ))
+        }
+    }
+
+    /// Update of our Replicas, after the section has changed its key.
+    /// Each new key set in the chain must be signed by the one preceding it,
+    /// starting from our current Replicas, and ending with the key set of the new Replicas.
+    /// Proofs signed by any of the key sets will still be valid, but validations
+    /// of transfers in flight are from then on only accepted from the new Replicas.
+    pub fn update_replicas(
+        &self,
+        replicas: SectionElders,
+        chain: Vec<SectionKeyLink>,
+    ) -> Outcome<ReplicasUpdated> {
+        if self.replicas_chain.contains(&replicas.key_set) {
+            return Outcome::no_change();
+        }
+        let mut current = self.replicas.key_set.clone();
+        for link in &chain {
+            let key_bytes = match bincode::serialize(&link.key_set.public_key()) {
+                Err(_) => return Err(Error::Serialisation("Could not serialise key".into())),
+                Ok(data) => data,
+            };
+            PublicKey::Bls(current.public_key())
+                .verify(&link.signature, &key_bytes)
+                .map_err(|_| Error::InvalidSignature)?;
+            current = link.key_set.clone();
+        }
+        if current != replicas.key_set {
+            return Outcome::rejected(Error::SectionKeyNeverExisted);
+        }
+        Outcome::success(ReplicasUpdated {
+            replicas,
+            chain: chain.into_iter().map(|link| link.key_set).collect(),
+        })
+    }
+
+    /// Re-broadcast of a stuck transfer.
+    /// Returns the very same signed transfer as was initiated, for sending to the Replicas again.
+    /// Replicas that have already validated it will recognise it and validate it again,
+    /// so this is always safe. Validations already received are kept.
+    /// (Applying the returned event again is a no-op.)
+    pub fn retry(&self, id: DebitId) -> Outcome<TransferInitiated> {
+        match self.pending_transfers.get(&id.counter) {
+            Some(transfer) if transfer.id() == id => Outcome::success(transfer.clone()),
+            _ => Outcome::rejected(Error::PendingTransferNotFound),
+        }
+    }
+
+    /// Re-broadcast of a stuck batch transfer.
+    /// See [retry](Actor::retry).
+    pub fn retry_batch(&self, id: DebitId) -> Outcome<BatchTransferInitiated> {
+        match self.pending_batches.get(&id.counter) {
+            Some(batch) if batch.id() == id => Outcome::success(batch.clone()),
+            _ => Outcome::rejected(Error::PendingTransferNotFound),
+        }
+    }
+
+    /// Abandons a stuck transfer, rolling back the pending state, so
+    /// that its debit counter can be used for a new transfer.
+    /// Only the most recently initiated transfer can be abandoned, and only if
+    /// no validations of it have been received.
+    /// NB: This is only safe if the transfer never reached the Replicas. A Replica
+    /// that has validated the abandoned transfer will reject any other transfer using
+    /// the same debit counter. When in doubt, [retry](Actor::retry) instead.
+    pub fn abandon(&self, id: DebitId) -> Outcome<TransferAbandoned> {
+        let pending_id = self
+            .pending_transfers
+            .get(&id.counter)
+            .map(|t| t.id())
+            .or_else(|| self.pending_batches.get(&id.counter).map(|b| b.id()));
+        if pending_id != Some(id) {
+            return Outcome::rejected(Error::PendingTransferNotFound);
+        }
+        if id.counter + 1 != self.next_expected_debit {
+            return Outcome::rejected(Error::OperationOutOfOrder(
+                id.counter,
+                self.next_expected_debit - 1,
+            ));
+        }
+        let validated = self
+            .accumulating_validations
+            .get(&id)
+            .is_some_and(|v| !v.is_empty())
+            || self
+                .accumulating_batch_validations
+                .get(&id)
+                .is_some_and(|v| !v.is_empty());
+        if validated {
+            return Outcome::rejected(Error::CannotAbandonValidatedTransfer);
+        }
+        Outcome::success(TransferAbandoned { id })
+    }
+
+    /// Signs the refund credit of an escrowed, or hash-time-locked, credit
+    /// refundable to us, for the Replicas of the wallet holding it to refund it.
+    pub fn sign_refund(&self, credit_id: &CreditId, locked: &LockedCredit) -> Result<Signature> {
+        let refund = escrow_refund(credit_id, locked)?;
+        if refund.recipient != self.id() {
+            return Err(Error::InvalidOperation);
+        }
+        Ok(self.signing.sign(&refund)?)
+    }
+
+    /// Receipt of the proof of the claim of a hash-time-locked credit to us,
+    /// made by our Replicas, which only sign a claim made before the deadline.
+    /// The claim is also verified against the hash lock of the credit.
+    pub fn receive_htlc_claim(&self, claim_proof: HtlcClaimProof) -> Outcome<CreditClaimed> {
+        let claim = &claim_proof.claim;
+        if claim.wallet != self.id() {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        match self.wallet.locked(&claim.credit_id).map(|c| &c.condition) {
+            Some(CreditCondition::HashLock { hash, .. }) => {
+                if hash != &hash_lock(&claim.preimage) {
+                    return Outcome::rejected(Error::InvalidPreimage);
+                }
+            }
+            // already claimed, or not known to us yet
+            _ => return Outcome::no_change(),
+        }
+        if !self.replicas_chain.contains(&claim_proof.replicas) {
+            return Outcome::rejected(Error::SectionKeyNeverExisted);
+        }
+        let replicas_key = PublicKey::Bls(claim_proof.replicas.public_key());
+        replicas_key
+            .verify(&claim_proof.replicas_sig, serialise(claim)?)
+            .map_err(|_| Error::InvalidSignature)?;
+        Outcome::success(CreditClaimed {
+            credit_id: claim.credit_id,
+            claim_proof,
+        })
+    }
+
+    /// Receipt of the proof of the refund of an escrowed,
+    /// or hash-time-locked, credit to us, made by our Replicas.
+    pub fn receive_refunded(
+        &self,
+        credit_id: CreditId,
+        refund_proof: CreditAgreementProof,
+    ) -> Outcome<CreditRefunded> {
+        let locked = match self.wallet.locked(&credit_id) {
+            Some(locked) => locked,
+            None => return Outcome::no_change(),
+        };
+        if refund_proof.signed_credit.credit != escrow_refund(&credit_id, locked)? {
+            return Outcome::rejected(Error::InvalidOperation);
+        } else if !self
+            .replicas_chain
+            .contains(&refund_proof.debiting_replicas_keys)
+        {
+            return Outcome::rejected(Error::SectionKeyNeverExisted);
+        }
+        let replicas_key = PublicKey::Bls(refund_proof.debiting_replicas_keys.public_key());
+        replicas_key
+            .verify(
+                &refund_proof.debiting_replicas_sig,
+                serialise(&refund_proof.signed_credit)?,
+            )
+            .map_err(|_| Error::InvalidSignature)?;
+        Outcome::success(CreditRefunded {
+            credit_id,
+            refund_proof,
+        })
+    }
+
     ///
     pub fn synch(
         &self,
@@ -357,14 +1010,24 @@ impl<S: Signing> Actor<S> {
     /// (*Non-complete means non-contiguous set or not starting immediately
     /// after current debit version.)
     pub fn from_history(&self, history: ActorHistory) -> Outcome<TransfersSynched> {
-        if history.is_empty() {
+        self.from_history_with_batches(history, vec![])
+    }
+
+    /// As [from_history](Actor::from_history), with the batch debits of the history,
+    /// which share the debit counters with the single debits.
+    pub fn from_history_with_batches(
+        &self,
+        history: ActorHistory,
+        batches: Vec<BatchTransferAgreementProof>,
+    ) -> Outcome<TransfersSynched> {
+        if history.is_empty() && batches.is_empty() {
             return Outcome::no_change();
         }
         // filter out any credits and debits already existing in current wallet
         let credits = self.validate_credits(&history.credits);
-        let debits = self.validate_debits(&history.debits);
-        if !credits.is_empty() || !debits.is_empty() {
-            Outcome::success(TransfersSynched(ActorHistory { credits, debits }))
+        let (debits, batches) = self.validate_debits(&history.debits, &batches);
+        if !credits.is_empty() || !debits.is_empty() || !batches.is_empty() {
+            Outcome::success(TransfersSynched(ActorHistory { credits, debits }, batches))
         } else {
             Err(Error::NoActorHistory) // TODO: the error is actually that credits and/or debits failed validation..
         }
@@ -391,32 +1054,44 @@ impl<S: Signing> Actor<S> {
 
     /// Filters out any debits already applied,
     /// and makes sure the returned set is a contiguous
-    /// set of debits beginning immediately after current debit version.
-    #[allow(clippy::explicit_counter_loop)]
-    fn validate_debits(&self, debits: &[TransferAgreementProof]) -> Vec<TransferAgreementProof> {
-        let mut debits: Vec<_> = debits
+    /// set of debits beginning immediately after current debit version,
+    /// be they single or batch debits.
+    fn validate_debits(
+        &self,
+        debits: &[TransferAgreementProof],
+        batches: &[BatchTransferAgreementProof],
+    ) -> (
+        Vec<TransferAgreementProof>,
+        Vec<BatchTransferAgreementProof>,
+    ) {
+        let debits: BTreeMap<_, _> = debits
             .iter()
-            .unique_by(|e| e.id())
             .filter(|transfer| self.id() == transfer.sender())
             .filter(|transfer| transfer.id().counter >= self.wallet.next_debit())
             .filter(|transfer| self.verify_transfer_proof(transfer).is_ok())
+            .map(|transfer| (transfer.id().counter, transfer))
+            .collect();
+        let batches: BTreeMap<_, _> = batches
+            .iter()
+            .filter(|batch| self.id() == batch.sender())
+            .filter(|batch| batch.id().counter >= self.wallet.next_debit())
+            .filter(|batch| self.verify_batch_proof(batch).is_ok())
+            .map(|batch| (batch.id().counter, batch))
             .collect();
 
-        debits.sort_by_key(|t| t.id().counter);
-
-        let mut iter = 0;
         let mut valid_debits = vec![];
-        for out in debits {
-            let version = out.id().counter;
-            let expected_version = iter + self.wallet.next_debit();
-            if version != expected_version {
-                break; // since it's sorted, if first is not matching, then no point continuing
+        let mut valid_batches = vec![];
+        for counter in self.wallet.next_debit().. {
+            if let Some(debit) = debits.get(&counter) {
+                valid_debits.push((*debit).clone());
+            } else if let Some(batch) = batches.get(&counter) {
+                valid_batches.push((*batch).clone());
+            } else {
+                break; // the next debit is missing, so no point continuing
             }
-            valid_debits.push(out.clone());
-            iter += 1;
         }
 
-        valid_debits
+        (valid_debits, valid_batches)
     }
 
     /// -----------------------------------------------------------------
@@ -428,42 +1103,165 @@ impl<S: Signing> Actor<S> {
     /// been properly validated before raised, and thus anything that breaks is a bug.
     pub fn apply(&mut self, event: ActorEvent) -> Result<()> {
         debug!("Transfer Actor {}: applying event {:?}", self.id(), event);
+        let balance = self.balance();
 
-        match event {
+        let applied = match event {
             ActorEvent::TransferInitiated(e) => {
-                self.next_expected_debit = e.id().counter + 1;
-                let _ = self.accumulating_validations.insert(e.id(), HashMap::new());
+                let id = e.id();
+                self.next_expected_debit = std::cmp::max(self.next_expected_debit, id.counter + 1);
+                let _ = self.accumulating_validations.entry(id).or_default();
+                let _ = self.pending_transfers.insert(id.counter, e);
                 Ok(())
             }
             ActorEvent::TransferValidationReceived(e) => {
-                match self.accumulating_validations.get_mut(&e.validation.id()) {
+                let id = e.validation.id();
+                match self.accumulating_validations.get_mut(&id) {
                     Some(map) => {
-                        let _ = map.insert(e.validation.replica_debit_sig.index, e.validation);
+                        let key = (
+                            e.validation.replicas.public_key(),
+                            e.validation.replica_debit_sig.index,
+                        );
+                        let _ = map.insert(key, e.validation);
                     }
                     None => return Err(Error::PendingTransferNotFound),
                 }
+                if let Some(proof) = e.proof {
+                    let _ = self.agreed_transfers.insert(id.counter, proof);
+                }
                 Ok(())
             }
             ActorEvent::TransferRegistrationSent(e) => {
-                self.wallet
-                    .apply_debit(e.transfer_proof.signed_debit.debit.clone())?;
-                self.accumulating_validations.clear();
+                let id = e.transfer_proof.id();
+                let debit = e.transfer_proof.signed_debit.debit.clone();
+                // the fallible steps come first, so that a failure changes nothing
+                let mut history_index = self.history_index.clone();
+                history_index.push_debit(&e.transfer_proof)?;
+                self.wallet.apply_debit(debit.clone())?;
+                self.history_index = history_index;
+                let _ = self.accumulating_validations.remove(&id);
+                let _ = self.agreed_transfers.remove(&id.counter);
+                let _ = self.pending_transfers.remove(&id.counter);
                 self.history.debits.push(e.transfer_proof);
+                self.subscribers.notify(self.debit_completed(&debit));
+                Ok(())
+            }
+            ActorEvent::BatchTransferInitiated(e) => {
+                let id = e.id();
+                self.next_expected_debit = std::cmp::max(self.next_expected_debit, id.counter + 1);
+                let _ = self.accumulating_batch_validations.entry(id).or_default();
+                let _ = self.pending_batches.insert(id.counter, e);
+                Ok(())
+            }
+            ActorEvent::BatchTransferValidationReceived(e) => {
+                let id = e.validation.id();
+                match self.accumulating_batch_validations.get_mut(&id) {
+                    Some(map) => {
+                        let key = (
+                            e.validation.replicas.public_key(),
+                            e.validation.replica_debit_sig.index,
+                        );
+                        let _ = map.insert(key, e.validation);
+                    }
+                    None => return Err(Error::PendingTransferNotFound),
+                }
+                if let Some(proof) = e.proof {
+                    let _ = self.agreed_batches.insert(id.counter, proof);
+                }
+                Ok(())
+            }
+            ActorEvent::BatchTransferRegistrationSent(e) => {
+                let id = e.transfer_proof.id();
+                let debit = e.transfer_proof.signed_debit.debit.clone();
+                // the fallible steps come first, so that a failure changes nothing
+                let mut history_index = self.history_index.clone();
+                history_index.push_batch_debit(&e.transfer_proof)?;
+                self.wallet.apply_debit(debit.clone())?;
+                self.history_index = history_index;
+                let _ = self.accumulating_batch_validations.remove(&id);
+                let _ = self.agreed_batches.remove(&id.counter);
+                let _ = self.pending_batches.remove(&id.counter);
+                self.batch_history.push(e.transfer_proof);
+                self.subscribers.notify(self.debit_completed(&debit));
+                Ok(())
+            }
+            ActorEvent::ReplicasUpdated(e) => {
+                for key_set in e.chain {
+                    if !self.replicas_chain.contains(&key_set) {
+                        self.replicas_chain.push(key_set);
+                    }
+                }
+                self.replicas = e.replicas;
+                Ok(())
+            }
+            ActorEvent::TransferAbandoned(e) => {
+                let _ = self.accumulating_validations.remove(&e.id);
+                let _ = self.pending_transfers.remove(&e.id.counter);
+                let _ = self.accumulating_batch_validations.remove(&e.id);
+                let _ = self.pending_batches.remove(&e.id.counter);
+                self.next_expected_debit = e.id.counter;
                 Ok(())
             }
             ActorEvent::TransfersSynched(e) => {
-                for credit in e.0.credits {
+                // applied to copies of the wallet and the history index,
+                // which replace them only once every transfer has applied
+                let mut wallet = self.wallet.clone();
+                let mut history_index = self.history_index.clone();
+                let mut notifications = vec![];
+                for credit in &e.0.credits {
                     // append credits _before_ debits
-                    self.wallet
-                        .apply_credit(credit.signed_credit.credit.clone())?;
-                    self.history.credits.push(credit);
+                    wallet.apply_credit(credit.signed_credit.credit.clone())?;
+                    history_index.push_credit(credit)?;
+                    notifications.push(Notification::CreditReceived {
+                        wallet: self.id(),
+                        credit_id: *credit.id(),
+                        amount: credit.amount(),
+                    });
                 }
-                for debit in e.0.debits {
-                    // append debits _after_ credits
-                    self.wallet.apply_debit(debit.signed_debit.debit.clone())?;
-                    self.history.debits.push(debit);
+                let debits =
+                    e.0.debits
+                        .into_iter()
+                        .map(|d| (d.id().counter, Either::Left(d)));
+                let batches = e.1.into_iter().map(|b| (b.id().counter, Either::Right(b)));
+                let debits: Vec<_> = debits
+                    .chain(batches)
+                    .sorted_by_key(|(counter, _)| *counter)
+                    .map(|(_, debit)| debit)
+                    .collect();
+                for debit in &debits {
+                    // append debits _after_ credits, in the order of their counters
+                    let debit = match debit {
+                        Either::Left(debit) => {
+                            history_index.push_debit(debit)?;
+                            &debit.signed_debit.debit
+                        }
+                        Either::Right(batch) => {
+                            history_index.push_batch_debit(batch)?;
+                            &batch.signed_debit.debit
+                        }
+                    };
+                    wallet.apply_debit(debit.clone())?;
+                    notifications.push(self.debit_completed(debit));
                 }
-                self.next_expected_debit = self.wallet.next_debit();
+                self.wallet = wallet;
+                self.history_index = history_index;
+                self.history.credits.extend(e.0.credits);
+                for debit in debits {
+                    match debit {
+                        Either::Left(debit) => self.history.debits.push(debit),
+                        Either::Right(batch) => self.batch_history.push(batch),
+                    }
+                }
+                self.drop_registered_transfers();
+                self.notify_all(notifications);
+                Ok(())
+            }
+            ActorEvent::CreditClaimed(e) => self.wallet.apply_release(&e.credit_id),
+            ActorEvent::CreditRefunded(e) => {
+                // the fallible steps come first, so that a failure changes nothing
+                let mut history_index = self.history_index.clone();
+                history_index.push_refund(&e.refund_proof)?;
+                self.wallet.apply_refund(&e.credit_id)?;
+                self.history_index = history_index;
                 Ok(())
             }
             ActorEvent::StateSynched(e) => {
@@ -473,17 +1271,114 @@ impl<S: Signing> Actor<S> {
                     e.debit_version,
                     e.credit_ids,
                 );
-                self.next_expected_debit = self.wallet.next_debit();
+                self.drop_registered_transfers();
+                self.history_index.push_balance(e.balance);
                 Ok(())
             }
+        };
+        if applied.is_ok() && self.balance() != balance {
+            self.subscribers.notify(Notification::BalanceChanged {
+                wallet: self.id(),
+                balance: self.balance(),
+            });
         }
+        applied
         // consider event log, to properly be able to reconstruct state from restart
     }
 
+    /// Applies the event without notifying the subscribers, and returns the
+    /// notifications it raised, to be sent once the new state has been stored.
+    pub(crate) fn apply_held(&mut self, event: ActorEvent) -> Result<Vec<Notification>> {
+        let subscribers = std::mem::take(&mut self.subscribers);
+        let held = self.subscribers.subscribe();
+        let applied = self.apply(event);
+        self.subscribers = subscribers;
+        applied.map(|()| held.try_iter().collect())
+    }
+
+    /// Sends the notifications held when applying an event.
+    pub(crate) fn notify_all(&self, notifications: Vec<Notification>) {
+        for notification in notifications {
+            self.subscribers.notify(notification);
+        }
+    }
+
+    /// Restores the state from the snapshot, as when an event applied to it
+    /// could not be stored. The signing, configuration and subscribers are kept.
+    pub(crate) fn restore(&mut self, snapshot: ActorSnapshot) -> Result<()>
+    where
+        S: Clone,
+    {
+        let mut restored = Actor::from_actor_snapshot(self.signing.clone(), snapshot)?;
+        restored.max_pending_debits = self.max_pending_debits;
+        restored.fee_policy = self.fee_policy;
+        restored.subscribers = std::mem::take(&mut self.subscribers);
+        *self = restored;
+        Ok(())
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Private methods --------------------------
     /// -----------------------------------------------------------------
 
+    fn debit_completed(&self, debit: &Debit) -> Notification {
+        Notification::DebitCompleted {
+            wallet: self.id(),
+            debit_id: debit.id(),
+            amount: debit.amount(),
+        }
+    }
+
+    /// Ensures that a new transfer is initiated in order,
+    /// and that no more than the max number of debits are in flight at a time.
+    fn validate_pending_window(&self) -> Result<()> {
+        if self.next_expected_debit < self.wallet.next_debit() {
+            return Err(Error::DebitProposed);
+        }
+        if self.next_expected_debit - self.wallet.next_debit() >= self.max_pending_debits {
+            return Err(Error::DebitPending);
+        }
+        Ok(())
+    }
+
+    /// The balance, minus the amounts of all transfers in flight.
+    fn available_balance(&self) -> Token {
+        let pending = self
+            .pending_transfers
+            .values()
+            .map(|t| t.signed_debit.amount().as_nano())
+            .chain(
+                self.pending_batches
+                    .values()
+                    .map(|b| b.signed_debit.amount().as_nano()),
+            )
+            .sum();
+        // The section time is not known here, so time-locks are left to the Replicas.
+        self.wallet
+            .unlocked_balance(u64::MAX)
+            .checked_sub(Token::from_nano(pending))
+            .unwrap_or_else(Token::zero)
+    }
+
+    /// After a synch, transfers already registered (f.ex. by another instance
+    /// of this Actor) are not in flight anymore, while the rest still are.
+    fn drop_registered_transfers(&mut self) {
+        let next_debit = self.wallet.next_debit();
+        self.pending_transfers
+            .retain(|counter, _| *counter >= next_debit);
+        self.accumulating_validations
+            .retain(|id, _| id.counter >= next_debit);
+        self.agreed_transfers
+            .retain(|counter, _| *counter >= next_debit);
+        self.pending_batches
+            .retain(|counter, _| *counter >= next_debit);
+        self.accumulating_batch_validations
+            .retain(|id, _| id.counter >= next_debit);
+        self.agreed_batches
+            .retain(|counter, _| *counter >= next_debit);
+        self.next_expected_debit = std::cmp::max(self.next_expected_debit, next_debit);
+    }
+
     /// We verify that we signed the underlying cmd,
     /// and the replica signature against the pk set included in the event.
     /// Note that we use the provided pk set to verify the event.
@@ -536,6 +1431,18 @@ impl<S: Signing> Actor<S> {
         }
     }
 
+    /// Validations are only accepted from the current key set of our Replicas,
+    /// as a key set they no longer have must not approve any further debits.
+    fn verify_current_replicas(&self, replicas: &PublicKeySet) -> Result<()> {
+        if replicas == &self.replicas.key_set {
+            Ok(())
+        } else if self.replicas_chain.contains(replicas) {
+            Err(Error::SectionKeyOutdated)
+        } else {
+            Err(Error::SectionKeyNeverExisted)
+        }
+    }
+
     /// Verify that this is a valid TransferAgreementProof over our cmd.
     fn verify_transfer_proof(&self, proof: &TransferAgreementProof) -> Result<()> {
         let signed_debit = &proof.signed_debit;
@@ -545,34 +1452,94 @@ impl<S: Signing> Actor<S> {
             return error;
         }
 
-        // Check that the proof corresponds to a/the public key set of our Replicas.
-        let valid_debit = match bincode::serialize(&proof.signed_debit) {
-            Err(_) => return Err(Error::Serialisation("Could not serialise debit".into())),
-            Ok(data) => {
-                let public_key = sn_data_types::PublicKey::Bls(self.replicas.key_set.public_key());
-                public_key.verify(&proof.debit_sig, &data).is_ok()
-            }
-        };
+        // Check that the proof corresponds to a public key set our Replicas had at some point.
+        if !self.replicas_chain.contains(&proof.debiting_replicas_keys) {
+            return Err(Error::SectionKeyNeverExisted);
+        }
+        verify_agreement_signatures(
+            &proof.debiting_replicas_keys.public_key(),
+            signed_debit,
+            signed_credit,
+            &proof.debit_sig,
+            &proof.credit_sig,
+        )
+    }
 
-        let valid_credit = match bincode::serialize(&proof.signed_credit) {
-            Err(_) => return Err(Error::Serialisation("Could not serialise credit".into())),
-            Ok(data) => {
-                let public_key = sn_data_types::PublicKey::Bls(self.replicas.key_set.public_key());
-                public_key.verify(&proof.credit_sig, &data).is_ok()
-            }
+    /// We verify that we signed the underlying batch cmd,
+    /// and the replica signatures against the pk set included in the event.
+    fn verify_batch(&self, event: &BatchTransferValidated) -> Result<()> {
+        let signed_debit = &event.signed_debit;
+        let signed_credits = &event.signed_credits;
+
+        // Check that we signed this.
+        self.verify_is_our_batch(signed_debit, signed_credits)?;
+
+        if signed_credits.len() != event.replica_credit_sigs.len() {
+            return Err(Error::InvalidSignature);
+        }
+        self.verify_share(signed_debit, &event.replica_debit_sig, &event.replicas)?;
+        for (signed_credit, sig) in signed_credits.iter().zip(&event.replica_credit_sigs) {
+            self.verify_share(signed_credit, sig, &event.replicas)?;
+        }
+        Ok(())
+    }
+
+    /// Verify that this is a valid BatchTransferAgreementProof over our cmd.
+    fn verify_batch_proof(&self, proof: &BatchTransferAgreementProof) -> Result<()> {
+        // Check that we signed this.
+        self.verify_is_our_batch(&proof.signed_debit, &proof.signed_credits)?;
+
+        // Check that the proof corresponds to a public key set our Replicas had at some point.
+        if !self.replicas_chain.contains(&proof.debiting_replicas_keys) {
+            return Err(Error::SectionKeyNeverExisted);
+        }
+        if proof.signed_credits.len() != proof.credit_sigs.len() {
+            return Err(Error::InvalidSignature);
+        }
+        let public_key = PublicKey::Bls(proof.debiting_replicas_keys.public_key());
+        let verify = |sig, item: Result<Vec<u8>>| {
+            public_key
+                .verify(sig, &item?)
+                .map_err(|_| Error::InvalidSignature)
         };
+        verify(&proof.debit_sig, serialise(&proof.signed_debit))?;
+        for (signed_credit, sig) in proof.signed_credits.iter().zip(&proof.credit_sigs) {
+            verify(sig, serialise(signed_credit))?;
+        }
+        Ok(())
+    }
 
-        if valid_debit && valid_credit {
-            Ok(())
-        } else {
-            Err(Error::InvalidSignature)
+    /// Check that we signed the batch, and that its credits belong to its debit.
+    fn verify_is_our_batch(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+    ) -> Result<()> {
+        if !self
+            .signing
+            .verify(&signed_debit.actor_signature, &signed_debit.debit)
+        {
+            return Err(Error::InvalidSignature);
         }
+        for (index, signed_credit) in signed_credits.iter().enumerate() {
+            if !self
+                .signing
+                .verify(&signed_credit.actor_signature, &signed_credit.credit)
+            {
+                return Err(Error::InvalidSignature);
+            } else if signed_credit.id() != &batch_credit_id(&signed_debit.id(), index)? {
+                return Err(Error::CreditDebitIdMismatch);
+            }
+        }
+        Ok(())
     }
 
     /// Verify that this is a valid ReceivedCredit.
     #[cfg(not(feature = "simulated-payouts"))]
     fn verify_credit_proof(&self, proof: &CreditAgreementProof) -> Result<()> {
-        let debiting_replicas_keys = PublicKey::Bls(proof.debiting_replicas_keys.public_key());
+        let debiting_replicas_keys = PublicKey::Bls(crate::known_sections::section_key(
+            &proof.debiting_replicas_keys,
+        )?);
 
         debug!("Verfying debiting_replicas_sig..!");
         // Check that the proof corresponds to a/the public key set of our Replicas.
@@ -612,15 +1579,48 @@ impl<S: Signing> Actor<S> {
     }
 }
 
+fn serialise<T: serde::Serialize>(item: &T) -> Result<Vec<u8>> {
+    bincode::serialize(item).map_err(|_| Error::Serialisation("Could not serialise item".into()))
+}
+
+/// Verify the signatures of the debiting Replicas over a transfer, with their section key.
+pub(crate) fn verify_agreement_signatures(
+    section_key: &threshold_crypto::PublicKey,
+    signed_debit: &SignedDebit,
+    signed_credit: &SignedCredit,
+    debit_sig: &Signature,
+    credit_sig: &Signature,
+) -> Result<()> {
+    let public_key = PublicKey::Bls(*section_key);
+
+    let valid_debit = match bincode::serialize(signed_debit) {
+        Err(_) => return Err(Error::Serialisation("Could not serialise debit".into())),
+        Ok(data) => public_key.verify(debit_sig, &data).is_ok(),
+    };
+
+    let valid_credit = match bincode::serialize(signed_credit) {
+        Err(_) => return Err(Error::Serialisation("Could not serialise credit".into())),
+        Ok(data) => public_key.verify(credit_sig, &data).is_ok(),
+    };
+
+    if valid_debit && valid_credit {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
 impl<S: Signing + fmt::Debug> fmt::Debug for Actor<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Actor {{ id: {:?}, signing: {:?}, wallet: {:?}, next_expected_debit: {:?}, accumulating_validations: {:?}, replicas: PkSet {{ public_key: {:?} }}}}",
+            "Actor {{ id: {:?}, signing: {:?}, wallet: {:?}, next_expected_debit: {:?}, max_pending_debits: {:?}, fee_policy: {:?}, accumulating_validations: {:?}, replicas: PkSet {{ public_key: {:?} }}}}",
             self.id,
             self.signing,
             self.wallet,
             self.next_expected_debit,
+            self.max_pending_debits,
+            self.fee_policy,
             self.accumulating_validations,
             self.replicas.key_set.public_key(),
         )
@@ -630,14 +1630,21 @@ impl<S: Signing + fmt::Debug> fmt::Debug for Actor<S> {
 #[cfg(test)]
 mod test {
     use super::{
-        Actor, ActorEvent, Error, OwnerType, Result, TransferInitiated, TransferRegistrationSent,
-        Wallet,
+        batch_credit_id, hash_lock, Actor, ActorEvent, CreditCondition, Error, FeePolicy,
+        OwnerType, Result, SectionKeyLink, TransferInitiated, TransferRegistrationSent,
+        TransfersSynched, Wallet,
+    };
+    use crate::{
+        htlc_claim_proof,
+        test_utils::{
+            get_actor_and_replicas_sk_set, get_credit, get_debit, get_transfer_validation_vec,
+        },
+        HtlcClaim, HtlcClaimProof, HtlcClaimed, ReplicaSigning, ReplicaSigningImpl,
     };
     use crdts::Dot;
     use serde::Serialize;
     use sn_data_types::{
-        Credit, Debit, Keypair, PublicKey, SectionElders, Signature, SignatureShare, Token,
-        TransferAgreementProof, TransferValidated,
+        ActorHistory, Keypair, PublicKey, SectionElders, Signature, Token, TransferAgreementProof,
     };
     use std::collections::BTreeMap;
     use threshold_crypto::{SecretKey, SecretKeySet};
@@ -720,6 +1727,35 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn failed_synch_changes_nothing() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(15)?;
+        let first = get_debit(&actor)?;
+        // the second debit is of another Actor
+        let (other, _) = get_actor_and_replicas_sk_set(15)?;
+        let second = get_debit(&other)?;
+        let debits = vec![
+            get_transfer_registration_sent(first, &sk_set)?.transfer_proof,
+            get_transfer_registration_sent(second, &sk_set)?.transfer_proof,
+        ];
+        let notifications = actor.subscribe();
+        let statement = actor.statement(0, 10);
+
+        let synched = TransfersSynched(
+            ActorHistory {
+                credits: vec![],
+                debits,
+            },
+            vec![],
+        );
+        assert!(actor.apply(ActorEvent::TransfersSynched(synched)).is_err());
+        assert_eq!(actor.balance(), Token::from_nano(15));
+        assert!(actor.history().debits.is_empty());
+        assert_eq!(actor.statement(0, 10), statement);
+        assert_eq!(notifications.try_iter().count(), 0);
+        Ok(())
+    }
+
     #[allow(clippy::needless_range_loop)]
     #[test]
     fn can_return_proof_for_validated_transfers() -> Result<()> {
@@ -750,67 +1786,363 @@ mod test {
         Ok(())
     }
 
-    fn get_debit(actor: &Actor<Keypair>) -> Result<TransferInitiated> {
-        let event = actor
-            .transfer(Token::from_nano(10), get_random_pk(), "asdf".to_string())?
+    #[test]
+    fn can_pipeline_transfers_within_window() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(40)?;
+        actor.set_max_pending_debits(3)?;
+
+        let mut transfers = vec![];
+        for _ in 0..3 {
+            let transfer = get_debit(&actor)?;
+            actor.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+            transfers.push(transfer);
+        }
+        assert_eq!(actor.pending_debits().len(), 3);
+        match actor.transfer(Token::from_nano(1), get_random_pk(), "asdf".to_string()) {
+            Err(Error::DebitPending) => (),
+            _ => panic!("Should not be able to exceed the window of pending debits."),
+        }
+
+        let proofs = transfers
+            .into_iter()
+            .map(|t| get_transfer_registration_sent(t, &sk_set))
+            .collect::<Result<Vec<_>>>()?;
+
+        // registration is strictly in order
+        match actor.register(proofs[1].transfer_proof.clone()) {
+            Err(Error::OperationOutOfOrder(1, 0)) => (),
+            _ => panic!("Should not be able to register out of order."),
+        }
+        for proof in proofs {
+            let registered = actor
+                .register(proof.transfer_proof)?
+                .ok_or(Error::RegisterProofFailed)?;
+            actor.apply(ActorEvent::TransferRegistrationSent(registered))?;
+        }
+
+        assert_eq!(Token::from_nano(10), actor.balance());
+        assert!(actor.pending_debits().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn batch_shares_debit_counters_and_balance() -> Result<()> {
+        let (mut actor, _sk_set) = get_actor_and_replicas_sk_set(20)?;
+        actor.set_max_pending_debits(2)?;
+
+        let transfer = get_debit(&actor)?;
+        actor.apply(ActorEvent::TransferInitiated(transfer))?;
+        let payments = vec![
+            (Token::from_nano(4), get_random_pk(), "a".to_string()),
+            (Token::from_nano(5), get_random_pk(), "b".to_string()),
+        ];
+        let batch = actor
+            .transfer_batch(payments)?
             .ok_or(Error::TransferCreationFailed)?;
-        Ok(event)
+        assert_eq!(batch.id().counter, 1);
+        assert_eq!(batch.signed_debit.amount(), Token::from_nano(9));
+        for (index, credit) in batch.signed_credits.iter().enumerate() {
+            assert_eq!(credit.id(), &batch_credit_id(&batch.id(), index)?);
+        }
+        actor.apply(ActorEvent::BatchTransferInitiated(batch.clone()))?;
+        assert_eq!(
+            actor.pending_debits(),
+            vec![Dot::new(actor.id(), 0), batch.id()]
+        );
+
+        actor.set_max_pending_debits(3)?;
+        // 10 + 9 of the 20 are in flight
+        let payments = vec![
+            (Token::from_nano(1), get_random_pk(), "a".to_string()),
+            (Token::from_nano(1), get_random_pk(), "b".to_string()),
+        ];
+        match actor.transfer_batch(payments) {
+            Err(Error::InsufficientBalance) => (),
+            _ => panic!("Should not be able to spend the balance of pending transfers."),
+        }
+        Ok(())
     }
 
-    fn try_serialize<T: Serialize>(value: T) -> Result<Vec<u8>> {
-        match bincode::serialize(&value) {
-            Ok(res) => Ok(res),
-            _ => Err(Error::Serialisation("Serialisation error".to_string())),
+    #[test]
+    fn includes_fees_in_batch_debit() -> Result<()> {
+        let (mut actor, _sk_set) = get_actor_and_replicas_sk_set(20)?;
+        actor.set_fee_policy(FeePolicy::SizeBased {
+            base: Token::from_nano(1),
+            per_byte: Token::from_nano(1),
+        });
+        let payments = vec![
+            (Token::from_nano(4), get_random_pk(), "a".to_string()),
+            (Token::from_nano(5), get_random_pk(), "bc".to_string()),
+        ];
+        let batch = actor
+            .transfer_batch(payments)?
+            .ok_or(Error::TransferCreationFailed)?;
+        // 4 + 2 and 5 + 3
+        assert_eq!(batch.signed_debit.amount(), Token::from_nano(14));
+        assert_eq!(batch.signed_credits[1].amount(), Token::from_nano(5));
+
+        let payments = vec![(Token::from_nano(19), get_random_pk(), "a".to_string())];
+        match actor.transfer_batch(payments) {
+            Err(Error::InsufficientBalance) => (),
+            _ => panic!("Should not be able to spend the fee on top of the balance."),
         }
+        Ok(())
     }
 
-    /// returns a vec of validated transfers from the sk_set 'replicas'
-    fn get_transfer_validation_vec(
-        transfer: TransferInitiated,
-        sk_set: &SecretKeySet,
-    ) -> Result<Vec<TransferValidated>> {
-        let signed_debit = transfer.signed_debit;
-        let signed_credit = transfer.signed_credit;
-        let serialized_signed_debit = try_serialize(&signed_debit)?;
-        let serialized_signed_credit = try_serialize(&signed_credit)?;
+    #[test]
+    fn hash_locked_credit_is_claimed_by_preimage() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let keypair = Keypair::new_ed25519(&mut rng);
+        let sk_set = SecretKeySet::random(1, &mut rng);
+        let mut wallet = Wallet::new(OwnerType::Single(keypair.public_key()));
+        wallet.apply_credit(get_credit(
+            Dot::new(get_random_pk(), 0),
+            keypair.public_key(),
+            Token::from_nano(10),
+        )?)?;
+        let condition = CreditCondition::HashLock {
+            hash: hash_lock(b"secret"),
+            refund_to: get_random_pk(),
+            deadline_epoch: 5,
+        };
+        let mut credit = get_credit(
+            Dot::new(get_random_pk(), 0),
+            keypair.public_key(),
+            Token::from_nano(5),
+        )?;
+        credit.msg = condition.encode("swap")?;
+        let credit_id = credit.id;
+        wallet.apply_credit(credit)?;
+        let replicas = SectionElders {
+            prefix: Prefix::default(),
+            names: Default::default(),
+            key_set: sk_set.public_keys(),
+        };
+        let mut actor = Actor::from_snapshot(wallet, keypair, replicas);
 
-        let sk_shares: Vec<_> = (0..7).map(|i| sk_set.secret_key_share(i)).collect();
-        let pk_set = sk_set.public_keys();
+        // the locked credit cannot be spent
+        match actor.transfer(Token::from_nano(15), get_random_pk(), "asdf".to_string()) {
+            Err(Error::InsufficientBalance) => (),
+            _ => panic!("Should not be able to spend a hash-locked credit."),
+        }
+        // nor can a plain transfer carry a condition
+        match actor.transfer(Token::from_nano(1), get_random_pk(), condition.encode("x")?) {
+            Err(Error::InvalidOperation) => (),
+            _ => panic!("Should not be able to pass a condition as a plain msg."),
+        }
+        // a claim is proven by a quorum of our Replicas
+        let claim_proof = |preimage: &[u8], sk_set: &SecretKeySet| -> Result<HtlcClaimProof> {
+            let claim = HtlcClaim {
+                wallet: actor.id(),
+                credit_id,
+                preimage: preimage.to_vec(),
+            };
+            let claims = (0..=sk_set.threshold())
+                .map(|index| {
+                    let signing = ReplicaSigningImpl::new(
+                        sk_set.secret_key_share(index),
+                        index,
+                        sk_set.public_keys(),
+                    );
+                    Ok(HtlcClaimed {
+                        replica_sig: signing.sign_htlc_claim(&claim)?,
+                        claim: claim.clone(),
+                        replicas: sk_set.public_keys(),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            htlc_claim_proof(&claims)?.ok_or(Error::CannotAggregate)
+        };
+        match actor.receive_htlc_claim(claim_proof(b"guess", &sk_set)?) {
+            Err(Error::InvalidPreimage) => (),
+            _ => panic!("Should not accept a claim with the wrong preimage."),
+        }
+        let other_replicas = SecretKeySet::random(1, &mut rng);
+        match actor.receive_htlc_claim(claim_proof(b"secret", &other_replicas)?) {
+            Err(Error::SectionKeyNeverExisted) => (),
+            _ => panic!("Should not accept a claim not proven by our Replicas."),
+        }
+        let mut forged = claim_proof(b"secret", &other_replicas)?;
+        forged.replicas = sk_set.public_keys();
+        match actor.receive_htlc_claim(forged) {
+            Err(Error::InvalidSignature) => (),
+            _ => panic!("Should not accept a claim not signed by our Replicas."),
+        }
+        let claimed = actor
+            .receive_htlc_claim(claim_proof(b"secret", &sk_set)?)?
+            .ok_or(Error::InvalidOperation)?;
+        actor.apply(ActorEvent::CreditClaimed(claimed))?;
+        assert!(actor
+            .transfer(Token::from_nano(15), get_random_pk(), "asdf".to_string())?
+            .is_some());
+        Ok(())
+    }
 
-        let debit_sig_shares: BTreeMap<_, _> = (0..7)
-            .map(|i| (i, sk_shares[i].sign(serialized_signed_debit.clone())))
-            .collect();
-        let credit_sig_shares: BTreeMap<_, _> = (0..7)
-            .map(|i| (i, sk_shares[i].sign(serialized_signed_credit.clone())))
-            .collect();
+    #[test]
+    fn pending_transfers_are_reserved_from_balance() -> Result<()> {
+        let (mut actor, _sk_set) = get_actor_and_replicas_sk_set(15)?;
+        actor.set_max_pending_debits(2)?;
+        let transfer = get_debit(&actor)?;
+        actor.apply(ActorEvent::TransferInitiated(transfer))?;
+
+        match actor.transfer(Token::from_nano(10), get_random_pk(), "asdf".to_string()) {
+            Err(Error::InsufficientBalance) => Ok(()),
+            _ => panic!("Should not be able to spend funds reserved by a pending transfer."),
+        }
+    }
+
+    #[test]
+    fn can_retry_pending_transfer() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(22)?;
+        let transfer = get_debit(&actor)?;
+        actor.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+        let validations = get_transfer_validation_vec(transfer.clone(), &sk_set)?;
+        let received = actor
+            .receive(validations[0].clone())?
+            .ok_or(Error::ReceiveValidationFailed)?;
+        actor.apply(ActorEvent::TransferValidationReceived(received))?;
+
+        let retried = actor
+            .retry(transfer.id())?
+            .ok_or(Error::PendingTransferNotFound)?;
+        assert_eq!(retried, transfer);
+        actor.apply(ActorEvent::TransferInitiated(retried))?;
+
+        // validations received before the retry are kept
+        match actor.receive(validations[0].clone()) {
+            Err(Error::ValidatedAlready) => (),
+            _ => panic!("Validation should have been kept."),
+        }
+        let received = actor
+            .receive(validations[1].clone())?
+            .ok_or(Error::ReceiveValidationFailed)?;
+        assert!(received.proof.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn can_abandon_unvalidated_transfer() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(22)?;
+        let transfer = get_debit(&actor)?;
+        actor.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+
+        let abandoned = actor
+            .abandon(transfer.id())?
+            .ok_or(Error::PendingTransferNotFound)?;
+        actor.apply(ActorEvent::TransferAbandoned(abandoned))?;
+        assert!(actor.pending_debits().is_empty());
+
+        // the debit counter is reused
+        let next = get_debit(&actor)?;
+        assert_eq!(next.id(), transfer.id());
+        actor.apply(ActorEvent::TransferInitiated(next.clone()))?;
+
+        // once validated, it can no longer be abandoned
+        let validations = get_transfer_validation_vec(next.clone(), &sk_set)?;
+        let received = actor
+            .receive(validations[0].clone())?
+            .ok_or(Error::ReceiveValidationFailed)?;
+        actor.apply(ActorEvent::TransferValidationReceived(received))?;
+        match actor.abandon(next.id()) {
+            Err(Error::CannotAbandonValidatedTransfer) => Ok(()),
+            _ => panic!("Should not be able to abandon a validated transfer."),
+        }
+    }
 
-        let mut validated_transfers = vec![];
+    #[test]
+    fn proofs_are_verified_against_replicas_chain() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(22)?;
+        actor.set_max_pending_debits(2)?;
+        let first = get_debit(&actor)?;
+        actor.apply(ActorEvent::TransferInitiated(first.clone()))?;
+
+        // the section changes its key
+        let new_sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let update = actor
+            .update_replicas(
+                get_section_elders(&new_sk_set),
+                vec![get_key_link(&sk_set, &new_sk_set)?],
+            )?
+            .ok_or(Error::InvalidOperation)?;
+        actor.apply(ActorEvent::ReplicasUpdated(update))?;
+        assert_eq!(actor.replicas_chain().len(), 2);
+
+        // a proof by the previous key set is still valid
+        let registered = get_transfer_registration_sent(first, &sk_set)?;
+        let registered = actor
+            .register(registered.transfer_proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        actor.apply(ActorEvent::TransferRegistrationSent(registered))?;
+
+        // validations by the new key set are accumulated
+        let second = get_debit(&actor)?;
+        actor.apply(ActorEvent::TransferInitiated(second.clone()))?;
+        let validations = get_transfer_validation_vec(second.clone(), &new_sk_set)?;
+        for validation in validations.into_iter().take(2) {
+            let received = actor
+                .receive(validation)?
+                .ok_or(Error::ReceiveValidationFailed)?;
+            actor.apply(ActorEvent::TransferValidationReceived(received))?;
+        }
 
-        for i in 0..7 {
-            let debit_sig_share = &debit_sig_shares[&i];
-            let credit_sig_share = &credit_sig_shares[&i];
-            assert!(pk_set
-                .public_key_share(i)
-                .verify(debit_sig_share, serialized_signed_debit.clone()));
-            assert!(pk_set
-                .public_key_share(i)
-                .verify(credit_sig_share, serialized_signed_credit.clone()));
-            validated_transfers.push(TransferValidated {
-                signed_debit: signed_debit.clone(),
-                signed_credit: signed_credit.clone(),
-                replica_debit_sig: SignatureShare {
-                    index: i,
-                    share: debit_sig_share.clone(),
-                },
-                replica_credit_sig: SignatureShare {
-                    index: i,
-                    share: credit_sig_share.clone(),
-                },
-                replicas: pk_set.clone(),
-            })
+        // while those by the previous key set are not
+        let validations = get_transfer_validation_vec(second.clone(), &sk_set)?;
+        match actor.receive(validations[0].clone()) {
+            Err(Error::SectionKeyOutdated) => (),
+            _ => panic!("Should not accept validations from a previous key set."),
         }
 
-        Ok(validated_transfers)
+        // nor are those by an unknown key set
+        let unknown_sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let validations = get_transfer_validation_vec(second, &unknown_sk_set)?;
+        match actor.receive(validations[0].clone()) {
+            Err(Error::SectionKeyNeverExisted) => Ok(()),
+            _ => panic!("Should not accept validations from an unknown key set."),
+        }
+    }
+
+    #[test]
+    fn rejects_unsigned_replicas_update() -> Result<()> {
+        let (actor, _sk_set) = get_actor_and_replicas_sk_set(10)?;
+        let new_sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let other_sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        match actor.update_replicas(
+            get_section_elders(&new_sk_set),
+            vec![get_key_link(&other_sk_set, &new_sk_set)?],
+        ) {
+            Err(Error::InvalidSignature) => Ok(()),
+            _ => panic!("Should not accept a key not signed by current replicas."),
+        }
+    }
+
+    fn get_section_elders(sk_set: &SecretKeySet) -> SectionElders {
+        SectionElders {
+            prefix: Prefix::default(),
+            names: Default::default(),
+            key_set: sk_set.public_keys(),
+        }
+    }
+
+    fn get_key_link(previous: &SecretKeySet, next: &SecretKeySet) -> Result<SectionKeyLink> {
+        let key_bytes = try_serialize(next.public_keys().public_key())?;
+        let sig_shares: BTreeMap<_, _> = (0..=previous.threshold())
+            .map(|i| (i, previous.secret_key_share(i).sign(&key_bytes)))
+            .collect();
+        let signature = previous
+            .public_keys()
+            .combine_signatures(&sig_shares)
+            .map_err(|_| Error::CannotAggregate)?;
+        Ok(SectionKeyLink {
+            key_set: next.public_keys(),
+            signature: Signature::Bls(signature),
+        })
+    }
+
+    fn try_serialize<T: Serialize>(value: T) -> Result<Vec<u8>> {
+        match bincode::serialize(&value) {
+            Ok(res) => Ok(res),
+            _ => Err(Error::Serialisation("Serialisation error".to_string())),
+        }
     }
 
     fn get_transfer_registration_sent(
@@ -865,38 +2197,6 @@ mod test {
         })
     }
 
-    fn get_actor_and_replicas_sk_set(amount: u64) -> Result<(Actor<Keypair>, SecretKeySet)> {
-        let mut rng = rand::thread_rng();
-        let keypair = Keypair::new_ed25519(&mut rng);
-        let client_pubkey = keypair.public_key();
-        let bls_secret_key = SecretKeySet::random(1, &mut rng);
-        let replicas_id = bls_secret_key.public_keys();
-        let balance = Token::from_nano(amount);
-        let sender = Dot::new(get_random_pk(), 0);
-        let credit = get_credit(sender, client_pubkey, balance)?;
-        let mut wallet = Wallet::new(OwnerType::Single(credit.recipient()));
-        wallet.apply_credit(credit)?;
-
-        let replicas = SectionElders {
-            prefix: Prefix::default(),
-            names: Default::default(),
-            key_set: replicas_id,
-        };
-
-        let actor = Actor::from_snapshot(wallet, keypair, replicas);
-        Ok((actor, bls_secret_key))
-    }
-
-    fn get_credit(from: Dot<PublicKey>, recipient: PublicKey, amount: Token) -> Result<Credit> {
-        let debit = Debit { id: from, amount };
-        Ok(Credit {
-            id: debit.credit_id()?,
-            recipient,
-            amount,
-            msg: "asdf".to_string(),
-        })
-    }
-
     #[allow(unused)]
     fn get_random_dot() -> Dot<PublicKey> {
         Dot::new(get_random_pk(), 0)
@@ -0,0 +1,106 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    history::HistoryIndex,
+    replica_snapshot::SnapshotOwner,
+    versioned::{deserialise, split_version, to_versioned_bytes},
+    BatchTransferAgreementProof, BatchTransferInitiated, BatchTransferValidated, Error,
+    LockedCredit, Result, TransferInitiated,
+};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    ActorHistory, CreditId, DebitId, OwnerType, PublicKey, SectionElders, Token,
+    TransferAgreementProof, TransferValidated,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use threshold_crypto::PublicKeySet;
+
+/// The current version of the ActorSnapshot format.
+pub const ACTOR_SNAPSHOT_VERSION: u16 = 1;
+
+/// The full state of an Actor, at some point in its history,
+/// including the transfers in flight.
+/// The signing of the Actor is not part of it, nor is its configuration.
+///
+/// A snapshot is stored as its [bytes](ActorSnapshot::to_bytes),
+/// which are prefixed by the version of their format.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActorSnapshot {
+    /// The owner of the Wallet.
+    pub(crate) id: SnapshotOwner,
+    /// The balance of the Wallet.
+    pub(crate) balance: Token,
+    /// The number of debits of the Wallet.
+    pub(crate) debit_version: u64,
+    /// The credits received by the Wallet.
+    pub(crate) credit_ids: HashSet<CreditId>,
+    /// The conditional credits of the Wallet, not yet released or refunded.
+    pub(crate) locked: BTreeMap<CreditId, LockedCredit>,
+    /// The counter of the next debit to initiate.
+    pub(crate) next_expected_debit: u64,
+    /// Transfers initiated but not yet registered, by debit counter.
+    pub(crate) pending_transfers: BTreeMap<u64, TransferInitiated>,
+    /// The validations received of initiated transfers.
+    pub(crate) accumulating_validations:
+        HashMap<DebitId, HashMap<(threshold_crypto::PublicKey, usize), TransferValidated>>,
+    /// The proofs of agreed transfers not yet registered, by debit counter.
+    pub(crate) agreed_transfers: BTreeMap<u64, TransferAgreementProof>,
+    /// Batch transfers initiated but not yet registered, by debit counter.
+    pub(crate) pending_batches: BTreeMap<u64, BatchTransferInitiated>,
+    /// The validations received of initiated batch transfers.
+    pub(crate) accumulating_batch_validations:
+        HashMap<DebitId, HashMap<(threshold_crypto::PublicKey, usize), BatchTransferValidated>>,
+    /// The proofs of agreed batch transfers not yet registered, by debit counter.
+    pub(crate) agreed_batches: BTreeMap<u64, BatchTransferAgreementProof>,
+    /// The Replicas of the Actor.
+    pub(crate) replicas: SectionElders,
+    /// All the PK Sets the Replicas have had, oldest first.
+    pub(crate) replicas_chain: Vec<PublicKeySet>,
+    /// The applied credits and debits.
+    pub(crate) history: ActorHistory,
+    /// The applied credits and debits, in order, indexed for queries.
+    pub(crate) history_index: HistoryIndex,
+    /// The registered batch debits.
+    pub(crate) batch_history: Vec<BatchTransferAgreementProof>,
+}
+
+impl ActorSnapshot {
+    /// The snapshot as bytes, prefixed by the format version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        to_versioned_bytes(ACTOR_SNAPSHOT_VERSION, self)
+    }
+
+    /// The snapshot from its bytes, decoded as per the format version they are prefixed by.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        // A change of the format adds the decoder of the previous version here.
+        match split_version(bytes)? {
+            (ACTOR_SNAPSHOT_VERSION, snapshot) => deserialise(snapshot),
+            (version, _) => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+
+    /// The key of the Wallet.
+    pub fn wallet_key(&self) -> PublicKey {
+        OwnerType::from(self.id.clone()).public_key()
+    }
+}
+
+impl fmt::Debug for ActorSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ActorSnapshot {{ wallet: {:?}, balance: {:?}, debit_version: {:?}, next_expected_debit: {:?} }}",
+            self.wallet_key(),
+            self.balance,
+            self.debit_version,
+            self.next_expected_debit
+        )
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    actor::Actor, actor_snapshot::ActorSnapshot, event_log::write_atomic,
+    subscribers::Notification, ActorEvent, BatchTransferAgreementProof, BatchTransferInitiated,
+    Error, FeePolicy, Result, TransferInitiated,
+};
+use sn_data_types::{Signing, TransferAgreementProof};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+
+/// A transfer in flight, and what remains to be done to complete it.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum InFlight {
+    /// Not yet agreed by the Replicas: the transfer is to be sent to them again,
+    /// as when it is [retried](crate::TransferActor::retry).
+    Validation(TransferInitiated),
+    /// Agreed by the Replicas: the proof is to be registered.
+    Registration(TransferAgreementProof),
+    /// Not yet agreed batch transfer, to be sent to the Replicas again.
+    BatchValidation(BatchTransferInitiated),
+    /// Agreed batch transfer, the proof of which is to be registered.
+    BatchRegistration(BatchTransferAgreementProof),
+}
+
+/// An on-disk store of the state of a client Actor.
+///
+/// The full state of the Actor, including the transfers in flight, is written to
+/// the file after every applied event, replacing the previous state atomically,
+/// so that a crash at any point leaves either the state before or after the event.
+/// The Actor is then restored with [load](ActorStore::load), along with its transfers
+/// in flight, which are to be resumed in order of their debit counters.
+///
+/// The configuration of the Actor, i.e. the fee policy and the max number of
+/// pending debits, is not stored, and is to be set again on a loaded store.
+///
+/// The subscribers of the Actor are notified of an event once its state is stored.
+pub struct ActorStore<S: Signing> {
+    path: PathBuf,
+    stored: StoredActor<S>,
+}
+
+impl<S: Signing + Clone> ActorStore<S> {
+    /// Creates the store of the Actor at the path, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P, actor: Actor<S>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let stored = StoredActor::new(actor, |_, snapshot| write_atomic(&path, snapshot))?;
+        Ok(Self { path, stored })
+    }
+
+    /// Loads the store at the path, restoring the Actor with its signing,
+    /// and returns it along with the transfers which were in flight.
+    ///
+    /// The loaded Actor has the default configuration, i.e. no fees and
+    /// a max of 1 pending debit, until it is set again.
+    pub fn load<P: AsRef<Path>>(path: P, signing: S) -> Result<(Self, Vec<InFlight>)> {
+        let path = path.as_ref().to_path_buf();
+        let snapshot = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        let (stored, in_flight) = StoredActor::restore(signing, snapshot)?;
+        Ok((Self { path, stored }, in_flight))
+    }
+
+    /// The Actor, for its queries and cmds.
+    pub fn actor(&self) -> &Actor<S> {
+        self.stored.actor()
+    }
+
+    /// The path of the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sets the max number of initiated transfers that can await registration at the same time.
+    pub fn set_max_pending_debits(&mut self, max: u64) -> Result<()> {
+        self.stored.set_max_pending_debits(max)
+    }
+
+    /// Sets the fee policy of the Replicas of the Actor.
+    pub fn set_fee_policy(&mut self, fee_policy: FeePolicy) {
+        self.stored.set_fee_policy(fee_policy)
+    }
+
+    /// Subscribes to the notifications of the Actor.
+    pub fn subscribe(&mut self) -> Receiver<Notification> {
+        self.stored.subscribe()
+    }
+
+    /// Applies the event to the Actor, and stores the resulting state.
+    /// If the state cannot be stored, the event is not applied,
+    /// and the subscribers are not notified of it.
+    pub fn apply(&mut self, event: ActorEvent) -> Result<()> {
+        let path = &self.path;
+        self.stored
+            .apply(event, |_, snapshot| write_atomic(path, snapshot))
+    }
+}
+
+/// An Actor, along with the bytes of the snapshot of its state as last written,
+/// shared by the stores of an Actor ([ActorStore], and the
+/// [WalletFile](crate::WalletFile)), which differ in how the state is written.
+///
+/// An event is applied in place, and the subscribers are notified of it once the
+/// new state is written. The Actor is otherwise restored to the state last written.
+pub(crate) struct StoredActor<S: Signing> {
+    actor: Actor<S>,
+    snapshot: Vec<u8>,
+}
+
+impl<S: Signing> StoredActor<S> {
+    pub(crate) fn actor(&self) -> &Actor<S> {
+        &self.actor
+    }
+}
+
+impl<S: Signing + Clone> StoredActor<S> {
+    /// Writes the state of the Actor, with the write of the store,
+    /// which is given the Actor, for its signing, and the bytes of its snapshot.
+    pub(crate) fn new<W>(actor: Actor<S>, write: W) -> Result<Self>
+    where
+        W: FnOnce(&Actor<S>, &[u8]) -> Result<()>,
+    {
+        let snapshot = actor.snapshot().to_bytes()?;
+        write(&actor, &snapshot)?;
+        Ok(Self { actor, snapshot })
+    }
+
+    /// Restores the Actor from the bytes of its snapshot, as read by the store,
+    /// and returns it along with the transfers which were in flight.
+    pub(crate) fn restore(signing: S, snapshot: Vec<u8>) -> Result<(Self, Vec<InFlight>)> {
+        let actor = Actor::from_actor_snapshot(signing, ActorSnapshot::from_bytes(&snapshot)?)?;
+        let in_flight = actor.in_flight();
+        Ok((Self { actor, snapshot }, in_flight))
+    }
+
+    pub(crate) fn set_max_pending_debits(&mut self, max: u64) -> Result<()> {
+        self.actor.set_max_pending_debits(max)
+    }
+
+    pub(crate) fn set_fee_policy(&mut self, fee_policy: FeePolicy) {
+        self.actor.set_fee_policy(fee_policy)
+    }
+
+    pub(crate) fn subscribe(&mut self) -> Receiver<Notification> {
+        self.actor.subscribe()
+    }
+
+    /// Applies the event, and writes the resulting state with the write of the store.
+    pub(crate) fn apply<W>(&mut self, event: ActorEvent, write: W) -> Result<()>
+    where
+        W: FnOnce(&Actor<S>, &[u8]) -> Result<()>,
+    {
+        match self.apply_and_write(event, write) {
+            Ok(notifications) => {
+                self.actor.notify_all(notifications);
+                Ok(())
+            }
+            Err(error) => {
+                self.actor
+                    .restore(ActorSnapshot::from_bytes(&self.snapshot)?)?;
+                Err(error)
+            }
+        }
+    }
+
+    fn apply_and_write<W>(&mut self, event: ActorEvent, write: W) -> Result<Vec<Notification>>
+    where
+        W: FnOnce(&Actor<S>, &[u8]) -> Result<()>,
+    {
+        let notifications = self.actor.apply_held(event)?;
+        let snapshot = self.actor.snapshot().to_bytes()?;
+        write(&self.actor, &snapshot)?;
+        self.snapshot = snapshot;
+        Ok(notifications)
+    }
+}
+
+impl<S: Signing> fmt::Debug for ActorStore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ActorStore {{ path: {:?}, actor: {:?} }}",
+            self.path,
+            self.stored.actor().id()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActorStore, InFlight};
+    use crate::{
+        subscribers::Notification, test_utils::*, ActorEvent, Error, Result, ACTOR_SNAPSHOT_VERSION,
+    };
+    use sn_data_types::{Keypair, Token};
+
+    #[test]
+    fn store_rolls_back_events_not_stored() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(40)?;
+        actor.set_max_pending_debits(2)?;
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("actor");
+        let mut store = ActorStore::create(&path, actor)?;
+        let notifications = store.subscribe();
+
+        let transfer = get_debit(store.actor())?;
+        let debit_id = transfer.id();
+        store.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+        let mut proof = None;
+        for validation in get_transfer_validation_vec(transfer, &sk_set)?
+            .into_iter()
+            .take(2)
+        {
+            let received = store
+                .actor()
+                .receive(validation)?
+                .ok_or(Error::ReceiveValidationFailed)?;
+            proof = received.proof.clone();
+            store.apply(ActorEvent::TransferValidationReceived(received))?;
+        }
+        let registered = store
+            .actor()
+            .register(proof.ok_or(Error::SenderValidationFailed)?)?
+            .ok_or(Error::RegisterProofFailed)?;
+
+        // the state can't be written while a directory is in the way of the temporary file
+        let blocked = dir.path().join("actor.tmp");
+        std::fs::create_dir(&blocked).map_err(|e| Error::Io(e.to_string()))?;
+        let before = store.actor().snapshot();
+        let event = ActorEvent::TransferRegistrationSent(registered);
+        assert!(store.apply(event.clone()).is_err());
+        assert_eq!(store.actor().snapshot(), before);
+        assert_eq!(store.actor().max_pending_debits(), 2);
+        assert!(notifications.try_iter().next().is_none());
+
+        // once written, the subscribers are notified
+        std::fs::remove_dir(&blocked).map_err(|e| Error::Io(e.to_string()))?;
+        store.apply(event)?;
+        let wallet = store.actor().id();
+        assert_eq!(
+            notifications.try_iter().collect::<Vec<_>>(),
+            vec![
+                Notification::DebitCompleted {
+                    wallet,
+                    debit_id,
+                    amount: Token::from_nano(10),
+                },
+                Notification::BalanceChanged {
+                    wallet,
+                    balance: Token::from_nano(30),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn store_resumes_in_flight_transfers() -> Result<()> {
+        let (mut actor, sk_set) = get_actor_and_replicas_sk_set(40)?;
+        actor.set_max_pending_debits(2)?;
+        let keypair = actor.signing().clone();
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("actor");
+        let mut store = ActorStore::create(&path, actor)?;
+
+        let first = get_debit(store.actor())?;
+        store.apply(ActorEvent::TransferInitiated(first.clone()))?;
+        let second = get_debit(store.actor())?;
+        store.apply(ActorEvent::TransferInitiated(second.clone()))?;
+        let mut proof = None;
+        for validation in get_transfer_validation_vec(first, &sk_set)?
+            .into_iter()
+            .take(2)
+        {
+            let received = store
+                .actor()
+                .receive(validation)?
+                .ok_or(Error::ReceiveValidationFailed)?;
+            proof = received.proof.clone();
+            store.apply(ActorEvent::TransferValidationReceived(received))?;
+        }
+        let proof = proof.ok_or(Error::SenderValidationFailed)?;
+        let balance = store.actor().balance();
+        drop(store);
+
+        // after a restart, the agreed transfer is registered, and the other validated again
+        let (mut store, in_flight) = ActorStore::load(&path, keypair.clone())?;
+        assert_eq!(
+            in_flight,
+            vec![
+                InFlight::Registration(proof.clone()),
+                InFlight::Validation(second.clone())
+            ]
+        );
+        assert_eq!(store.actor().balance(), balance);
+        let registered = store
+            .actor()
+            .register(proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        store.apply(ActorEvent::TransferRegistrationSent(registered))?;
+
+        let (store, in_flight) = ActorStore::load(&path, keypair.clone())?;
+        assert_eq!(in_flight, vec![InFlight::Validation(second)]);
+        assert_eq!(store.actor().history().debits.len(), 1);
+        assert_eq!(store.actor().balance(), Token::from_nano(30));
+
+        let other = Keypair::new_ed25519(&mut rand::thread_rng());
+        assert_eq!(
+            ActorStore::load(&path, other).map(|_| ()),
+            Err(Error::InvalidOwner)
+        );
+
+        // a store of an unknown format version is not decoded
+        let mut bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        bytes[..2].copy_from_slice(&(ACTOR_SNAPSHOT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).map_err(|e| Error::Io(e.to_string()))?;
+        assert_eq!(
+            ActorStore::load(&path, keypair).map(|_| ()),
+            Err(Error::UnsupportedVersion(ACTOR_SNAPSHOT_VERSION + 1))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Batch transfers, where a single debit pays many recipients.
+//! The debit amount is the sum of the credit amounts, and the id of every
+//! credit is derived from the debit id and the position of the credit
+//! in the batch. A batch is validated and registered as a whole, at the
+//! Replicas of the sender, and each credit is then propagated by itself,
+//! with a `CreditAgreementProof` of its own.
+
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    CreditAgreementProof, CreditId, DebitId, PublicKey, Signature, SignatureShare, SignedCredit,
+    SignedDebit, Token,
+};
+use std::fmt::{self, Debug, Formatter};
+use threshold_crypto::PublicKeySet;
+use tiny_keccak::{Hasher, Sha3};
+
+/// The id of the credit at the index of a batch.
+pub fn batch_credit_id(debit_id: &DebitId, index: usize) -> Result<CreditId> {
+    let id_bytes = bincode::serialize(&(debit_id, index as u64))
+        .map_err(|_| Error::Serialisation("Could not serialise debit id".into()))?;
+    let mut hasher = Sha3::v256();
+    let mut output = [0; 32];
+    hasher.update(&id_bytes);
+    hasher.finalize(&mut output);
+    Ok(output)
+}
+
+/// The sum of the amounts, if it does not overflow.
+pub(crate) fn sum_amounts<I: IntoIterator<Item = Token>>(amounts: I) -> Option<Token> {
+    amounts
+        .into_iter()
+        .try_fold(Token::zero(), |total, amount| total.checked_add(amount))
+}
+
+/// A debit and the credits it pays, signed by the Actor.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct SignedBatchTransfer {
+    /// The debit, of the sum of the credit amounts.
+    pub debit: SignedDebit,
+    /// The credits, in order.
+    pub credits: Vec<SignedCredit>,
+}
+
+/// This event is raised by the Actor after having
+/// successfully created a batch transfer cmd to send
+/// to the Replicas for validation.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct BatchTransferInitiated {
+    /// The debit signed by the initiating Actor.
+    pub signed_debit: SignedDebit,
+    /// The credits signed by the initiating Actor.
+    pub signed_credits: Vec<SignedCredit>,
+}
+
+impl BatchTransferInitiated {
+    /// Get the debit id
+    pub fn id(&self) -> DebitId {
+        self.signed_debit.id()
+    }
+}
+
+/// The Replica event raised when
+/// a batch transfer has been validated.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BatchTransferValidated {
+    /// The debit initiated by the Actor.
+    pub signed_debit: SignedDebit,
+    /// The credits initiated by the Actor.
+    pub signed_credits: Vec<SignedCredit>,
+    /// Replica signature over the signed debit.
+    pub replica_debit_sig: SignatureShare,
+    /// Replica signatures over the signed credits, in order.
+    pub replica_credit_sigs: Vec<SignatureShare>,
+    /// The PK Set of the Replicas
+    pub replicas: PublicKeySet,
+}
+
+impl BatchTransferValidated {
+    /// Get the debit id
+    pub fn id(&self) -> DebitId {
+        self.signed_debit.id()
+    }
+
+    /// Get the sender
+    pub fn sender(&self) -> PublicKey {
+        self.signed_debit.sender()
+    }
+}
+
+impl Debug for BatchTransferValidated {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BatchTransferValidated {{ signed_debit: {:?}, signed_credits: {:?}, replica_debit_sig: {:?}, replica_credit_sigs: {:?}, replicas: PkSet {{ public_key: {:?} }} }}",
+            self.signed_debit,
+            self.signed_credits,
+            self.replica_debit_sig,
+            self.replica_credit_sigs,
+            self.replicas.public_key()
+        )
+    }
+}
+
+/// Raised when a Replica responds with
+/// a successful validation of a batch transfer.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct BatchTransferValidationReceived {
+    /// The event raised by a Replica.
+    pub(crate) validation: BatchTransferValidated,
+    /// Added when quorum of validations
+    /// have been received from Replicas.
+    pub proof: Option<BatchTransferAgreementProof>,
+}
+
+/// The aggregated Replica signatures of a batch transfer.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BatchTransferAgreementProof {
+    /// The debit generated by sender Actor.
+    pub signed_debit: SignedDebit,
+    /// The credits generated by sender Actor.
+    pub signed_credits: Vec<SignedCredit>,
+    /// Quorum of Replica sigs over the debit.
+    pub debit_sig: Signature,
+    /// Quorum of Replica sigs over each of the credits, in order.
+    pub credit_sigs: Vec<Signature>,
+    /// PublicKeySet of the replica when it validated the batch.
+    pub debiting_replicas_keys: PublicKeySet,
+}
+
+impl BatchTransferAgreementProof {
+    /// Get the debit id
+    pub fn id(&self) -> DebitId {
+        self.signed_debit.id()
+    }
+
+    /// Get the sender
+    pub fn sender(&self) -> PublicKey {
+        self.signed_debit.sender()
+    }
+
+    /// Get the amount of the debit
+    pub fn amount(&self) -> Token {
+        self.signed_debit.amount()
+    }
+
+    /// The proofs of the individual credits, for propagation to their recipients.
+    pub fn credit_proofs(&self) -> Vec<CreditAgreementProof> {
+        self.signed_credits
+            .iter()
+            .zip(self.credit_sigs.iter())
+            .map(|(signed_credit, sig)| CreditAgreementProof {
+                signed_credit: signed_credit.clone(),
+                debiting_replicas_sig: sig.clone(),
+                debiting_replicas_keys: self.debiting_replicas_keys.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Debug for BatchTransferAgreementProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BatchTransferAgreementProof {{ signed_debit: {:?}, signed_credits: {:?}, debiting_replicas_keys: PkSet {{ public_key: {:?} }} }}",
+            self.signed_debit,
+            self.signed_credits,
+            self.debiting_replicas_keys.public_key()
+        )
+    }
+}
+
+/// Raised when the Actor has accumulated a
+/// quorum of validations of a batch, and produced
+/// a RegisterBatchTransfer cmd for sending to Replicas.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct BatchTransferRegistrationSent {
+    pub(crate) transfer_proof: BatchTransferAgreementProof,
+}
+
+/// The Replica event raised when
+/// a batch transfer has been registered.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct BatchTransferRegistered {
+    /// The proof of the batch.
+    pub transfer_proof: BatchTransferAgreementProof,
+}
+
+impl BatchTransferRegistered {
+    /// Get the sender
+    pub fn sender(&self) -> PublicKey {
+        self.transfer_proof.sender()
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Evidence of an Actor equivocating, i.e. signing two
+//! different transfers with the same debit id, in an attempt to
+//! spend the same balance twice. The proof holds both signed
+//! transfers, the debit and its credits, and can be verified by anyone
+//! knowing nothing but the proof itself, since the id of a debit
+//! includes its sender, and the id of a credit is derived from its debit.
+
+use crate::{batch_credit_id, Error, Result, SignedBatchTransfer};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{DebitId, PublicKey, Signature};
+
+/// Two transfers with the same debit id, in conflict, both signed by the sender.
+/// A single transfer is held as a batch of its one credit.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct EquivocationProof {
+    /// The transfer first seen.
+    pub first: SignedBatchTransfer,
+    /// The conflicting transfer.
+    pub second: SignedBatchTransfer,
+}
+
+impl EquivocationProof {
+    /// Get the debit id
+    pub fn id(&self) -> DebitId {
+        self.first.debit.id()
+    }
+
+    /// Get the sender
+    pub fn sender(&self) -> PublicKey {
+        self.first.debit.sender()
+    }
+
+    /// Verifies that the transfers are in conflict,
+    /// and that both were signed by the sender.
+    pub fn verify(&self) -> Result<()> {
+        if !conflicts(&self.first, &self.second)? {
+            return Err(Error::InvalidOperation);
+        }
+        verify_transfer_signatures(&self.first)?;
+        verify_transfer_signatures(&self.second)
+    }
+}
+
+/// Whether the transfers have the same debit id, but a different debit,
+/// or a different credit at the same position, which an honest sender never signs.
+/// The credits of a batch are signed one by one, so a batch with only
+/// more or fewer credits than another cannot be told from it by anyone else,
+/// and is not in conflict with it.
+pub(crate) fn conflicts(first: &SignedBatchTransfer, second: &SignedBatchTransfer) -> Result<bool> {
+    if first.debit.id() != second.debit.id() {
+        return Ok(false);
+    }
+    let kinds_differ = is_single(first)? != is_single(second)?;
+    Ok(first.debit.debit != second.debit.debit
+        || kinds_differ
+        || first
+            .credits
+            .iter()
+            .zip(&second.credits)
+            .any(|(a, b)| a.credit != b.credit))
+}
+
+/// Whether the credits are that of a single transfer, rather than of a batch.
+/// Credits not belonging to the debit are an error.
+fn is_single(transfer: &SignedBatchTransfer) -> Result<bool> {
+    let debit_id = transfer.debit.id();
+    match transfer.credits.as_slice() {
+        [] => Err(Error::InvalidOperation),
+        [signed_credit] if signed_credit.id() == &transfer.debit.credit_id()? => Ok(true),
+        credits => {
+            for (index, signed_credit) in credits.iter().enumerate() {
+                if signed_credit.id() != &batch_credit_id(&debit_id, index)? {
+                    return Err(Error::CreditDebitIdMismatch);
+                }
+            }
+            Ok(false)
+        }
+    }
+}
+
+fn verify_transfer_signatures(transfer: &SignedBatchTransfer) -> Result<()> {
+    let sender = transfer.debit.sender();
+    verify_signature(
+        &sender,
+        &transfer.debit.actor_signature,
+        &transfer.debit.debit,
+    )?;
+    for signed_credit in &transfer.credits {
+        verify_signature(
+            &sender,
+            &signed_credit.actor_signature,
+            &signed_credit.credit,
+        )?;
+    }
+    Ok(())
+}
+
+fn verify_signature<T: Serialize>(
+    sender: &PublicKey,
+    signature: &Signature,
+    item: &T,
+) -> Result<()> {
+    let bytes = bincode::serialize(item)
+        .map_err(|_| Error::Serialisation("Could not serialise item".into()))?;
+    sender
+        .verify(signature, bytes)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+#[cfg(test)]
+mod test {
+    use super::EquivocationProof;
+    use crate::{batch_credit_id, Error, Result, SignedBatchTransfer};
+    use crdts::Dot;
+    use sn_data_types::{
+        Credit, CreditId, Debit, Keypair, PublicKey, SignedCredit, SignedDebit, Signing, Token,
+    };
+
+    #[test]
+    fn verifies_conflicting_transfers() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let keypair = Keypair::new_ed25519(&mut rng);
+        let alice: PublicKey = Keypair::new_ed25519(&mut rng).public_key();
+        let bob: PublicKey = Keypair::new_ed25519(&mut rng).public_key();
+        let debit = |amount| -> Result<SignedDebit> {
+            let debit = Debit {
+                id: Dot::new(keypair.public_key(), 0),
+                amount: Token::from_nano(amount),
+            };
+            let actor_signature = Signing::sign(&keypair, &debit)?;
+            Ok(SignedDebit {
+                debit,
+                actor_signature,
+            })
+        };
+        let credit = |id: CreditId, recipient, amount| -> Result<SignedCredit> {
+            let credit = Credit {
+                id,
+                recipient,
+                amount: Token::from_nano(amount),
+                msg: "asdf".to_string(),
+            };
+            let actor_signature = Signing::sign(&keypair, &credit)?;
+            Ok(SignedCredit {
+                credit,
+                actor_signature,
+            })
+        };
+        let single = |amount, recipient| -> Result<SignedBatchTransfer> {
+            let debit = debit(amount)?;
+            let credits = vec![credit(debit.credit_id()?, recipient, amount)?];
+            Ok(SignedBatchTransfer { debit, credits })
+        };
+        let batch = |recipients: Vec<PublicKey>| -> Result<SignedBatchTransfer> {
+            let debit = debit(recipients.len() as u64)?;
+            let credits = recipients
+                .into_iter()
+                .enumerate()
+                .map(|(i, recipient)| credit(batch_credit_id(&debit.id(), i)?, recipient, 1))
+                .collect::<Result<_>>()?;
+            Ok(SignedBatchTransfer { debit, credits })
+        };
+
+        // the same amount, to another recipient
+        let proof = EquivocationProof {
+            first: single(10, alice)?,
+            second: single(10, bob)?,
+        };
+        proof.verify()?;
+        EquivocationProof {
+            first: single(10, alice)?,
+            second: single(20, alice)?,
+        }
+        .verify()?;
+        EquivocationProof {
+            first: single(1, alice)?,
+            second: batch(vec![alice])?,
+        }
+        .verify()?;
+        EquivocationProof {
+            first: batch(vec![alice, bob])?,
+            second: batch(vec![bob, alice])?,
+        }
+        .verify()?;
+
+        let same = EquivocationProof {
+            first: single(10, alice)?,
+            second: single(10, alice)?,
+        };
+        assert_eq!(same.verify(), Err(Error::InvalidOperation));
+        // a part of a batch cannot be told from the batch
+        let mut part = batch(vec![alice, bob])?;
+        let _ = part.credits.pop();
+        let part = EquivocationProof {
+            first: batch(vec![alice, bob])?,
+            second: part,
+        };
+        assert_eq!(part.verify(), Err(Error::InvalidOperation));
+
+        let mut forged = proof.clone();
+        let other: PublicKey = Keypair::new_ed25519(&mut rng).public_key();
+        forged.second.debit.debit.id = Dot::new(other, 0);
+        assert_eq!(forged.verify(), Err(Error::InvalidOperation));
+        let mut forged = proof.clone();
+        forged.second.credits[0].credit.id = [0; 32];
+        assert_eq!(forged.verify(), Err(Error::CreditDebitIdMismatch));
+        let mut forged = proof;
+        forged.second.credits[0].credit.amount = Token::from_nano(30);
+        assert_eq!(forged.verify(), Err(Error::InvalidSignature));
+        Ok(())
+    }
+}
@@ -28,6 +28,9 @@ pub enum Error {
     /// The provided PublicKey does not correlate with any key in the section chain history.
     #[error("PublicKey provided by the transfer was never a part of the section chain.")]
     SectionKeyNeverExisted,
+    /// The key set was one of our Replicas, but they have since changed their key.
+    #[error("PublicKey provided by the validation is no longer that of the Replicas.")]
+    SectionKeyOutdated,
     /// The proposed debit has already been seen, or is not the next expected debit
     #[error("Debit already proposed or out of order")]
     DebitProposed,
@@ -66,6 +69,9 @@ pub enum Error {
     /// The validation has already been received
     #[error("Validation already received")]
     ValidatedAlready,
+    /// A transfer with received validations cannot be abandoned
+    #[error("Transfer has been validated by Replicas and cannot be abandoned")]
+    CannotAbandonValidatedTransfer,
     /// Debit is not from this wallet
     #[error("Debit is not from wallet {0}. Debit: {1:?}")]
     DebitDoesNotBelong(PublicKey, Debit),
@@ -129,6 +135,9 @@ pub enum Error {
     /// Owner is not valid
     #[error("Owner is not valid")]
     InvalidOwner,
+    /// The debit does not cover the fee of the transfer.
+    #[error("Insufficient fee for this transfer")]
+    InsufficientFee,
     /// Insufficient coins.
     #[error("Insufficient balance to complete this operation")]
     InsufficientBalance,
@@ -147,4 +156,28 @@ pub enum Error {
     /// Serialisation
     #[error("Serialisation error. {0}")]
     Serialisation(String),
+    /// I/O error
+    #[error("I/O error. {0}")]
+    Io(String),
+    /// An event log record, other than the last one, is corrupt.
+    #[error("Corrupt event log record at offset {0}")]
+    CorruptEventLog(u64),
+    /// The snapshot, receipt or wallet file is of a format version not supported.
+    #[error("Unsupported format version {0}")]
+    UnsupportedVersion(u16),
+    /// The debit conflicts with another debit, of the same id, signed by the Actor.
+    #[error("Debit conflicts with a debit of the same id")]
+    DebitEquivocation,
+    /// The Actor has equivocated, and the wallet accepts no further debits.
+    #[error("Wallet is frozen")]
+    WalletFrozen,
+    /// The preimage does not match the hash lock of the credit.
+    #[error("Preimage does not match the hash lock")]
+    InvalidPreimage,
+    /// The debit is not signed by enough of the owners for the spending policy of the wallet.
+    #[error("Debit does not meet the spending policy of the wallet")]
+    SpendingPolicyNotMet,
+    /// The wallet file cannot be decrypted with the password, or has been tampered with.
+    #[error("Wrong password, or corrupt wallet file")]
+    DecryptionFailed,
 }
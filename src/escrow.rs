@@ -0,0 +1,291 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Conditional credits, which are added to the balance of the recipient
+//! but cannot be debited until their condition is met.
+//! The condition is set by the sender, in a typed envelope carried by the msg
+//! of the credit, so that it is covered by the signatures of the Actor and of
+//! the Replicas. A plain msg cannot start with the marker of the envelope,
+//! so that a msg to the recipient never reads as a condition.
+//! A time-locked credit is unlocked when the section time (the epoch)
+//! reaches its unlock epoch. An escrowed credit is unlocked when released
+//! by its arbiter, or else leaves the wallet of the recipient, as a refund,
+//! once the section time reaches its refund epoch. A hash-time-locked
+//! credit is an escrowed credit released by the preimage of its hash (see [htlc]).
+//!
+//! [htlc]: crate::htlc
+
+use crate::{Error, Result};
+use multibase::Base;
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    Credit, CreditAgreementProof, CreditId, PublicKey, SignatureShare, SignedCredit, Token,
+};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Formatter};
+use threshold_crypto::PublicKeySet;
+use tiny_keccak::{Hasher, Sha3};
+
+/// The start of the msg of a conditional credit, which a plain msg cannot start with.
+const CONDITION_MARKER: char = '\u{0}';
+
+/// Whether the msg of a credit is that of a conditional credit,
+/// be it well-formed or not.
+pub(crate) fn is_conditional(msg: &str) -> bool {
+    msg.starts_with(CONDITION_MARKER)
+}
+
+/// The condition of a credit, and the msg to the recipient,
+/// as carried in the msg of the credit.
+#[derive(Serialize, Deserialize)]
+struct ConditionalMsg {
+    condition: CreditCondition,
+    msg: String,
+}
+
+/// The condition for the debiting of a credit.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub enum CreditCondition {
+    /// The credit can be debited from the epoch on.
+    TimeLock {
+        /// The first epoch at which the credit is unlocked.
+        unlock_epoch: u64,
+    },
+    /// The credit is held until released by the arbiter,
+    /// or refunded to the sender from the refund epoch on.
+    Escrow {
+        /// The key which releases the credit to the recipient.
+        arbiter: PublicKey,
+        /// The key to which the credit is refunded.
+        refund_to: PublicKey,
+        /// The first epoch at which the credit can be refunded.
+        refund_epoch: u64,
+    },
+    /// The credit is held until claimed by revealing the preimage of the hash,
+    /// before the deadline, or refunded to the sender from the deadline on.
+    HashLock {
+        /// The hash of the preimage which claims the credit.
+        hash: [u8; 32],
+        /// The key to which the credit is refunded.
+        refund_to: PublicKey,
+        /// The first epoch at which the credit can no longer
+        /// be claimed, but can be refunded.
+        deadline_epoch: u64,
+    },
+}
+
+impl CreditCondition {
+    /// The msg of a credit with this condition, and the msg to the recipient.
+    pub fn encode(&self, msg: &str) -> Result<String> {
+        let conditional = ConditionalMsg {
+            condition: self.clone(),
+            msg: msg.to_string(),
+        };
+        let bytes = bincode::serialize(&conditional)
+            .map_err(|_| Error::Serialisation("Could not serialise condition".into()))?;
+        Ok(format!(
+            "{}{}",
+            CONDITION_MARKER,
+            multibase::encode(Base::Base32Z, bytes)
+        ))
+    }
+
+    /// The condition, and the msg to the recipient, of the msg of a credit.
+    /// Returns None if the credit is unconditional, and an error
+    /// if the msg has the marker of a condition, but no condition.
+    pub fn decode(msg: &str) -> Result<Option<(Self, String)>> {
+        let encoded = match msg.strip_prefix(CONDITION_MARKER) {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+        let conditional: ConditionalMsg = match multibase::decode(encoded) {
+            Ok((Base::Base32Z, bytes)) => bincode::deserialize(&bytes)
+                .map_err(|_| Error::Serialisation("Could not deserialise condition".into()))?,
+            _ => return Err(Error::Serialisation("Expected z-base-32 encoding".into())),
+        };
+        Ok(Some((conditional.condition, conditional.msg)))
+    }
+
+    /// Whether a credit with this condition cannot be debited at the epoch.
+    pub fn is_locked_at(&self, epoch: u64) -> bool {
+        match self {
+            Self::TimeLock { unlock_epoch } => epoch < *unlock_epoch,
+            Self::Escrow { .. } | Self::HashLock { .. } => true,
+        }
+    }
+
+    /// The key to which a credit with this condition is refunded,
+    /// and the first epoch at which it can be, if it can be refunded.
+    pub fn refund(&self) -> Option<(PublicKey, u64)> {
+        match self {
+            Self::TimeLock { .. } => None,
+            Self::Escrow {
+                refund_to,
+                refund_epoch,
+                ..
+            } => Some((*refund_to, *refund_epoch)),
+            Self::HashLock {
+                refund_to,
+                deadline_epoch,
+                ..
+            } => Some((*refund_to, *deadline_epoch)),
+        }
+    }
+}
+
+/// A credit which cannot be debited until its condition is met.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct LockedCredit {
+    /// The amount of the credit.
+    pub amount: Token,
+    /// The condition of the credit.
+    pub condition: CreditCondition,
+}
+
+/// What the arbiter of an escrowed credit signs, to release it.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct EscrowRelease {
+    /// The id of the escrowed credit.
+    pub credit_id: CreditId,
+}
+
+/// The credit refunding the escrowed credit, to be signed by the key it is refunded to.
+pub fn escrow_refund(credit_id: &CreditId, locked: &LockedCredit) -> Result<Credit> {
+    let (refund_to, _) = locked.condition.refund().ok_or(Error::InvalidOperation)?;
+    let id_bytes = bincode::serialize(&(credit_id, "refund"))
+        .map_err(|_| Error::Serialisation("Could not serialise credit id".into()))?;
+    let mut hasher = Sha3::v256();
+    let mut id = [0; 32];
+    hasher.update(&id_bytes);
+    hasher.finalize(&mut id);
+    Ok(Credit {
+        id,
+        recipient: refund_to,
+        amount: locked.amount,
+        msg: "refund".to_string(),
+    })
+}
+
+/// The Replica event raised when
+/// an escrowed credit has been released by its arbiter.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct EscrowReleased {
+    /// The wallet holding the credit.
+    pub wallet: PublicKey,
+    /// The id of the credit.
+    pub credit_id: CreditId,
+}
+
+/// The Replica event raised when an escrowed credit
+/// has been refunded, and so left the wallet holding it.
+/// A quorum of these, from the Replicas of the wallet,
+/// makes the proof of the refund credit.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EscrowRefunded {
+    /// The wallet holding the credit.
+    pub wallet: PublicKey,
+    /// The id of the credit.
+    pub credit_id: CreditId,
+    /// The refund credit, signed by the key it is refunded to.
+    pub signed_refund: SignedCredit,
+    /// Replica signature over the signed refund credit.
+    pub replica_sig: SignatureShare,
+    /// The PK Set of the Replicas
+    pub replicas: PublicKeySet,
+}
+
+impl Debug for EscrowRefunded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EscrowRefunded {{ wallet: {:?}, credit_id: {:?}, signed_refund: {:?}, replica_sig: {:?}, replicas: PkSet {{ public_key: {:?} }} }}",
+            self.wallet,
+            self.credit_id,
+            self.signed_refund,
+            self.replica_sig,
+            self.replicas.public_key()
+        )
+    }
+}
+
+/// The proof of a refund credit, for propagation to the Replicas
+/// of the key it is refunded to, once a quorum of the Replicas
+/// of the wallet holding the escrowed credit have refunded it.
+/// Returns None until there is a quorum.
+pub fn refund_proof(refunds: &[EscrowRefunded]) -> Result<Option<CreditAgreementProof>> {
+    let first = match refunds.first() {
+        Some(first) => first,
+        None => return Ok(None),
+    };
+    let shares: BTreeMap<_, _> = refunds
+        .iter()
+        .filter(|r| r.replicas == first.replicas && r.signed_refund == first.signed_refund)
+        .map(|r| (r.replica_sig.index, &r.replica_sig.share))
+        .collect();
+    if shares.len() <= first.replicas.threshold() {
+        return Ok(None);
+    }
+    let signature = first
+        .replicas
+        .combine_signatures(shares)
+        .map_err(|_| Error::CannotAggregate)?;
+    Ok(Some(CreditAgreementProof {
+        signed_credit: first.signed_refund.clone(),
+        debiting_replicas_sig: sn_data_types::Signature::Bls(signature),
+        debiting_replicas_keys: first.replicas.clone(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_conditional, CreditCondition};
+    use crate::Result;
+    use sn_data_types::{Keypair, PublicKey};
+
+    #[test]
+    fn encodes_conditions_in_msg() -> Result<()> {
+        let time_lock = CreditCondition::TimeLock { unlock_epoch: 7 };
+        let msg = time_lock.encode("rent: march")?;
+        assert!(is_conditional(&msg));
+        assert_eq!(
+            CreditCondition::decode(&msg)?,
+            Some((time_lock, "rent: march".to_string()))
+        );
+
+        let key = || -> PublicKey { Keypair::new_ed25519(&mut rand::thread_rng()).public_key() };
+        let escrow = CreditCondition::Escrow {
+            arbiter: key(),
+            refund_to: key(),
+            refund_epoch: 9,
+        };
+        let msg = escrow.encode("")?;
+        assert_eq!(
+            CreditCondition::decode(&msg)?,
+            Some((escrow, String::new()))
+        );
+
+        let hash_lock = CreditCondition::HashLock {
+            hash: crate::htlc::hash_lock(b"secret"),
+            refund_to: key(),
+            deadline_epoch: 3,
+        };
+        let msg = hash_lock.encode("swap")?;
+        assert_eq!(
+            CreditCondition::decode(&msg)?,
+            Some((hash_lock, "swap".to_string()))
+        );
+
+        // a plain msg never reads as a condition
+        assert_eq!(CreditCondition::decode("asdf")?, None);
+        assert_eq!(CreditCondition::decode("timelock:7:asdf")?, None);
+        assert!(!is_conditional("timelock:7:asdf"));
+        assert!(CreditCondition::decode("\u{0}timelock:7:asdf").is_err());
+        Ok(())
+    }
+}
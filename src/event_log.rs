@@ -0,0 +1,665 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{Error, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+/// The length of the payload and its checksum, followed by the
+/// checksum of the payload, preceding the payload in every record.
+const HEADER_LEN: usize = 12;
+
+/// When the appended records are synced to disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Every record is synced before `append` returns.
+    Always,
+    /// Records are synced at every n:th append.
+    EveryN(usize),
+    /// Syncing is left to the OS, or to explicit calls to `sync`.
+    Never,
+}
+
+/// A file-backed, append-only, log of events.
+/// It can hold the events of a single wallet, or those of all
+/// the wallets of a section, and be replayed into the corresponding
+/// `WalletReplica` or `TransferReplicaStore` at startup.
+///
+/// Every record is a header, of the length of the payload along with
+/// its crc32 checksum, and of the crc32 checksum of the payload,
+/// followed by the payload, i.e. the bincode serialised event.
+/// A torn final record, as left by a crash during an append,
+/// is dropped when the log is opened. Any other corrupt record
+/// fails the opening, so that no event after it is ever dropped.
+///
+/// The log can be compacted into a snapshot of the state
+/// that its events lead to, kept in a file of its own.
+/// The snapshot is kept as the bytes it is encoded to, such as by
+/// `WalletReplicaSnapshot::to_bytes`, which carry the version of its format.
+pub struct EventLog<E> {
+    path: PathBuf,
+    file: File,
+    /// The length of the log, in bytes.
+    len: u64,
+    sync_policy: SyncPolicy,
+    /// Records appended since the last sync.
+    unsynced: usize,
+    _event: PhantomData<E>,
+}
+
+impl<E: Serialize + DeserializeOwned> EventLog<E> {
+    /// Opens the log at the path, creating it if it does not exist,
+    /// and returns it along with all the events it holds, in order.
+    pub fn open<P: AsRef<Path>>(path: P, sync_policy: SyncPolicy) -> Result<(Self, Vec<E>)> {
+        Self::open_from(path, sync_policy, 0)
+    }
+
+    /// Opens the log at the path, along with the snapshot it has been compacted into, if any.
+    /// Returns the log, the bytes of the snapshot, and the events appended after the snapshot, in order.
+    pub fn open_with_snapshot<P, Q>(
+        path: P,
+        snapshot_path: Q,
+        sync_policy: SyncPolicy,
+    ) -> Result<(Self, Option<Vec<u8>>, Vec<E>)>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let snapshot_path = snapshot_path.as_ref();
+        if !snapshot_path.exists() {
+            let (log, events) = Self::open(path, sync_policy)?;
+            return Ok((log, None, events));
+        }
+        let bytes = std::fs::read(snapshot_path).map_err(io_error)?;
+        let file: SnapshotFile = bincode::deserialize(&bytes)
+            .map_err(|_| Error::Serialisation("Could not deserialise snapshot".into()))?;
+
+        let log_len = std::fs::metadata(path.as_ref()).map_or(0, |m| m.len());
+        if log_len < file.log_len {
+            // A compaction was interrupted after the log was truncated,
+            // so the snapshot covers none of the events in the log.
+            write_atomic(snapshot_path, &SnapshotFile::to_bytes(0, &file.snapshot)?)?;
+            let (log, events) = Self::open(path, sync_policy)?;
+            return Ok((log, Some(file.snapshot), events));
+        }
+        let (log, events) = Self::open_from(path, sync_policy, file.log_len)?;
+        Ok((log, Some(file.snapshot), events))
+    }
+
+    /// Opens the log, skipping the records in the first `skip` bytes.
+    fn open_from<P: AsRef<Path>>(
+        path: P,
+        sync_policy: SyncPolicy,
+        skip: u64,
+    ) -> Result<(Self, Vec<E>)> {
+        let path = path.as_ref().to_path_buf();
+        let existed = path.exists();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(io_error)?;
+        if !existed {
+            sync_dir(&path)?;
+        }
+
+        let mut bytes = vec![];
+        let _ = file.read_to_end(&mut bytes).map_err(io_error)?;
+        let skip = (skip as usize).min(bytes.len());
+        let (events, valid_len) = Self::read_records(&bytes[skip..])?;
+        let len = (skip + valid_len) as u64;
+        if len < bytes.len() as u64 {
+            // drop the torn final record
+            file.set_len(len).map_err(io_error)?;
+            file.sync_all().map_err(io_error)?;
+        }
+
+        let log = Self {
+            path,
+            file,
+            len,
+            sync_policy,
+            unsynced: 0,
+            _event: PhantomData,
+        };
+        Ok((log, events))
+    }
+
+    /// The path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The length of the log, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the log holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The sync policy of the log.
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.sync_policy
+    }
+
+    /// Appends the event to the log, syncing it as per the sync policy.
+    pub fn append(&mut self, event: &E) -> Result<()> {
+        let payload = bincode::serialize(event)
+            .map_err(|_| Error::Serialisation("Could not serialise event".into()))?;
+        let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+        let len = (payload.len() as u32).to_le_bytes();
+        record.extend_from_slice(&len);
+        record.extend_from_slice(&crc32fast::hash(&len).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        self.file.write_all(&record).map_err(io_error)?;
+        self.len += record.len() as u64;
+
+        self.unsynced += 1;
+        match self.sync_policy {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::EveryN(n) if self.unsynced >= n => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Syncs all appended records to disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync_data().map_err(io_error)?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Compacts the log into the bytes of the snapshot, which must be of the state
+    /// that all the events in the log lead to. The snapshot is written
+    /// to its file, and the log is then truncated, so that replay
+    /// only covers the events appended after the snapshot.
+    /// An interruption at any point leaves a state that is recovered
+    /// by `open_with_snapshot`.
+    pub fn compact<P: AsRef<Path>>(&mut self, snapshot_path: P, snapshot: &[u8]) -> Result<()> {
+        let snapshot_path = snapshot_path.as_ref();
+        self.sync()?;
+        write_atomic(snapshot_path, &SnapshotFile::to_bytes(self.len, snapshot)?)?;
+        self.file.set_len(0).map_err(io_error)?;
+        self.file.sync_all().map_err(io_error)?;
+        self.len = 0;
+        write_atomic(snapshot_path, &SnapshotFile::to_bytes(0, snapshot)?)
+    }
+
+    /// Returns the events of all the complete records, and the length
+    /// of the bytes they span. Only the final record may be incomplete,
+    /// i.e. a record of which the header or the payload is cut short,
+    /// or fails its checksum, which no valid record follows.
+    fn read_records(bytes: &[u8]) -> Result<(Vec<E>, usize)> {
+        let mut events = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let remaining = &bytes[offset..];
+            let record = match Record::parse(remaining) {
+                Record::Valid(payload) => payload,
+                Record::Invalid if !Record::any_follows(remaining) => break,
+                Record::Invalid => return Err(Error::CorruptEventLog(offset as u64)),
+            };
+            let event = bincode::deserialize(record)
+                .map_err(|_| Error::Serialisation("Could not deserialise event".into()))?;
+            events.push(event);
+            offset += HEADER_LEN + record.len();
+        }
+        Ok((events, offset))
+    }
+}
+
+/// A record at the start of the bytes.
+enum Record<'a> {
+    /// A complete record, of the payload.
+    Valid(&'a [u8]),
+    /// A record of which the header or the payload is cut short, or fails its checksum.
+    /// Followed by no valid record, it is the torn tail left by an interrupted append,
+    /// e.g. a header partly written, or zeroes, and is otherwise corrupt.
+    Invalid,
+}
+
+impl<'a> Record<'a> {
+    fn parse(bytes: &'a [u8]) -> Self {
+        if bytes.len() < HEADER_LEN {
+            return Record::Invalid;
+        }
+        let word =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if crc32fast::hash(&bytes[..4]) != word(4) {
+            return Record::Invalid;
+        }
+        let end = HEADER_LEN + word(0) as usize;
+        if bytes.len() < end {
+            return Record::Invalid;
+        }
+        let payload = &bytes[HEADER_LEN..end];
+        if crc32fast::hash(payload) == word(8) {
+            Record::Valid(payload)
+        } else {
+            Record::Invalid
+        }
+    }
+
+    /// Whether a valid record starts anywhere after the start of the bytes.
+    fn any_follows(bytes: &[u8]) -> bool {
+        (1..bytes.len()).any(|i| matches!(Record::parse(&bytes[i..]), Record::Valid(_)))
+    }
+}
+
+impl<E> fmt::Debug for EventLog<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EventLog {{ path: {:?}, len: {:?}, sync_policy: {:?}, unsynced: {:?} }}",
+            self.path, self.len, self.sync_policy, self.unsynced
+        )
+    }
+}
+
+/// The bytes of a snapshot, along with the length of the log it covers.
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    log_len: u64,
+    snapshot: Vec<u8>,
+}
+
+impl SnapshotFile {
+    fn to_bytes(log_len: u64, snapshot: &[u8]) -> Result<Vec<u8>> {
+        let snapshot = snapshot.to_vec();
+        bincode::serialize(&SnapshotFile { log_len, snapshot })
+            .map_err(|_| Error::Serialisation("Could not serialise snapshot".into()))
+    }
+}
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::Io(error.to_string())
+}
+
+/// Replaces the file at the path with the bytes, so that
+/// either the previous or the new content is there after a crash.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_file(path, bytes, false)
+}
+
+/// As [write_atomic], with the file, and the temporary file it is written to,
+/// readable and writable by the owner only.
+pub(crate) fn write_atomic_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_file(path, bytes, true)
+}
+
+fn write_file(path: &Path, bytes: &[u8], private: bool) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut file = File::create(&tmp_path).map_err(io_error)?;
+    if private {
+        // also restricts a temporary file left over by an earlier write
+        restrict_to_owner(&file)?;
+    }
+    file.write_all(bytes).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    std::fs::rename(&tmp_path, path).map_err(io_error)?;
+    sync_dir(path)
+}
+
+#[cfg(unix)]
+fn restrict_to_owner(file: &File) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))
+        .map_err(io_error)
+}
+
+#[cfg(not(unix))]
+fn restrict_to_owner(_file: &File) -> Result<()> {
+    Ok(())
+}
+
+/// Makes the creation of the file durable.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            File::open(dir).and_then(|d| d.sync_all()).map_err(io_error)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_atomic, write_atomic_private, EventLog, SnapshotFile, SyncPolicy};
+    use crate::{
+        test_utils::get_random_genesis, Error, Result, WalletReplica, WalletReplicaSnapshot,
+        REPLICA_SNAPSHOT_VERSION,
+    };
+    use sn_data_types::{Keypair, OwnerType, ReplicaEvent, Token, TransferPropagated};
+    use std::{fs::OpenOptions, io::Write};
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn replays_appended_events() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+        let events = get_credits(owner, 3)?;
+
+        let (mut log, replayed) = EventLog::open(&path, SyncPolicy::EveryN(2))?;
+        assert!(replayed.is_empty());
+        for event in &events {
+            log.append(event)?;
+        }
+        drop(log);
+
+        let (_, replayed) = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Always)?;
+        assert_eq!(replayed, events);
+
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let replica = WalletReplica::from_history(
+            OwnerType::Single(owner),
+            sk_set.secret_key_share(0).public_key_share(),
+            0,
+            sk_set.public_keys(),
+            replayed,
+        )?;
+        assert_eq!(replica.balance(), Token::from_nano(30));
+        Ok(())
+    }
+
+    #[test]
+    fn drops_torn_final_record() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+        let events = get_credits(owner, 2)?;
+
+        let (mut log, _) = EventLog::open(&path, SyncPolicy::Always)?;
+        for event in &events {
+            log.append(event)?;
+        }
+        drop(log);
+        let len = std::fs::metadata(&path)
+            .map_err(|e| Error::Io(e.to_string()))?
+            .len();
+
+        // a crash in the middle of an append
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| Error::Io(e.to_string()))?;
+        file.write_all(&[42, 0, 0, 0, 1, 2])
+            .map_err(|e| Error::Io(e.to_string()))?;
+        drop(file);
+
+        let (mut log, replayed) = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Always)?;
+        assert_eq!(replayed, events);
+        let truncated_len = std::fs::metadata(&path)
+            .map_err(|e| Error::Io(e.to_string()))?
+            .len();
+        assert_eq!(truncated_len, len);
+
+        // appending continues after the last complete record
+        let next = get_credits(owner, 1)?;
+        log.append(&next[0])?;
+        drop(log);
+        let (_, replayed) = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Never)?;
+        assert_eq!(replayed.len(), 3);
+
+        // a crash with the payload of the final record partly written
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .map_err(|e| Error::Io(e.to_string()))?;
+        let full_len = file.metadata().map_err(|e| Error::Io(e.to_string()))?.len();
+        file.set_len(full_len - 3)
+            .map_err(|e| Error::Io(e.to_string()))?;
+        drop(file);
+        let (_, replayed) = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Never)?;
+        assert_eq!(replayed, events);
+        Ok(())
+    }
+
+    #[test]
+    fn drops_torn_final_header() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+        let events = get_credits(owner, 1)?;
+
+        let (mut log, _) = EventLog::open(&path, SyncPolicy::Always)?;
+        log.append(&events[0])?;
+        let len = log.len();
+        drop(log);
+        let header = &std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?[..12];
+
+        // a crash in the middle of the write of a header, and a tail of zeroes,
+        // as left by a file system which extended the file before the crash
+        let tails = vec![header[..4].to_vec(), header[..7].to_vec(), vec![0; 40]];
+        for tail in tails {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .map_err(|e| Error::Io(e.to_string()))?;
+            file.write_all(&tail)
+                .map_err(|e| Error::Io(e.to_string()))?;
+            drop(file);
+
+            let (log, replayed) = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Always)?;
+            assert_eq!(replayed, events);
+            assert_eq!(log.len(), len);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_corrupt_record_before_the_last() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+
+        let (mut log, _) = EventLog::open(&path, SyncPolicy::Always)?;
+        for event in &get_credits(owner, 2)? {
+            log.append(event)?;
+        }
+        drop(log);
+
+        let mut bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        bytes[10] ^= 0xff;
+        std::fs::write(&path, bytes).map_err(|e| Error::Io(e.to_string()))?;
+
+        let result = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Always);
+        assert_eq!(result.err(), Some(Error::CorruptEventLog(0)));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_corrupt_length_of_a_middle_record() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+
+        let (mut log, _) = EventLog::open(&path, SyncPolicy::Always)?;
+        let events = get_credits(owner, 3)?;
+        log.append(&events[0])?;
+        let second = log.len();
+        for event in &events[1..] {
+            log.append(event)?;
+        }
+        drop(log);
+
+        // a bit flip making the length of the second record run past the end of the log
+        let mut bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        let len = bytes.len();
+        bytes[second as usize + 3] ^= 0x01;
+        std::fs::write(&path, bytes).map_err(|e| Error::Io(e.to_string()))?;
+
+        let result = EventLog::<ReplicaEvent>::open(&path, SyncPolicy::Always);
+        assert_eq!(result.err(), Some(Error::CorruptEventLog(second)));
+        // and none of the events after it are dropped
+        let corrupt_len = std::fs::metadata(&path)
+            .map_err(|e| Error::Io(e.to_string()))?
+            .len();
+        assert_eq!(corrupt_len, len as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn compacts_into_replica_snapshot() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let snapshot_path = dir.path().join("wallet.snapshot");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let mut replica = WalletReplica::from_history(
+            OwnerType::Single(owner),
+            sk_set.secret_key_share(0).public_key_share(),
+            0,
+            sk_set.public_keys(),
+            vec![],
+        )?;
+
+        let (mut log, _) = EventLog::open(&path, SyncPolicy::Always)?;
+        for event in get_credits(owner, 2)? {
+            log.append(&event)?;
+            replica.apply(event)?;
+        }
+        log.compact(&snapshot_path, &replica.snapshot().to_bytes()?)?;
+        assert!(log.is_empty());
+
+        let next = get_credits(owner, 1)?;
+        log.append(&next[0])?;
+        drop(log);
+
+        let (log, snapshot, events) = EventLog::<ReplicaEvent>::open_with_snapshot(
+            &path,
+            &snapshot_path,
+            SyncPolicy::Always,
+        )?;
+        assert_eq!(events, next);
+        let mut snapshot = snapshot.ok_or(Error::InvalidOperation)?;
+        let mut restored =
+            WalletReplica::from_replica_snapshot(WalletReplicaSnapshot::from_bytes(&snapshot)?)?;
+        for event in events {
+            restored.apply(event)?;
+        }
+        assert_eq!(restored.balance(), Token::from_nano(30));
+        assert_eq!(log.len(), std::fs::metadata(&path).map_or(0, |m| m.len()));
+
+        // the version is read before the snapshot is decoded
+        snapshot[..2].copy_from_slice(&(REPLICA_SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            WalletReplicaSnapshot::from_bytes(&snapshot).err(),
+            Some(Error::UnsupportedVersion(REPLICA_SNAPSHOT_VERSION + 1))
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_file_is_restricted_to_owner() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("secret");
+        // a temporary file left over by an interrupted write, readable by all
+        let tmp = dir.path().join("secret.tmp");
+        std::fs::write(&tmp, b"stale").map_err(|e| Error::Io(e.to_string()))?;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o644))
+            .map_err(|e| Error::Io(e.to_string()))?;
+
+        write_atomic_private(&path, b"secret")?;
+        let metadata = std::fs::metadata(&path).map_err(|e| Error::Io(e.to_string()))?;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(
+            std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?,
+            b"secret"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn recovers_interrupted_compaction() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet.log");
+        let snapshot_path = dir.path().join("wallet.snapshot");
+        let mut rng = rand::thread_rng();
+        let owner = Keypair::new_ed25519(&mut rng).public_key();
+
+        let (mut log, _) = EventLog::open(&path, SyncPolicy::Always)?;
+        for event in get_credits(owner, 2)? {
+            log.append(&event)?;
+        }
+        // a crash after the snapshot was written, before the log was truncated
+        write_atomic(&snapshot_path, &SnapshotFile::to_bytes(log.len(), &[20])?)?;
+        drop(log);
+        let (log, snapshot, events) = EventLog::<ReplicaEvent>::open_with_snapshot(
+            &path,
+            &snapshot_path,
+            SyncPolicy::Always,
+        )?;
+        assert_eq!(snapshot, Some(vec![20]));
+        assert!(events.is_empty());
+
+        // a crash after the log was truncated, before the snapshot was updated
+        write_atomic(&snapshot_path, &SnapshotFile::to_bytes(log.len(), &[20])?)?;
+        log.file.set_len(0).map_err(|e| Error::Io(e.to_string()))?;
+        drop(log);
+        let (mut log, snapshot, events) = EventLog::<ReplicaEvent>::open_with_snapshot(
+            &path,
+            &snapshot_path,
+            SyncPolicy::Always,
+        )?;
+        assert_eq!(snapshot, Some(vec![20]));
+        assert!(events.is_empty());
+
+        // later appends are not covered by the snapshot
+        let next = get_credits(owner, 2)?;
+        for event in &next {
+            log.append(event)?;
+        }
+        drop(log);
+        let (_, snapshot, events) = EventLog::<ReplicaEvent>::open_with_snapshot(
+            &path,
+            &snapshot_path,
+            SyncPolicy::Always,
+        )?;
+        assert_eq!(snapshot, Some(vec![20]));
+        assert_eq!(events, next);
+        Ok(())
+    }
+
+    fn get_credits(recipient: sn_data_types::PublicKey, count: usize) -> Result<Vec<ReplicaEvent>> {
+        (0..count)
+            .map(|_| {
+                Ok(ReplicaEvent::TransferPropagated(TransferPropagated {
+                    credit_proof: get_random_genesis(10, recipient)?,
+                }))
+            })
+            .collect()
+    }
+}
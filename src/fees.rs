@@ -0,0 +1,160 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{batch::sum_amounts, Error, Result};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{CreditId, Debit, DebitId, Token};
+use std::convert::TryFrom;
+use tiny_keccak::{Hasher, Sha3};
+
+/// The fee for the processing of a transfer, paid by the sender
+/// on top of the credited amount. The debit of a transfer is the
+/// credited amount plus the fee, and the fee is credited to the
+/// fee wallet of the section of the sender.
+/// In a batch, the fee is that of each of the credits, summed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeePolicy {
+    /// No fee.
+    #[default]
+    Free,
+    /// The same fee for every transfer.
+    Flat(Token),
+    /// A share of the credited amount, in basis points (1/100th of a percent),
+    /// rounded up to the nearest nano.
+    Percentage(u16),
+    /// A base fee, plus a fee per byte of the msg to the recipient.
+    SizeBased {
+        /// The fee for every transfer.
+        base: Token,
+        /// The fee per byte of the msg.
+        per_byte: Token,
+    },
+}
+
+impl FeePolicy {
+    /// The fee for crediting the amount, with the msg.
+    /// Returns None if the fee overflows.
+    pub fn fee(&self, amount: Token, msg: &str) -> Option<Token> {
+        match *self {
+            Self::Free => Some(Token::zero()),
+            Self::Flat(fee) => Some(fee),
+            Self::Percentage(basis_points) => {
+                let fee = (amount.as_nano() as u128 * basis_points as u128).div_ceil(10_000);
+                u64::try_from(fee).ok().map(Token::from_nano)
+            }
+            Self::SizeBased { base, per_byte } => {
+                let size_fee = per_byte.as_nano().checked_mul(msg.len() as u64)?;
+                base.checked_add(Token::from_nano(size_fee))
+            }
+        }
+    }
+
+    /// The amount to debit, for the credits and their fees.
+    /// Returns None if the amount overflows.
+    pub fn debit_amount<'a, I>(&self, credits: I) -> Option<Token>
+    where
+        I: IntoIterator<Item = (Token, &'a str)>,
+    {
+        credits
+            .into_iter()
+            .try_fold(Token::zero(), |total, (amount, msg)| {
+                total
+                    .checked_add(amount)?
+                    .checked_add(self.fee(amount, msg)?)
+            })
+    }
+
+    /// Validates that the debit amount is exactly the credited amounts plus their fees.
+    pub(crate) fn validate<'a, I>(&self, debit_amount: Token, credits: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Token, &'a str)> + Clone,
+    {
+        let credited = sum_amounts(credits.clone().into_iter().map(|(amount, _)| amount))
+            .ok_or(Error::CreditDebitValueMismatch)?;
+        if debit_amount < credited {
+            return Err(Error::CreditDebitValueMismatch);
+        }
+        match self.debit_amount(credits) {
+            Some(expected) if debit_amount == expected => Ok(()),
+            Some(expected) if debit_amount > expected => Err(Error::CreditDebitValueMismatch),
+            _ => Err(Error::InsufficientFee),
+        }
+    }
+}
+
+/// The id of the credit of the fee paid by a debit.
+pub fn fee_credit_id(debit_id: &DebitId) -> Result<CreditId> {
+    let id_bytes = bincode::serialize(&(debit_id, "fee"))
+        .map_err(|_| Error::Serialisation("Could not serialise debit id".into()))?;
+    let mut hasher = Sha3::v256();
+    let mut output = [0; 32];
+    hasher.update(&id_bytes);
+    hasher.finalize(&mut output);
+    Ok(output)
+}
+
+/// The fee paid by the debit, being what it debits in excess of the credited amount.
+pub(crate) fn paid_fee(debit: &Debit, credited: Token) -> Result<Token> {
+    debit
+        .amount
+        .checked_sub(credited)
+        .ok_or(Error::CreditDebitValueMismatch)
+}
+
+#[cfg(test)]
+mod test {
+    use super::FeePolicy;
+    use crate::Error;
+    use sn_data_types::Token;
+
+    #[test]
+    fn computes_fees_by_policy() {
+        let amount = Token::from_nano(1_001);
+        assert_eq!(FeePolicy::Free.fee(amount, "asdf"), Some(Token::zero()));
+        assert_eq!(
+            FeePolicy::Flat(Token::from_nano(5)).fee(amount, "asdf"),
+            Some(Token::from_nano(5))
+        );
+        // 1% of 1001, rounded up
+        assert_eq!(
+            FeePolicy::Percentage(100).fee(amount, "asdf"),
+            Some(Token::from_nano(11))
+        );
+        let size_based = FeePolicy::SizeBased {
+            base: Token::from_nano(2),
+            per_byte: Token::from_nano(3),
+        };
+        assert_eq!(size_based.fee(amount, "asdf"), Some(Token::from_nano(14)));
+    }
+
+    #[test]
+    fn validates_debit_amount() {
+        let policy = FeePolicy::Flat(Token::from_nano(5));
+        let credits = vec![(Token::from_nano(10), "a"), (Token::from_nano(20), "b")];
+        assert_eq!(
+            policy.debit_amount(credits.clone()),
+            Some(Token::from_nano(40))
+        );
+        assert_eq!(
+            policy.validate(Token::from_nano(40), credits.clone()),
+            Ok(())
+        );
+        assert_eq!(
+            policy.validate(Token::from_nano(39), credits.clone()),
+            Err(Error::InsufficientFee)
+        );
+        assert_eq!(
+            policy.validate(Token::from_nano(41), credits.clone()),
+            Err(Error::CreditDebitValueMismatch)
+        );
+        assert_eq!(
+            policy.validate(Token::from_nano(29), credits),
+            Err(Error::CreditDebitValueMismatch)
+        );
+    }
+}
@@ -0,0 +1,457 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! An indexed view of the history of an Actor, in the order the
+//! credits and debits were applied, queried by counterparty, amount,
+//! debit counter and credit id, a page at a time.
+//! Each entry is also a line of the statement of the wallet,
+//! with the balance after it. Every change of the balance of the Actor
+//! has an entry, so that the last line has the balance of the Actor.
+
+use crate::{batch::sum_amounts, BatchTransferAgreementProof, Error, Result};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    CreditAgreementProof, CreditId, DebitId, PublicKey, Token, TransferAgreementProof,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+/// Whether an entry added to, or took from, the balance.
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub enum EntryKind {
+    /// A credit to the wallet.
+    Credit,
+    /// A debit of the wallet.
+    Debit,
+    /// The refund of an escrowed credit to the wallet, which left it.
+    Refund,
+    /// The balance of the wallet, as of a snapshot,
+    /// or as synched from the Replicas.
+    Balance,
+}
+
+/// A credit or debit in the history of an Actor.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct HistoryEntry {
+    /// The position of the entry in the history, from 0.
+    pub index: usize,
+    /// Whether a credit or a debit.
+    pub kind: EntryKind,
+    /// The recipient of a debit or refund. A credit does not name its sender.
+    pub counterparty: Option<PublicKey>,
+    /// The amount credited, to the wallet or to the recipient,
+    /// or the balance itself, of a balance entry.
+    pub amount: Token,
+    /// The fee paid with a debit, on top of the amount.
+    pub fee: Token,
+    /// The msg of the credit.
+    pub msg: String,
+    /// The id of the credit, or the default id, of a balance entry.
+    pub credit_id: CreditId,
+    /// The id of a debit.
+    pub debit_id: Option<DebitId>,
+}
+
+/// A line of the statement of a wallet.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct StatementLine {
+    /// The credit or debit.
+    pub entry: HistoryEntry,
+    /// The balance after the entry.
+    pub balance: Token,
+}
+
+/// The criteria of a history query. Unset criteria match any entry.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct HistoryQuery {
+    /// Only debits to this recipient.
+    pub counterparty: Option<PublicKey>,
+    /// Only entries of at least this amount.
+    pub min_amount: Option<Token>,
+    /// Only entries of at most this amount.
+    pub max_amount: Option<Token>,
+    /// Only debits with a counter in this range.
+    pub debit_counters: Option<Range<u64>>,
+    /// Only the entry of this credit id.
+    pub credit_id: Option<CreditId>,
+}
+
+/// A page of the results of a query.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct HistoryPage<T> {
+    /// The results on the page.
+    pub items: Vec<T>,
+    /// The number of results of the query, on all pages.
+    pub total: usize,
+}
+
+impl HistoryQuery {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        let counter = entry.debit_id.map(|id| id.counter);
+        self.counterparty
+            .is_none_or(|key| entry.counterparty == Some(key))
+            && self.min_amount.is_none_or(|min| entry.amount >= min)
+            && self.max_amount.is_none_or(|max| entry.amount <= max)
+            && self
+                .debit_counters
+                .as_ref()
+                .is_none_or(|range| counter.is_some_and(|counter| range.contains(&counter)))
+            && self.credit_id.is_none_or(|id| entry.credit_id == id)
+    }
+}
+
+/// The entries of the history, with the balance after each,
+/// and indices by counterparty, debit counter and credit id.
+/// A batch debit has an entry per credit, which follow each other,
+/// so a debit counter is indexed to the first of them.
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub(crate) struct HistoryIndex {
+    lines: Vec<StatementLine>,
+    by_counterparty: HashMap<PublicKey, Vec<usize>>,
+    by_debit_counter: BTreeMap<u64, usize>,
+    by_credit_id: HashMap<CreditId, usize>,
+}
+
+impl HistoryIndex {
+    /// The index of a history opening with the balance.
+    pub(crate) fn opening(balance: Token) -> Self {
+        let mut index = Self::default();
+        index.push_balance(balance);
+        index
+    }
+
+    pub(crate) fn push_credit(&mut self, proof: &CreditAgreementProof) -> Result<()> {
+        let credit = &proof.signed_credit.credit;
+        self.push(HistoryEntry {
+            index: self.lines.len(),
+            kind: EntryKind::Credit,
+            counterparty: None,
+            amount: credit.amount,
+            fee: Token::zero(),
+            msg: credit.msg.clone(),
+            credit_id: credit.id,
+            debit_id: None,
+        })
+    }
+
+    pub(crate) fn push_debit(&mut self, proof: &TransferAgreementProof) -> Result<()> {
+        let credit = &proof.signed_credit.credit;
+        let fee = proof
+            .signed_debit
+            .amount()
+            .checked_sub(credit.amount)
+            .unwrap_or_else(Token::zero);
+        self.push(HistoryEntry {
+            index: self.lines.len(),
+            kind: EntryKind::Debit,
+            counterparty: Some(credit.recipient),
+            amount: credit.amount,
+            fee,
+            msg: credit.msg.clone(),
+            credit_id: credit.id,
+            debit_id: Some(proof.id()),
+        })
+    }
+
+    /// An entry per credit of the batch, the first of which has the fee of the debit.
+    pub(crate) fn push_batch_debit(&mut self, proof: &BatchTransferAgreementProof) -> Result<()> {
+        let mut fee = sum_amounts(proof.signed_credits.iter().map(|c| c.amount()))
+            .and_then(|credited| proof.amount().checked_sub(credited))
+            .unwrap_or_else(Token::zero);
+        for signed_credit in &proof.signed_credits {
+            let credit = &signed_credit.credit;
+            self.push(HistoryEntry {
+                index: self.lines.len(),
+                kind: EntryKind::Debit,
+                counterparty: Some(credit.recipient),
+                amount: credit.amount,
+                fee,
+                msg: credit.msg.clone(),
+                credit_id: credit.id,
+                debit_id: Some(proof.id()),
+            })?;
+            fee = Token::zero();
+        }
+        Ok(())
+    }
+
+    /// The refund credit, made by our Replicas, of an escrowed credit to us.
+    pub(crate) fn push_refund(&mut self, proof: &CreditAgreementProof) -> Result<()> {
+        let credit = &proof.signed_credit.credit;
+        self.push(HistoryEntry {
+            index: self.lines.len(),
+            kind: EntryKind::Refund,
+            counterparty: Some(credit.recipient),
+            amount: credit.amount,
+            fee: Token::zero(),
+            msg: credit.msg.clone(),
+            credit_id: credit.id,
+            debit_id: None,
+        })
+    }
+
+    /// The balance of the wallet, unless the statement already ends at it.
+    /// A balance entry is not indexed, as it has no counterparty, debit or credit.
+    pub(crate) fn push_balance(&mut self, balance: Token) {
+        if self.balance() == balance {
+            return;
+        }
+        self.lines.push(StatementLine {
+            entry: HistoryEntry {
+                index: self.lines.len(),
+                kind: EntryKind::Balance,
+                counterparty: None,
+                amount: balance,
+                fee: Token::zero(),
+                msg: String::new(),
+                credit_id: Default::default(),
+                debit_id: None,
+            },
+            balance,
+        });
+    }
+
+    /// The balance after the last entry.
+    pub(crate) fn balance(&self) -> Token {
+        self.lines
+            .last()
+            .map(|l| l.balance)
+            .unwrap_or_else(Token::zero)
+    }
+
+    /// The lines of the entries matching the query, a page at a time.
+    pub(crate) fn query(
+        &self,
+        query: &HistoryQuery,
+        offset: usize,
+        limit: usize,
+    ) -> HistoryPage<StatementLine> {
+        // narrow down the candidates by the most selective index
+        let candidates: Box<dyn Iterator<Item = &StatementLine>> = if let Some(id) = query.credit_id
+        {
+            Box::new(
+                self.by_credit_id
+                    .get(&id)
+                    .map(|i| &self.lines[*i])
+                    .into_iter(),
+            )
+        } else if let Some(key) = query.counterparty {
+            let indices = self.by_counterparty.get(&key).map(Vec::as_slice);
+            Box::new(
+                indices
+                    .unwrap_or_default()
+                    .iter()
+                    .map(move |i| &self.lines[*i]),
+            )
+        } else if let Some(range) = query.debit_counters.clone() {
+            Box::new(
+                self.by_debit_counter
+                    .range(range)
+                    .flat_map(move |(counter, i)| {
+                        self.lines[*i..].iter().take_while(move |l| {
+                            l.entry.debit_id.map(|id| id.counter) == Some(*counter)
+                        })
+                    }),
+            )
+        } else {
+            Box::new(self.lines.iter())
+        };
+        let matches: Vec<_> = candidates.filter(|l| query.matches(&l.entry)).collect();
+        HistoryPage {
+            total: matches.len(),
+            items: matches
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn push(&mut self, entry: HistoryEntry) -> Result<()> {
+        let previous = self.balance();
+        let balance = match entry.kind {
+            EntryKind::Credit => previous
+                .checked_add(entry.amount)
+                .ok_or(Error::AdditionOverflow(previous, entry.amount))?,
+            EntryKind::Debit | EntryKind::Refund => entry
+                .amount
+                .checked_add(entry.fee)
+                .and_then(|debited| previous.checked_sub(debited))
+                .ok_or(Error::SubtractionOverflow(entry.amount, previous))?,
+            EntryKind::Balance => entry.amount,
+        };
+        if let Some(key) = entry.counterparty {
+            self.by_counterparty
+                .entry(key)
+                .or_default()
+                .push(entry.index);
+        }
+        if let Some(id) = entry.debit_id {
+            let _ = self
+                .by_debit_counter
+                .entry(id.counter)
+                .or_insert(entry.index);
+        }
+        let _ = self.by_credit_id.insert(entry.credit_id, entry.index);
+        self.lines.push(StatementLine { entry, balance });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EntryKind, HistoryIndex, HistoryQuery};
+    use crate::{batch_credit_id, BatchTransferAgreementProof, Error, Result};
+    use crdts::Dot;
+    use sn_data_types::{
+        Credit, CreditAgreementProof, Debit, Keypair, PublicKey, SignedCredit, SignedDebit,
+        Signing, Token, TransferAgreementProof,
+    };
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn queries_pages_of_statement() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let keypair = Keypair::new_ed25519(&mut rng);
+        let replicas = SecretKeySet::random(0, &mut rng).public_keys();
+        let alice: PublicKey = Keypair::new_ed25519(&mut rng).public_key();
+        let bob: PublicKey = Keypair::new_ed25519(&mut rng).public_key();
+        let signature = Signing::sign(&keypair, &"sig")?;
+
+        let debit = |counter, recipient, amount| -> Result<TransferAgreementProof> {
+            let debit = Debit {
+                id: Dot::new(keypair.public_key(), counter),
+                amount: Token::from_nano(amount + 1),
+            };
+            let credit = Credit {
+                id: debit.credit_id()?,
+                recipient,
+                amount: Token::from_nano(amount),
+                msg: format!("payment {}", counter),
+            };
+            Ok(TransferAgreementProof {
+                signed_debit: SignedDebit {
+                    debit,
+                    actor_signature: signature.clone(),
+                },
+                signed_credit: SignedCredit {
+                    credit,
+                    actor_signature: signature.clone(),
+                },
+                debit_sig: signature.clone(),
+                credit_sig: signature.clone(),
+                debiting_replicas_keys: replicas.clone(),
+            })
+        };
+        let credit = CreditAgreementProof {
+            signed_credit: SignedCredit {
+                credit: Credit {
+                    id: [7; 32],
+                    recipient: keypair.public_key(),
+                    amount: Token::from_nano(100),
+                    msg: "salary".to_string(),
+                },
+                actor_signature: signature.clone(),
+            },
+            debiting_replicas_sig: signature.clone(),
+            debiting_replicas_keys: replicas.clone(),
+        };
+
+        let mut index = HistoryIndex::default();
+        index.push_credit(&credit)?;
+        index.push_debit(&debit(0, alice, 10)?)?;
+        index.push_debit(&debit(1, bob, 20)?)?;
+        index.push_debit(&debit(2, alice, 30)?)?;
+        let batch = debit(3, bob, 5)?;
+        let signed_credits = vec![bob, alice]
+            .into_iter()
+            .enumerate()
+            .map(|(i, recipient)| {
+                Ok(SignedCredit {
+                    credit: Credit {
+                        id: batch_credit_id(&batch.id(), i)?,
+                        recipient,
+                        amount: Token::from_nano(2),
+                        msg: format!("share {}", i),
+                    },
+                    actor_signature: signature.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        index.push_batch_debit(&BatchTransferAgreementProof {
+            signed_debit: batch.signed_debit,
+            credit_sigs: vec![signature.clone(); signed_credits.len()],
+            signed_credits,
+            debit_sig: signature.clone(),
+            debiting_replicas_keys: replicas.clone(),
+        })?;
+
+        let all = index.query(&HistoryQuery::default(), 0, 10);
+        assert_eq!(all.total, 6);
+        let balances: Vec<_> = all.items.iter().map(|l| l.balance.as_nano()).collect();
+        assert_eq!(balances, vec![100, 89, 68, 37, 33, 31]);
+        assert_eq!(all.items[4].entry.fee, Token::from_nano(2));
+        assert_eq!(all.items[0].entry.kind, EntryKind::Credit);
+        assert_eq!(all.items[1].entry.fee, Token::from_nano(1));
+
+        let query = HistoryQuery {
+            counterparty: Some(alice),
+            ..Default::default()
+        };
+        let page = index.query(&query, 1, 1);
+        assert_eq!(page.total, 3);
+        assert_eq!(page.items[0].entry.msg, "payment 2");
+
+        let query = HistoryQuery {
+            min_amount: Some(Token::from_nano(15)),
+            debit_counters: Some(0..2),
+            ..Default::default()
+        };
+        let page = index.query(&query, 0, 10);
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].entry.counterparty, Some(bob));
+
+        let query = HistoryQuery {
+            debit_counters: Some(2..4),
+            ..Default::default()
+        };
+        let page = index.query(&query, 0, 10);
+        assert_eq!(page.total, 3);
+        assert_eq!(page.items[2].entry.msg, "share 1");
+
+        let query = HistoryQuery {
+            credit_id: Some([7; 32]),
+            ..Default::default()
+        };
+        assert_eq!(index.query(&query, 0, 10).items[0].entry.msg, "salary");
+
+        // the statement never goes below zero, but is reset to the synched balance
+        assert_eq!(
+            index.push_debit(&debit(4, bob, 40)?),
+            Err(Error::SubtractionOverflow(
+                Token::from_nano(40),
+                Token::from_nano(31)
+            ))
+        );
+        index.push_balance(Token::from_nano(31));
+        index.push_balance(Token::from_nano(50));
+        let mut refund = credit;
+        refund.signed_credit.credit.recipient = alice;
+        refund.signed_credit.credit.amount = Token::from_nano(5);
+        index.push_refund(&refund)?;
+        let all = index.query(&HistoryQuery::default(), 0, 10);
+        let kinds: Vec<_> = all.items[5..].iter().map(|l| l.entry.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![EntryKind::Debit, EntryKind::Balance, EntryKind::Refund]
+        );
+        assert_eq!(index.balance(), Token::from_nano(45));
+        Ok(())
+    }
+}
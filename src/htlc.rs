@@ -0,0 +1,227 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Hash-time-locked transfers, for atomic swaps between wallets of
+//! different sections. The credit of such a transfer is escrowed with
+//! a hash lock (see [CreditCondition::HashLock]). It is claimed by
+//! revealing the preimage of the hash before the deadline epoch, and
+//! otherwise refunded to the sender, the same way as any escrowed credit.
+//!
+//! In a swap, the party knowing the preimage locks its tokens with the
+//! later deadline, and the other party locks its tokens with the same hash,
+//! and an earlier deadline. The claim of the first party reveals the
+//! preimage, with which the other party then claims its tokens.
+//!
+//! A claim is signed by each of the Replicas of the wallet holding the credit,
+//! and a quorum of them makes the proof of the claim (see [htlc_claim_proof]),
+//! which the Actor of the wallet requires before unlocking the credit.
+//!
+//! [CreditCondition::HashLock]: crate::CreditCondition::HashLock
+
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{CreditAgreementProof, CreditId, PublicKey, Signature, SignatureShare};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Formatter};
+use threshold_crypto::PublicKeySet;
+use tiny_keccak::{Hasher, Sha3};
+
+/// The hash lock of the preimage.
+pub fn hash_lock(preimage: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3::v256();
+    let mut output = [0; 32];
+    hasher.update(preimage);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// The claim of a hash-time-locked credit, by the revealing
+/// of the preimage of its hash, as signed by the Replicas.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct HtlcClaim {
+    /// The wallet holding the credit.
+    pub wallet: PublicKey,
+    /// The id of the credit.
+    pub credit_id: CreditId,
+    /// The preimage of the hash lock.
+    pub preimage: Vec<u8>,
+}
+
+/// The Replica event raised when a hash-time-locked credit
+/// has been claimed, before its deadline.
+/// A quorum of these, from the Replicas of the wallet,
+/// makes the proof of the claim.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HtlcClaimed {
+    /// The claim of the credit.
+    pub claim: HtlcClaim,
+    /// Replica signature over the claim.
+    pub replica_sig: SignatureShare,
+    /// The PK Set of the Replicas
+    pub replicas: PublicKeySet,
+}
+
+impl Debug for HtlcClaimed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HtlcClaimed {{ claim: {:?}, replica_sig: {:?}, replicas: PkSet {{ public_key: {:?} }} }}",
+            self.claim,
+            self.replica_sig,
+            self.replicas.public_key()
+        )
+    }
+}
+
+/// The claim of a hash-time-locked credit,
+/// agreed by a quorum of the Replicas of the wallet holding it.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HtlcClaimProof {
+    /// The claim of the credit.
+    pub claim: HtlcClaim,
+    /// Quorum of Replica sigs over the claim.
+    pub replicas_sig: Signature,
+    /// PK of the Replicas.
+    pub replicas: PublicKeySet,
+}
+
+impl Debug for HtlcClaimProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HtlcClaimProof {{ claim: {:?}, replicas_sig: {:?}, replicas: PkSet {{ public_key: {:?} }} }}",
+            self.claim,
+            self.replicas_sig,
+            self.replicas.public_key()
+        )
+    }
+}
+
+/// The proof of a claim, once a quorum of the Replicas
+/// of the wallet holding the credit have signed it.
+/// Returns None until there is a quorum.
+///
+/// A share is counted only if it is valid for its claim, by the key share of its index,
+/// and only the shares of the same claim, by the same key set, are combined.
+pub fn htlc_claim_proof(claims: &[HtlcClaimed]) -> Result<Option<HtlcClaimProof>> {
+    let mut agreeing: Vec<(&HtlcClaimed, BTreeMap<_, _>)> = vec![];
+    for claimed in claims {
+        let share = &claimed.replica_sig;
+        let claim_bytes = bincode::serialize(&claimed.claim)
+            .map_err(|_| Error::Serialisation("Could not serialise claim".into()))?;
+        if !claimed
+            .replicas
+            .public_key_share(share.index)
+            .verify(&share.share, claim_bytes)
+        {
+            continue;
+        }
+        match agreeing
+            .iter_mut()
+            .find(|(c, _)| c.replicas == claimed.replicas && c.claim == claimed.claim)
+        {
+            Some((_, shares)) => {
+                let _ = shares.insert(share.index, &share.share);
+            }
+            None => agreeing.push((
+                claimed,
+                vec![(share.index, &share.share)].into_iter().collect(),
+            )),
+        }
+    }
+    let (claimed, shares) = match agreeing
+        .into_iter()
+        .find(|(c, shares)| shares.len() > c.replicas.threshold())
+    {
+        Some(quorum) => quorum,
+        None => return Ok(None),
+    };
+    let signature = claimed
+        .replicas
+        .combine_signatures(shares)
+        .map_err(|_| Error::CannotAggregate)?;
+    Ok(Some(HtlcClaimProof {
+        claim: claimed.claim.clone(),
+        replicas_sig: Signature::Bls(signature),
+        replicas: claimed.replicas.clone(),
+    }))
+}
+
+/// Raised when the Actor has received the proof of the claim
+/// of a hash-time-locked credit it was holding.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct CreditClaimed {
+    /// The id of the claimed credit.
+    pub credit_id: CreditId,
+    /// The proof of the claim, by our Replicas.
+    pub claim_proof: HtlcClaimProof,
+}
+
+/// Raised when the Actor has received the proof of the refund
+/// of an escrowed, or hash-time-locked, credit it was holding.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct CreditRefunded {
+    /// The id of the refunded credit.
+    pub credit_id: CreditId,
+    /// The proof of the refund credit, by our Replicas.
+    pub refund_proof: CreditAgreementProof,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{htlc_claim_proof, HtlcClaim, HtlcClaimed};
+    use crate::{Error, ReplicaSigning, ReplicaSigningImpl, Result};
+    use sn_data_types::{CreditId, Keypair, Signature};
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn claim_proof_counts_valid_shares_of_the_same_claim() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(1, &mut rng);
+        let wallet = Keypair::new_ed25519(&mut rng).public_key();
+        let credit_id: CreditId = rand::random();
+        let claim = |preimage: &[u8]| HtlcClaim {
+            wallet,
+            credit_id,
+            preimage: preimage.to_vec(),
+        };
+        let claimed = |claim: &HtlcClaim, index: usize| -> Result<HtlcClaimed> {
+            let signing = ReplicaSigningImpl::new(
+                sk_set.secret_key_share(index),
+                index,
+                sk_set.public_keys(),
+            );
+            Ok(HtlcClaimed {
+                claim: claim.clone(),
+                replica_sig: signing.sign_htlc_claim(claim)?,
+                replicas: sk_set.public_keys(),
+            })
+        };
+        let secret = claim(b"secret");
+
+        // a claim of another preimage comes first, and a share is of another index than its signer
+        let mut garbage = claimed(&secret, 0)?;
+        garbage.replica_sig.index = 1;
+        let claims = vec![claimed(&claim(b"guess"), 2)?, claimed(&secret, 0)?, garbage];
+        assert_eq!(htlc_claim_proof(&claims)?, None);
+
+        let claims = vec![
+            claimed(&claim(b"guess"), 2)?,
+            claimed(&secret, 0)?,
+            claimed(&secret, 1)?,
+        ];
+        let proof = htlc_claim_proof(&claims)?.ok_or(Error::CannotAggregate)?;
+        assert_eq!(proof.claim, secret);
+        let bytes = bincode::serialize(&secret).map_err(|e| Error::Serialisation(e.to_string()))?;
+        match proof.replicas_sig {
+            Signature::Bls(sig) => assert!(sk_set.public_keys().public_key().verify(&sig, bytes)),
+            _ => panic!("Should be a BLS signature."),
+        }
+        Ok(())
+    }
+}
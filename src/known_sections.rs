@@ -0,0 +1,57 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{Error, Result};
+use std::collections::HashSet;
+use std::mem::size_of;
+use threshold_crypto::{PublicKey, PublicKeySet};
+
+/// The knowledge of which keys are, or have been, section keys.
+/// Replicas consult it to make sure that the credits they
+/// receive were debited at the Replicas of an actual section.
+pub trait KnownSections {
+    /// Whether the key is, or has ever been, the key of a known section.
+    fn is_known(&self, key: &PublicKey) -> bool;
+}
+
+/// An impl of KnownSections, holding the section keys in memory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InMemoryKnownSections {
+    keys: HashSet<PublicKey>,
+}
+
+impl InMemoryKnownSections {
+    /// A new instance, knowing of the provided section keys.
+    pub fn new<I: IntoIterator<Item = PublicKey>>(keys: I) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    /// Adds a section key, returning false if it was already known.
+    pub fn add(&mut self, key: PublicKey) -> bool {
+        self.keys.insert(key)
+    }
+}
+
+impl KnownSections for InMemoryKnownSections {
+    fn is_known(&self, key: &PublicKey) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+/// The key of the key set of a proof received from the network.
+/// A key set decoded from a message can be empty, and asking
+/// such a set for its key panics, so it is rejected instead.
+pub(crate) fn section_key(key_set: &PublicKeySet) -> Result<PublicKey> {
+    // an empty set serialises to the zero length of its coefficients only
+    match bincode::serialize(key_set) {
+        Ok(bytes) if bytes.len() > size_of::<u64>() => Ok(key_set.public_key()),
+        _ => Err(Error::InvalidSignature),
+    }
+}
@@ -26,21 +26,86 @@
 )]
 
 mod actor;
+mod actor_snapshot;
+mod actor_store;
+mod batch;
+mod equivocation;
 mod error;
+mod escrow;
+mod event_log;
+mod fees;
+mod history;
+mod htlc;
+mod known_sections;
+mod multisig_actor;
+mod receipt;
+mod replica_signing;
+mod replica_snapshot;
+mod replica_store;
+#[cfg(any(test, feature = "simulator"))]
+mod simulator;
+mod spending_policy;
+mod subscribers;
+#[cfg(test)]
 mod test_utils;
+mod versioned;
 mod wallet;
+mod wallet_file;
 mod wallet_replica;
 
 pub use self::{
-    actor::Actor as TransferActor, error::Error, wallet::Wallet, wallet_replica::WalletReplica,
+    actor::Actor as TransferActor,
+    actor_snapshot::{ActorSnapshot, ACTOR_SNAPSHOT_VERSION},
+    actor_store::{ActorStore, InFlight},
+    batch::{
+        batch_credit_id, BatchTransferAgreementProof, BatchTransferInitiated,
+        BatchTransferRegistered, BatchTransferRegistrationSent, BatchTransferValidated,
+        BatchTransferValidationReceived, SignedBatchTransfer,
+    },
+    equivocation::EquivocationProof,
+    error::Error,
+    escrow::{
+        escrow_refund, refund_proof, CreditCondition, EscrowRefunded, EscrowRelease,
+        EscrowReleased, LockedCredit,
+    },
+    event_log::{EventLog, SyncPolicy},
+    fees::{fee_credit_id, FeePolicy},
+    history::{EntryKind, HistoryEntry, HistoryPage, HistoryQuery, StatementLine},
+    htlc::{
+        hash_lock, htlc_claim_proof, CreditClaimed, CreditRefunded, HtlcClaim, HtlcClaimProof,
+        HtlcClaimed,
+    },
+    known_sections::{InMemoryKnownSections, KnownSections},
+    multisig_actor::{MultisigActor, MultisigTransfer, TransferShareAccumulated},
+    receipt::{Receipt, RECEIPT_VERSION},
+    replica_signing::{ReplicaSigning, ReplicaSigningImpl},
+    replica_snapshot::{WalletReplicaSnapshot, REPLICA_SNAPSHOT_VERSION},
+    replica_store::TransferReplicaStore,
+    spending_policy::{
+        MultisigBatchValidated, MultisigTransferValidated, SpendingPolicy, SpendingPolicyDeclared,
+        SpendingTier,
+    },
+    subscribers::Notification,
+    wallet::Wallet,
+    wallet_file::{KdfParams, WalletFile, WALLET_FILE_VERSION},
+    wallet_replica::WalletReplica,
+};
+
+#[cfg(feature = "simulator")]
+pub use self::simulator::{
+    DeliveryOrder, ElderBehaviour, ElderId, Message, NetworkFaults, SimElder, SimSection, Simulator,
 };
 
 use serde::{Deserialize, Serialize};
 use sn_data_types::{
-    ActorHistory, CreditId, DebitId, PublicKey, SignedCredit, SignedDebit, Token,
-    TransferAgreementProof, TransferValidated,
+    ActorHistory, Credit, CreditId, DebitId, PublicKey, SectionElders, Signature, SignedCredit,
+    SignedDebit, Token, TransferAgreementProof, TransferValidated,
 };
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    fmt::{self, Debug, Formatter},
+};
+use threshold_crypto::PublicKeySet;
 
 type Result<T> = std::result::Result<T, Error>;
 type Outcome<T> = Result<Option<T>>;
@@ -81,12 +146,34 @@ pub enum ActorEvent {
     /// quorum of validations, and produced a RegisterTransfer cmd
     /// for sending to Replicas.
     TransferRegistrationSent(TransferRegistrationSent),
+    /// Raised when a request to create
+    /// a batch transfer validation cmd for Replicas,
+    /// has been successful (valid on local state).
+    BatchTransferInitiated(BatchTransferInitiated),
+    /// Raised when an Actor receives a Replica batch transfer validation.
+    BatchTransferValidationReceived(BatchTransferValidationReceived),
+    /// Raised when the Actor has accumulated a
+    /// quorum of validations of a batch, and produced a
+    /// RegisterBatchTransfer cmd for sending to Replicas.
+    BatchTransferRegistrationSent(BatchTransferRegistrationSent),
+    /// Raised when the Actor has received a
+    /// signed update of the key chain of its Replicas.
+    ReplicasUpdated(ReplicasUpdated),
+    /// Raised when the Actor has abandoned
+    /// a transfer that was never validated.
+    TransferAbandoned(TransferAbandoned),
     /// Raised when the Actor has received
     /// unknown credits on querying Replicas.
     TransfersSynched(TransfersSynched),
     /// Raised when the Actor has received
     /// unknown credits on querying Replicas.
     StateSynched(StateSynched),
+    /// Raised when the claim of a hash-time-locked
+    /// credit to the Actor has been agreed by its Replicas.
+    CreditClaimed(CreditClaimed),
+    /// Raised when an escrowed, or hash-time-locked,
+    /// credit to the Actor has been refunded.
+    CreditRefunded(CreditRefunded),
 }
 
 /// Raised when the Actor has received
@@ -108,9 +195,9 @@ pub struct StateSynched {
 /// the propagation of them from a remote group of Replicas,
 /// or unknown debits that its Replicas were holding
 /// upon the registration of them from another
-/// instance of the same Actor.
+/// instance of the same Actor, including batch debits.
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-pub struct TransfersSynched(ActorHistory);
+pub struct TransfersSynched(ActorHistory, Vec<BatchTransferAgreementProof>);
 
 /// This event is raised by the Actor after having
 /// successfully created a transfer cmd to send to the
@@ -149,32 +236,142 @@ pub struct TransferRegistrationSent {
     transfer_proof: TransferAgreementProof,
 }
 
-#[allow(unused)]
+// ------------------------------------------------------------
+//                      Replica
+// ------------------------------------------------------------
+
+/// Events raised by the Replica, other than the [ReplicaEvent]s
+/// of the network data types.
+///
+/// [ReplicaEvent]: sn_data_types::ReplicaEvent
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub enum WalletReplicaEvent {
+    /// The event raised when
+    /// a batch transfer has been validated.
+    BatchTransferValidated(BatchTransferValidated),
+    /// The event raised when
+    /// a batch transfer has been registered.
+    BatchTransferRegistered(BatchTransferRegistered),
+    /// The event raised when the fee paid by
+    /// a registered transfer has been credited.
+    FeeCredited(FeeCredited),
+    /// The event raised when the Actor has been found
+    /// to sign two different debits with the same id.
+    EquivocationDetected(EquivocationDetected),
+    /// The event raised when an escrowed
+    /// credit has been released by its arbiter.
+    EscrowReleased(EscrowReleased),
+    /// The event raised when an escrowed
+    /// credit has been refunded.
+    EscrowRefunded(EscrowRefunded),
+    /// The event raised when a hash-time-locked
+    /// credit has been claimed.
+    HtlcClaimed(HtlcClaimed),
+    /// The event raised when the owners of a
+    /// multisig wallet have declared its spending policy.
+    SpendingPolicyDeclared(SpendingPolicyDeclared),
+    /// The event raised when a transfer of
+    /// a multisig wallet has been validated.
+    MultisigTransferValidated(MultisigTransferValidated),
+    /// The event raised when a batch transfer of
+    /// a multisig wallet has been validated.
+    MultisigBatchValidated(MultisigBatchValidated),
+}
+
+/// The Replica event raised when the fee paid by a registered
+/// transfer has been credited to the fee wallet of the section.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct FeeCredited {
+    /// The credit of the fee, with an id derived from the id of the debit.
+    pub credit: Credit,
+}
+
+/// The Replica event raised when an Actor has equivocated.
+/// The wallet is frozen, and no further debits are validated.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct EquivocationDetected {
+    /// The evidence, to be shared with the other Replicas.
+    pub proof: EquivocationProof,
+}
+
+impl EquivocationDetected {
+    /// Get the sender
+    pub fn sender(&self) -> PublicKey {
+        self.proof.sender()
+    }
+}
+
+/// A new key set of a section, signed by the key set preceding it in the section chain.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SectionKeyLink {
+    /// The new key set.
+    pub key_set: PublicKeySet,
+    /// Signature by the preceding key set, over the public key of the new key set.
+    pub signature: Signature,
+}
+
+impl Debug for SectionKeyLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SectionKeyLink {{ key_set: PkSet {{ public_key: {:?} }}, signature: {:?} }}",
+            self.key_set.public_key(),
+            self.signature
+        )
+    }
+}
+
+/// Raised when the Actor has verified an update of
+/// the key chain of its Replicas.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReplicasUpdated {
+    /// The new Replicas.
+    replicas: SectionElders,
+    /// The verified key sets, in order, ending with that of the new Replicas.
+    chain: Vec<PublicKeySet>,
+}
+
+impl Debug for ReplicasUpdated {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ReplicasUpdated {{ replicas: {:?}, chain: {:?} }}",
+            self.replicas,
+            self.chain
+                .iter()
+                .map(|k| k.public_key())
+                .collect::<Vec<_>>()
+        )
+    }
+}
+
+/// Raised when the Actor has abandoned a transfer
+/// that was never validated by its Replicas.
+/// Its debit counter will be used by the next transfer.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct TransferAbandoned {
+    /// The id of the abandoned debit.
+    pub id: DebitId,
+}
+
+#[cfg(test)]
 mod test {
+    use crate::simulator::{DeliveryOrder, Message, Simulator};
     use crate::{
-        actor::Actor, test_utils, test_utils::*, wallet, wallet_replica::WalletReplica, ActorEvent,
-        Error, Result, TransferInitiated, Wallet,
+        actor::Actor, test_utils::*, ActorEvent, Error, FeePolicy, HistoryQuery,
+        InMemoryKnownSections, Notification, Receipt, ReplicaSigningImpl, Result,
+        TransferReplicaStore, WalletReplicaEvent, RECEIPT_VERSION,
     };
     use crdts::{
-        quickcheck::{quickcheck, TestResult},
+        quickcheck::{Arbitrary, Gen, QuickCheck, TestResult},
         Dot,
     };
+    use rand::Rng;
     use sn_data_types::{
-        ActorHistory, Credit, CreditAgreementProof, CreditId, Debit, Keypair, OwnerType, PublicKey,
-        ReplicaEvent, SectionElders, SignatureShare, SignedCredit, SignedDebit, SignedTransfer,
-        Token, Transfer, TransferAgreementProof,
+        ActorHistory, Keypair, PublicKey, ReplicaEvent, Token, TransferPropagated,
     };
-    use std::collections::{HashMap, HashSet};
-    use std::sync::Arc;
-    use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKey, SecretKeySet, SecretKeyShare};
-
-    macro_rules! hashmap {
-        ($( $key: expr => $val: expr ),*) => {{
-             let mut map = ::std::collections::HashMap::new();
-             $( let _ = map.insert($key, $val); )*
-             map
-        }}
-    }
+    use threshold_crypto::SecretKey;
 
     // ------------------------------------------------------------------------
     // ------------------------ Basic Transfer --------------------------------
@@ -185,59 +382,391 @@ mod test {
         let _ = transfer_between_actors(100, 10, 3);
     }
 
-    // #[allow(trivial_casts)]
-    // #[test]
-    // fn quickcheck_basic_transfer() {
-    //     quickcheck(transfer_between_actors as fn(u64, u64, u8, u8, u8, u8) -> TestResult);
-    // }
+    // ------------------------------------------------------------------------
+    // ------------------------ Conservation of Value -------------------------
+    // ------------------------------------------------------------------------
+
+    #[allow(trivial_casts)]
+    #[test]
+    fn quickcheck_conservation_of_value() {
+        QuickCheck::new()
+            .tests(10)
+            .quickcheck(conservation_of_value as fn(TransferScenario) -> TestResult);
+    }
 
     #[test]
     fn synching() -> Result<()> {
-        let section_count = 1;
-        let replicas_per_section = 1;
-        let section_configs = vec![vec![]];
-        let Network {
-            genesis_credit,
-            mut sections,
+        let SingleElder {
             mut actors,
-        } = setup_new_network(section_count, replicas_per_section, section_configs)?;
+            mut store,
+            ..
+        } = setup_single_elder(&[0])?;
+        let mut actor = actors.remove(0);
+
+        let genesis_credit = get_random_genesis(u32::MAX as u64, actor.id())?;
+        let known_sections =
+            InMemoryKnownSections::new(vec![genesis_credit.debiting_replicas_keys.public_key()]);
+        let _ = store
+            .genesis(&genesis_credit, &known_sections)?
+            .ok_or(Error::GenesisFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis_credit.clone(),
+        }))?;
 
-        let genesis_key = genesis_credit.recipient();
-        let genesis_elder = &mut sections.remove(0).elders.remove(0);
-        let wallet_replica = match genesis_elder.replicas.get_mut(&genesis_key) {
-            Some(w) => w,
-            None => panic!("Failed the test; no such wallet."),
+        let synched_event = actor
+            .from_history(ActorHistory {
+                credits: vec![genesis_credit.clone()],
+                debits: vec![],
+            })?
+            .ok_or(Error::NoActorHistory)?;
+        actor.apply(ActorEvent::TransfersSynched(synched_event))?;
+
+        let balance = store.balance(&actor.id())?;
+        assert_eq!(genesis_credit.amount(), balance);
+        assert_eq!(balance, actor.balance());
+        Ok(())
+    }
+
+    #[test]
+    fn resent_transfer_is_validated_again() -> Result<()> {
+        let SingleElder {
+            mut actors,
+            mut store,
+            signing,
+            ..
+        } = setup_single_elder(&[100, 10])?;
+        let mut sender = actors.remove(0);
+        let recipient = actors.remove(0).id();
+
+        let transfer = sender
+            .transfer(sender.balance(), recipient, "asdf".to_string())?
+            .ok_or(Error::TransferCreationFailed)?;
+        sender.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+        // the same transfer, to be sent again
+        let retried = sender
+            .retry(transfer.id())?
+            .ok_or(Error::PendingTransferNotFound)?;
+
+        let validation = store
+            .validate_and_sign(&transfer.signed_debit, &transfer.signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply(ReplicaEvent::TransferValidated(validation.clone()))?;
+
+        let revalidation = store
+            .validate_and_sign(&retried.signed_debit, &retried.signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        assert_eq!(validation, revalidation);
+        store.apply(ReplicaEvent::TransferValidated(revalidation))?;
+        Ok(())
+    }
+
+    #[test]
+    fn batch_transfer_pays_all_recipients() -> Result<()> {
+        let SingleElder {
+            mut actors,
+            mut store,
+            signing,
+            known_sections,
+            ..
+        } = setup_single_elder(&[100, 10, 10])?;
+        let mut sender = actors.remove(0);
+        let first = actors.remove(0).id();
+        let second = actors.remove(0).id();
+        let mut synched = sender.clone();
+
+        // 1. Init batch at Sender Actor.
+        let batch = sender
+            .transfer_batch(vec![
+                (Token::from_nano(3), first, "first".to_string()),
+                (Token::from_nano(4), second, "second".to_string()),
+            ])?
+            .ok_or(Error::TransferCreationFailed)?;
+        sender.apply(ActorEvent::BatchTransferInitiated(batch.clone()))?;
+        assert_eq!(batch.signed_debit.amount(), Token::from_nano(7));
+
+        // 2. Validate at Sender Replicas.
+        let sender_id = sender.id();
+        let validation = store
+            .validate_and_sign_batch(&batch.signed_debit, &batch.signed_credits, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply_wallet_event(WalletReplicaEvent::BatchTransferValidated(
+            validation.clone(),
+        ))?;
+
+        let received = sender
+            .receive_batch(validation)?
+            .ok_or(Error::ReceiveValidationFailed)?;
+        let proof = received
+            .proof
+            .clone()
+            .ok_or(Error::SenderValidationFailed)?;
+        sender.apply(ActorEvent::BatchTransferValidationReceived(received))?;
+
+        // 3. Register at Sender Replicas.
+        let registration = sender
+            .register_batch(proof.clone())?
+            .ok_or(Error::RegisterProofFailed)?;
+        sender.apply(ActorEvent::BatchTransferRegistrationSent(registration))?;
+        let registered = store
+            .register_batch(&proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        store.apply_wallet_event(WalletReplicaEvent::BatchTransferRegistered(registered))?;
+        assert_eq!(store.balance(&sender_id)?, Token::from_nano(93));
+        assert_eq!(sender.balance(), Token::from_nano(93));
+
+        // The batch is in the history of the Sender, with an entry and a receipt per credit.
+        assert_eq!(sender.batch_history(), std::slice::from_ref(&proof));
+        let query = HistoryQuery {
+            debit_counters: Some(0..1),
+            ..Default::default()
         };
-        let _ = wallet_replica
-            .genesis(&genesis_credit)?
-            .ok_or(Error::GenesisFailed)?;
+        let entries = sender.query_history(&query, 0, 10).items;
+        let amounts: Vec<_> = entries.iter().map(|e| (e.counterparty, e.amount)).collect();
+        assert_eq!(
+            amounts,
+            vec![
+                (Some(first), Token::from_nano(3)),
+                (Some(second), Token::from_nano(4))
+            ]
+        );
+        let receipts = sender.batch_receipts(&proof.id());
+        assert_eq!(receipts.len(), 2);
+        for receipt in &receipts {
+            receipt.verify(&known_sections)?;
+            assert_eq!(&Receipt::from_bytes(&receipt.to_bytes()?)?, receipt);
+        }
+        assert_eq!(receipts[1].recipient(), second);
+
+        // Another instance of the Sender synchs the batch from the history.
+        let event = synched
+            .from_history_with_batches(sender.history(), sender.batch_history().to_vec())?
+            .ok_or(Error::NoActorHistory)?;
+        synched.apply(ActorEvent::TransfersSynched(event))?;
+        assert_eq!(synched.balance(), sender.balance());
+        assert_eq!(synched.statement(0, 10), sender.statement(0, 10));
+        let statement = sender.statement(0, 10).items;
+        assert_eq!(
+            statement.last().map(|line| line.balance),
+            Some(sender.balance())
+        );
 
-        let event = ReplicaEvent::TransferPropagated(sn_data_types::TransferPropagated {
-            credit_proof: genesis_credit.clone(),
-        });
-        wallet_replica.apply(event)?;
-
-        println!("Finding genesis actor by id: {}", genesis_key);
-        let mut actor_balance = None;
-        for actor in actors.iter_mut() {
-            println!("Actor id: {}", actor.actor.id());
-            if actor.actor.id() == genesis_key {
-                println!("Found actor!");
-                if let Some(synched_event) = actor.actor.from_history(ActorHistory {
-                    credits: vec![genesis_credit.clone()],
-                    debits: vec![],
-                })? {
-                    actor
-                        .actor
-                        .apply(ActorEvent::TransfersSynched(synched_event))?;
-                    actor_balance = Some(actor.actor.balance());
-                }
-                break;
-            }
+        // 4. Propagate each credit to its Recipient Replicas.
+        for credit_proof in proof.credit_proofs() {
+            store
+                .receive_propagated(&credit_proof, &known_sections)?
+                .ok_or(Error::ReceivePropagationFailed)?;
+            store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+                credit_proof,
+            }))?;
         }
-        let balance = wallet_replica.balance();
-        assert_eq!(genesis_credit.amount(), balance);
-        assert_eq!(Some(balance), actor_balance);
+        assert_eq!(store.balance(&first)?, Token::from_nano(13));
+        assert_eq!(store.balance(&second)?, Token::from_nano(14));
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_fee_is_credited_to_fee_wallet() -> Result<()> {
+        let SingleElder {
+            mut actors,
+            mut store,
+            signing,
+            known_sections,
+            ..
+        } = setup_single_elder(&[100, 10, 10])?;
+        let mut sender = actors.remove(0);
+        let recipient = actors.remove(0).id();
+        let fee_wallet = actors.remove(0).id();
+        let fee_policy = FeePolicy::Flat(Token::from_nano(1));
+        store.set_fees(fee_policy, fee_wallet);
+
+        // A debit without the fee is rejected by the Replicas.
+        let underpaid = sender
+            .transfer(Token::from_nano(3), recipient, "asdf".to_string())?
+            .ok_or(Error::TransferCreationFailed)?;
+        match store.validate_and_sign(&underpaid.signed_debit, &underpaid.signed_credit, &signing) {
+            Err(Error::InsufficientFee) => (),
+            _ => panic!("Should not validate a debit without the fee."),
+        }
+
+        // The Actor includes the fee in the debit.
+        sender.set_fee_policy(fee_policy);
+        let transfer = sender
+            .transfer(Token::from_nano(3), recipient, "asdf".to_string())?
+            .ok_or(Error::TransferCreationFailed)?;
+        assert_eq!(transfer.signed_debit.amount(), Token::from_nano(4));
+        assert_eq!(transfer.signed_credit.amount(), Token::from_nano(3));
+        sender.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+        let validation = store
+            .validate_and_sign(&transfer.signed_debit, &transfer.signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply(ReplicaEvent::TransferValidated(validation.clone()))?;
+        let proof = sender
+            .receive(validation)?
+            .ok_or(Error::ReceiveValidationFailed)?
+            .proof
+            .ok_or(Error::SenderValidationFailed)?;
+        let registered = store.register(&proof)?.ok_or(Error::RegisterProofFailed)?;
+        store.apply(ReplicaEvent::TransferRegistered(registered))?;
+        assert_eq!(store.balance(&sender.id())?, Token::from_nano(96));
+
+        // The fee is credited, once, to the fee wallet.
+        let fee_credited = store
+            .receive_fee(&proof, &known_sections)?
+            .ok_or(Error::ReceivePropagationFailed)?;
+        assert_eq!(fee_credited.credit.amount, Token::from_nano(1));
+        assert_eq!(fee_credited.credit.recipient, fee_wallet);
+        store.apply_wallet_event(WalletReplicaEvent::FeeCredited(fee_credited))?;
+        assert_eq!(store.balance(&fee_wallet)?, Token::from_nano(11));
+        assert!(store.receive_fee(&proof, &known_sections)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn receipt_of_registered_transfer_verifies_offline() -> Result<()> {
+        let SingleElder {
+            mut actors,
+            store,
+            signing,
+            section_key,
+            ..
+        } = setup_single_elder(&[100, 10])?;
+        let mut sender = actors.remove(0);
+        let recipient = actors.remove(0).id();
+        let sender_id = sender.id();
+
+        let transfer = sender
+            .transfer(Token::from_nano(3), recipient, "for the bike".to_string())?
+            .ok_or(Error::TransferCreationFailed)?;
+        sender.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+        let validation = store
+            .validate_and_sign(&transfer.signed_debit, &transfer.signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        let proof = sender
+            .receive(validation)?
+            .ok_or(Error::ReceiveValidationFailed)?
+            .proof
+            .ok_or(Error::SenderValidationFailed)?;
+        let id = proof.id();
+        assert!(sender.receipt(&id).is_none());
+        let registration = sender.register(proof)?.ok_or(Error::RegisterProofFailed)?;
+        sender.apply(ActorEvent::TransferRegistrationSent(registration))?;
+
+        let receipt = sender.receipt(&id).ok_or(Error::RegisterProofFailed)?;
+        assert_eq!(receipt.debit_id(), id);
+        assert_eq!(receipt.sender(), sender_id);
+        assert_eq!(receipt.recipient(), recipient);
+        assert_eq!(receipt.amount(), Token::from_nano(3));
+        assert_eq!(receipt.msg(), "for the bike");
+
+        // shared as text, and verified by the recipient knowing nothing but the section key
+        let shared = Receipt::decode(&receipt.encode()?)?;
+        assert_eq!(shared, receipt);
+        let mut bytes = receipt.to_bytes()?;
+        assert_eq!(Receipt::from_bytes(&bytes)?, receipt);
+        bytes[..2].copy_from_slice(&(RECEIPT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Receipt::from_bytes(&bytes),
+            Err(Error::UnsupportedVersion(RECEIPT_VERSION + 1))
+        );
+        let known_sections = InMemoryKnownSections::new(vec![section_key]);
+        shared.verify(&known_sections)?;
+        assert_eq!(
+            shared.verify(&InMemoryKnownSections::default()),
+            Err(Error::SectionKeyNeverExisted)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subscribers_are_notified_of_wallet_changes() -> Result<()> {
+        let SingleElder {
+            mut actors,
+            store,
+            signing,
+            ..
+        } = setup_single_elder(&[100, 10])?;
+        let mut sender = actors.remove(0);
+        let recipient = actors.remove(0).id();
+        let sender_id = sender.id();
+        let mut wallet_replica = store
+            .get(&sender_id)
+            .cloned()
+            .ok_or(Error::WalletNotFound(sender_id))?;
+        let actor_notifications = sender.subscribe();
+        let replica_notifications = wallet_replica.subscribe();
+
+        let transfer = sender
+            .transfer(Token::from_nano(3), recipient, "asdf".to_string())?
+            .ok_or(Error::TransferCreationFailed)?;
+        let id = transfer.id();
+        sender.apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+
+        // a debit not signed by the sender is rejected
+        let mut forged_debit = transfer.signed_debit.clone();
+        forged_debit.debit.amount = Token::from_nano(1);
+        let rejected = wallet_replica.validate(&forged_debit, &transfer.signed_credit);
+        assert_eq!(rejected, Err(Error::InvalidSignature));
+        assert_eq!(
+            replica_notifications.try_iter().collect::<Vec<_>>(),
+            vec![Notification::ValidationRejected {
+                wallet: sender_id,
+                debit_id: id,
+                error: Error::InvalidSignature,
+            }]
+        );
+        // the rejection is passed on to the Actor, which only notifies it for its own pending debits
+        sender.receive_rejection(forged_debit.id(), Error::InvalidSignature);
+        sender.receive_rejection(Dot::new(get_random_pk(), 0), Error::InvalidSignature);
+        assert_eq!(
+            actor_notifications.try_iter().collect::<Vec<_>>(),
+            vec![Notification::ValidationRejected {
+                wallet: sender_id,
+                debit_id: id,
+                error: Error::InvalidSignature,
+            }]
+        );
+
+        let validation = wallet_replica
+            .validate_and_sign(&transfer.signed_debit, &transfer.signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        // a validation the Actor can't verify is not a rejection by the Replicas
+        let mut tampered = validation.clone();
+        tampered.replica_debit_sig = tampered.replica_credit_sig.clone();
+        assert_eq!(sender.receive(tampered), Err(Error::InvalidSignature));
+        assert!(actor_notifications.try_iter().next().is_none());
+        let proof = sender
+            .receive(validation)?
+            .ok_or(Error::ReceiveValidationFailed)?
+            .proof
+            .ok_or(Error::SenderValidationFailed)?;
+        let registration = sender
+            .register(proof.clone())?
+            .ok_or(Error::RegisterProofFailed)?;
+        sender.apply(ActorEvent::TransferRegistrationSent(registration))?;
+        let registered = wallet_replica
+            .register(&proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        wallet_replica.apply(ReplicaEvent::TransferRegistered(registered))?;
+
+        let completed = vec![
+            Notification::DebitCompleted {
+                wallet: sender_id,
+                debit_id: id,
+                amount: Token::from_nano(3),
+            },
+            Notification::BalanceChanged {
+                wallet: sender_id,
+                balance: Token::from_nano(97),
+            },
+        ];
+        assert_eq!(
+            actor_notifications.try_iter().collect::<Vec<_>>(),
+            completed
+        );
+        assert_eq!(
+            replica_notifications.try_iter().collect::<Vec<_>>(),
+            completed
+        );
         Ok(())
     }
 
@@ -247,63 +776,41 @@ mod test {
 
     #[test]
     fn can_start_with_genesis() -> Result<()> {
-        let section_count = 1;
-        let replicas_per_section = 1;
-        let section_configs = vec![vec![u32::MAX as u64]];
-        let Network {
-            genesis_credit,
-            mut sections,
-            ..
-        } = setup_new_network(section_count, replicas_per_section, section_configs)?;
+        let SingleElder { mut store, .. } = setup_single_elder(&[])?;
 
+        let genesis_credit = get_random_genesis(u32::MAX as u64, get_random_pk())?;
         let genesis_key = genesis_credit.recipient();
-        let genesis_elder = &mut sections.remove(0).elders.remove(0);
-        let wallet_replica = match genesis_elder.replicas.get_mut(&genesis_key) {
-            Some(w) => w,
-            None => panic!("Failed the test; no such wallet."),
-        };
-        let _ = wallet_replica
-            .genesis(&genesis_credit)?
+        let known_sections =
+            InMemoryKnownSections::new(vec![genesis_credit.debiting_replicas_keys.public_key()]);
+        let _ = store
+            .genesis(&genesis_credit, &known_sections)?
             .ok_or(Error::GenesisFailed)?;
 
-        wallet_replica.apply(ReplicaEvent::TransferPropagated(
-            sn_data_types::TransferPropagated {
-                credit_proof: genesis_credit.clone(),
-            },
-        ))?;
-        let balance = wallet_replica.balance();
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis_credit.clone(),
+        }))?;
+        let balance = store.balance(&genesis_key)?;
         assert_eq!(genesis_credit.amount(), balance);
         Ok(())
     }
 
     #[test]
     fn genesis_can_only_be_the_first() -> Result<()> {
-        let section_count = 1;
-        let replica_count = 1;
-        let section_configs = vec![vec![0]];
+        let SingleElder { mut store, .. } = setup_single_elder(&[])?;
 
-        let Network {
-            genesis_credit,
-            mut sections,
-            ..
-        } = setup_new_network(section_count, replica_count, section_configs)?;
-        let genesis_elder = &mut sections.remove(0).elders.remove(0);
-        let wallet_replica = match genesis_elder.replicas.get_mut(&genesis_credit.recipient()) {
-            Some(w) => w,
-            None => panic!("Failed the test; no such wallet."),
-        };
-        let _ = wallet_replica
-            .genesis(&genesis_credit)?
+        let genesis_credit = get_random_genesis(u32::MAX as u64, get_random_pk())?;
+        let known_sections =
+            InMemoryKnownSections::new(vec![genesis_credit.debiting_replicas_keys.public_key()]);
+        let _ = store
+            .genesis(&genesis_credit, &known_sections)?
             .ok_or(Error::GenesisFailed)?;
 
-        wallet_replica.apply(ReplicaEvent::TransferPropagated(
-            sn_data_types::TransferPropagated {
-                credit_proof: genesis_credit.clone(),
-            },
-        ))?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis_credit.clone(),
+        }))?;
 
         // try genesis again..
-        let result = wallet_replica.genesis(&genesis_credit);
+        let result = store.genesis(&genesis_credit, &known_sections);
         match result {
             Ok(_) => panic!("Should not be able to genesis again."),
             Err(e) => assert_eq!(e, Error::InvalidOperation),
@@ -311,6 +818,140 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn genesis_from_unknown_section_is_rejected() -> Result<()> {
+        let SingleElder { store, .. } = setup_single_elder(&[])?;
+
+        let genesis_credit = get_random_genesis(u32::MAX as u64, get_random_pk())?;
+        let known_sections = InMemoryKnownSections::new(vec![SecretKey::random().public_key()]);
+
+        let result = store.genesis(&genesis_credit, &known_sections);
+        match result {
+            Ok(_) => panic!("Should not accept a credit from an unknown section."),
+            Err(e) => assert_eq!(e, Error::SectionKeyNeverExisted),
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+    // ------------------------ Conservation of Value Body --------------------
+    // ------------------------------------------------------------------------
+
+    /// Clients with random balances in random sections,
+    /// and a random sequence of transfers between them.
+    #[derive(Clone, Debug)]
+    struct TransferScenario {
+        seed: u64,
+        sections: usize,
+        elders_per_section: usize,
+        /// The section and the initial balance of each client.
+        clients: Vec<(usize, u64)>,
+        /// The sender, the recipient, and the amount of each transfer.
+        transfers: Vec<(usize, usize, u64)>,
+    }
+
+    impl Arbitrary for TransferScenario {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let sections = g.gen_range(1, 4);
+            let client_count = g.gen_range(2, 6);
+            let clients = (0..client_count)
+                .map(|_| (g.gen_range(0, sections), g.gen_range(0, 1_000)))
+                .collect();
+            let transfers = (0..g.gen_range(1, 7))
+                .map(|_| {
+                    (
+                        g.gen_range(0, client_count),
+                        g.gen_range(0, client_count),
+                        g.gen_range(0, 600),
+                    )
+                })
+                .collect();
+            Self {
+                seed: g.gen(),
+                sections,
+                elders_per_section: g.gen_range(1, 3),
+                clients,
+                transfers,
+            }
+        }
+    }
+
+    fn conservation_of_value(scenario: TransferScenario) -> TestResult {
+        match check_conservation_of_value(&scenario) {
+            Ok(()) => TestResult::passed(),
+            Err(e) => TestResult::error(format!("{:?}: {}", scenario, e)),
+        }
+    }
+
+    /// Runs the transfers of the scenario one after another, and checks after each that
+    /// the total supply is what was minted at every Elder, that no balance goes below zero,
+    /// and that the debit versions only increase.
+    /// Finally, an Actor synched from the history of each wallet has the same balance
+    /// and debit version as the Actor which made the transfers, and as the Replicas.
+    fn check_conservation_of_value(scenario: &TransferScenario) -> Result<()> {
+        let mut network = Simulator::new(
+            scenario.seed,
+            scenario.sections,
+            scenario.elders_per_section,
+        )?;
+        network.set_delivery_order(DeliveryOrder::Random);
+        let mut keys = vec![];
+        // the expected balance and debit version of each client
+        let mut expected = vec![];
+        for (section, balance) in &scenario.clients {
+            keys.push(network.add_client(*section, Token::from_nano(*balance))?);
+            expected.push((*balance, 0));
+        }
+        let genesis = network.minted();
+
+        for (sender, recipient, amount) in &scenario.transfers {
+            let (sender, recipient, amount) = (*sender, *recipient, *amount);
+            let result = network.transfer(&keys[sender], keys[recipient], Token::from_nano(amount));
+            // a transfer which would take a balance below zero is rejected by the Actor
+            if amount == 0 || sender == recipient || amount > expected[sender].0 {
+                assert!(result.is_err());
+                continue;
+            }
+            let _ = result?;
+            let _ = network.run();
+            network.synch(&keys[recipient])?;
+            expected[sender].0 -= amount;
+            expected[sender].1 += 1;
+            expected[recipient].0 += amount;
+
+            for elder in 0..scenario.elders_per_section {
+                assert_eq!(network.supply_at(elder)?, Some(genesis));
+            }
+            for (key, (balance, debit_version)) in keys.iter().zip(&expected) {
+                let actor = network.actor(key).ok_or(Error::WalletNotFound(*key))?;
+                assert_eq!(actor.balance(), Token::from_nano(*balance));
+                assert_eq!(actor.snapshot().debit_version, *debit_version);
+                for balance_at_elder in network.replica_balances(key)? {
+                    assert_eq!(balance_at_elder, Token::from_nano(*balance));
+                }
+            }
+        }
+        assert_eq!(
+            expected.iter().map(|(balance, _)| balance).sum::<u64>(),
+            genesis.as_nano()
+        );
+
+        for key in &keys {
+            let actor = network.actor(key).ok_or(Error::WalletNotFound(*key))?;
+            let history = network.history(key).ok_or(Error::WalletNotFound(*key))?;
+            let mut synched = Actor::new(actor.signing().clone(), actor.replicas());
+            if let Some(event) = synched.from_history(history)? {
+                synched.apply(ActorEvent::TransfersSynched(event))?;
+            }
+            assert_eq!(synched.balance(), actor.balance());
+            assert_eq!(
+                synched.snapshot().debit_version,
+                actor.snapshot().debit_version
+            );
+        }
+        Ok(())
+    }
+
     // ------------------------------------------------------------------------
     // ------------------------ Basic Transfer Body ---------------------------
     // ------------------------------------------------------------------------
@@ -337,191 +978,48 @@ mod test {
             return Ok(None);
         }
 
-        // --- Arrange ---
-        let section_count = 2;
-        let sender_index = 0;
-        let recipient_index = 1;
-        let recipient_final = sender_balance + recipient_balance;
-        let section_configs = vec![vec![sender_balance], vec![recipient_balance]];
-        let Network {
-            mut actors,
-            mut sections,
-            ..
-        } = setup_new_network(section_count, replica_count, section_configs)?;
-        let mut sender_section = sections.remove(0);
-        let mut recipient_section = sections.remove(0);
-        let mut sender = actors.remove(0);
-        let mut recipient = actors.remove(0);
-
-        // --- Act ---
-        // 1. Init transfer at Sender Actor.
-        let transfer = init_transfer(&mut sender, recipient.actor.id())?;
-        // 2. Validate at Sender Replicas.
-        let debit_proof = validate_at_sender_replicas(transfer, &mut sender)?
-            .ok_or(Error::SenderValidationFailed)?;
-        // 3. Register at Sender Replicas.
-        register_at_debiting_replicas(&debit_proof, &mut sender_section)?;
-        // 4. Propagate to Recipient Replicas.
-        let events =
-            propagate_to_crediting_replicas(debit_proof.credit_proof(), &mut recipient_section);
-        // 5. Synch at Recipient Actor.
-        synch(&mut recipient)?;
-
-        // --- Assert ---
-        // Actor and Replicas have the correct balance.
-        assert_balance(sender, Token::zero());
-        assert_balance(recipient, Token::from_nano(recipient_final));
-        Ok(Some(()))
-    }
-
-    fn assert_balance(actor: TestActor, amount: Token) {
-        assert!(actor.actor.balance() == amount);
-        actor.section.elders.iter().for_each(|elder| {
-            let wallet = match elder.replicas.get(&actor.actor.id()) {
-                Some(w) => w,
-                None => panic!("Failed the test; no such wallet."),
-            };
-            assert_eq!(wallet.balance(), amount)
-        });
-    }
-
-    // ------------------------------------------------------------------------
-    // ------------------------ AT2 Steps -------------------------------------
-    // ------------------------------------------------------------------------
-
-    // 1. Init debit at Sender Actor.
-    fn init_transfer(sender: &mut TestActor, to: PublicKey) -> Result<TransferInitiated> {
-        let transfer = sender
-            .actor
-            .transfer(sender.actor.balance(), to, "asdf".to_string())?
-            .ok_or(Error::TransferCreationFailed)?;
-
-        sender
-            .actor
-            .apply(ActorEvent::TransferInitiated(transfer.clone()))?;
-
-        Ok(transfer)
-    }
-
-    // 2. Validate debit at Sender Replicas.
-    fn validate_at_sender_replicas(
-        transfer: TransferInitiated,
-        sender: &mut TestActor,
-    ) -> Result<Option<TransferAgreementProof>> {
-        for elder in &mut sender.section.elders {
-            let wallet_replica = match elder.replicas.get_mut(&sender.actor.id()) {
-                Some(w) => w,
-                None => panic!("Failed the test; no such wallet."),
-            };
-            let _ = wallet_replica
-                .validate(&transfer.signed_debit, &transfer.signed_credit)?
-                .ok_or(Error::ValidationFailed)?;
-
-            let signed_transfer = SignedTransfer {
-                debit: transfer.signed_debit.clone(),
-                credit: transfer.signed_credit.clone(),
-            };
-            let (replica_debit_sig, replica_credit_sig) =
-                elder.signing.sign_transfer(&signed_transfer)?;
-            let validation = sn_data_types::TransferValidated {
-                signed_credit: signed_transfer.credit,
-                signed_debit: signed_transfer.debit,
-                replica_debit_sig,
-                replica_credit_sig,
-                replicas: sender.section.id.clone(),
-            };
-            // then apply to inmem state
-            wallet_replica.apply(ReplicaEvent::TransferValidated(validation.clone()))?;
-
-            let validation_received = sender
-                .actor
-                .receive(validation)?
-                .ok_or(Error::ReceiveValidationFailed)?;
-            sender.actor.apply(ActorEvent::TransferValidationReceived(
-                validation_received.clone(),
-            ))?;
-            if let Some(proof) = validation_received.proof {
-                let registered = sender
-                    .actor
-                    .register(proof.clone())?
-                    .ok_or(Error::RegisterProofFailed)?;
-                sender
-                    .actor
-                    .apply(ActorEvent::TransferRegistrationSent(registered))?;
-                return Ok(Some(proof));
-            }
+        let recipient_final = Token::from_nano(sender_balance + recipient_balance);
+        for order in [
+            DeliveryOrder::Fifo,
+            DeliveryOrder::Lifo,
+            DeliveryOrder::Random,
+        ] {
+            // --- Arrange ---
+            let mut network = Simulator::new(sender_balance, 2, replica_count as usize)?;
+            network.set_delivery_order(order);
+            let sender = network.add_client(0, Token::from_nano(sender_balance))?;
+            let recipient = network.add_client(1, Token::from_nano(recipient_balance))?;
+
+            // --- Act ---
+            // Validated and registered at the sender Replicas,
+            // then propagated to the recipient Replicas.
+            let amount = network.actor(&sender).ok_or(Error::NoSuchSender)?.balance();
+            let _ = network.transfer(&sender, recipient, amount)?;
+            let _ = network.run();
+            network.synch(&recipient)?;
+
+            // --- Assert ---
+            // Actor and Replicas have the correct balance.
+            // only a validation arriving after the registration is rejected
+            assert!(network
+                .rejected()
+                .iter()
+                .all(|(message, _)| matches!(message, Message::Validate { .. })));
+            assert_balance(&network, &sender, Token::zero())?;
+            assert_balance(&network, &recipient, recipient_final)?;
         }
-        Ok(None)
+        Ok(Some(()))
     }
 
-    // 3. Register debit at Sender Replicas.
-    fn register_at_debiting_replicas(
-        debit_proof: &TransferAgreementProof,
-        section: &mut Section,
-    ) -> Result<()> {
-        for elder in &mut section.elders {
-            let wallet_replica = match elder.replicas.get_mut(&debit_proof.sender()) {
-                Some(w) => w,
-                None => panic!("Failed the test; no such wallet."),
-            };
-            let registered = wallet_replica
-                .register(debit_proof)?
-                .ok_or(Error::RegisterProofFailed)?;
-            wallet_replica.apply(ReplicaEvent::TransferRegistered(registered))?;
+    fn assert_balance(network: &Simulator, key: &PublicKey, amount: Token) -> Result<()> {
+        let actor = network.actor(key).ok_or(Error::WalletNotFound(*key))?;
+        assert_eq!(actor.balance(), amount);
+        for balance in network.replica_balances(key)? {
+            assert_eq!(balance, amount);
         }
         Ok(())
     }
 
-    // 4. Propagate credit to Recipient Replicas.
-    fn propagate_to_crediting_replicas(
-        credit_proof: CreditAgreementProof,
-        section: &mut Section,
-    ) -> Vec<ReplicaEvent> {
-        section
-            .elders
-            .iter_mut()
-            .map(|replica| {
-                let wallet_replica = match replica.replicas.get_mut(&credit_proof.recipient()) {
-                    Some(w) => w,
-                    None => panic!("Failed the test; no such wallet."),
-                };
-                let _ = wallet_replica
-                    .receive_propagated(&credit_proof)?
-                    .ok_or(Error::ReceivePropagationFailed)?;
-
-                let propagated = sn_data_types::TransferPropagated {
-                    credit_proof: credit_proof.clone(),
-                };
-                // then apply to inmem state
-                wallet_replica.apply(ReplicaEvent::TransferPropagated(propagated.clone()))?;
-                Ok(ReplicaEvent::TransferPropagated(propagated))
-            })
-            .filter_map(|c: Result<ReplicaEvent>| match c {
-                Ok(c) => Some(c),
-                _ => None,
-            })
-            .collect()
-    }
-
-    // 5. Synch at Recipient Actor.
-    fn synch(recipient: &mut TestActor) -> Result<()> {
-        let section = &recipient.section;
-        let wallet = section.elders[0]
-            .replicas
-            .get(&recipient.actor.id())
-            .ok_or_else(|| Error::WalletNotFound(recipient.actor.id()))?;
-        let snapshot = wallet.wallet().ok_or(Error::CouldNotGetWalletForReplica)?;
-        let state = recipient
-            .actor
-            .synch(
-                snapshot.balance,
-                snapshot.debit_version,
-                snapshot.credit_ids,
-            )?
-            .ok_or(Error::SyncFailed)?;
-        recipient.actor.apply(ActorEvent::StateSynched(state))
-    }
-
     // ------------------------------------------------------------------------
     // ------------------------ Setup Helpers ---------------------------------
     // ------------------------------------------------------------------------
@@ -530,267 +1028,41 @@ mod test {
         PublicKey::from(SecretKey::random().public_key())
     }
 
-    fn find_group(index: u8, sections: &[Section]) -> Option<Section> {
-        for section in sections {
-            if section.index == index {
-                return Some(section.clone());
-            }
-        }
-        None
-    }
-
-    fn setup_random_wallet(balance: u64, section: u8) -> Result<TestWallet> {
-        let mut rng = rand::thread_rng();
-        let keypair = Keypair::new_ed25519(&mut rng);
-        let recipient = keypair.public_key();
-        let owner = OwnerType::Single(recipient);
-        let mut wallet = Wallet::new(owner);
-        setup_wallet(balance, section, keypair, wallet)
-    }
-
-    fn setup_wallet(
-        balance: u64,
-        section: u8,
-        keypair: Keypair,
-        wallet: Wallet,
-    ) -> Result<TestWallet> {
-        let mut wallet = wallet;
-        if balance > 0 {
-            let amount = Token::from_nano(balance);
-            let sender = Dot::new(get_random_pk(), 0);
-            let debit = Debit { id: sender, amount };
-            let credit = Credit {
-                id: debit.credit_id()?,
-                recipient: wallet.id().public_key(),
-                amount,
-                msg: "".to_string(),
-            };
-            let _ = wallet.apply_credit(credit)?;
-        }
-
-        Ok(TestWallet {
-            wallet,
-            keypair,
-            section,
-        })
-    }
-
-    fn setup_actor(wallet: TestWallet, sections: &[Section]) -> Result<TestActor> {
-        let section = find_group(wallet.section, sections).ok_or(Error::CouldNotFindGroup)?;
-        let replicas = SectionElders {
-            prefix: xor_name::Prefix::default(),
-            key_set: section.id.clone(),
-            names: Default::default(),
-        };
-        let actor = Actor::from_snapshot(wallet.wallet, wallet.keypair, replicas);
-
-        Ok(TestActor { actor, section })
-    }
-
-    // Create n replica groups, with k replicas in each
-    fn setup_section_keys(group_count: u8, replica_count: u8) -> HashMap<u8, SectionKeys> {
-        let mut rng = rand::thread_rng();
-        let mut groups = HashMap::new();
-        for i in 0..group_count {
-            let threshold = std::cmp::max(1, 2 * replica_count / 3) - 1;
-            let bls_secret_key = SecretKeySet::random(threshold as usize, &mut rng);
-            let peers = bls_secret_key.public_keys();
-            let mut shares = vec![];
-            for j in 0..replica_count {
-                let share = bls_secret_key.secret_key_share(j as usize);
-                shares.push((share, j as usize));
-            }
-            let _ = groups.insert(
-                i,
-                SectionKeys {
-                    index: i,
-                    id: peers,
-                    keys: shares,
-                },
-            );
-        }
-        groups
+    /// Copies of the Actors of the clients, and of the wallets and signing of the Elder,
+    /// of a network of a single section with a single Elder,
+    /// for the steps of a transfer to be taken one by one.
+    struct SingleElder {
+        actors: Vec<Actor<Keypair>>,
+        store: TransferReplicaStore,
+        signing: ReplicaSigningImpl,
+        known_sections: InMemoryKnownSections,
+        section_key: threshold_crypto::PublicKey,
     }
 
-    fn setup_new_network(
-        section_count: u8,
-        replicas_per_section: u8,
-        section_configs: Vec<Vec<u64>>,
-    ) -> Result<Network> {
-        // setup genesis section
-        let mut wallets_in_genesis_section = vec![];
-        let genesis_index = 0;
-        let mut section_configs = section_configs;
-        let mut genesis_section_configs = section_configs.remove(genesis_index);
-        for balance in genesis_section_configs {
-            let wallet = setup_random_wallet(balance, genesis_index as u8)?;
-            let _ = wallets_in_genesis_section.push(wallet);
-        }
-
-        let ((genesis_credit, genesis_wallet), genesis_section) =
-            setup_genesis_section(replicas_per_section, wallets_in_genesis_section.clone())?;
-        let _ = wallets_in_genesis_section.insert(0, genesis_wallet);
-
-        let mut all_sections = vec![genesis_section];
-        let mut other_section_wallets = vec![];
-        if section_count > 1 {
-            // setup rest of the sections
-            for (section_index, wallet_configs) in section_configs.iter().enumerate() {
-                let mut next_section_wallets = vec![];
-                for balance in wallet_configs {
-                    let wallet = setup_random_wallet(*balance, (section_index + 1) as u8)?;
-                    let _ = next_section_wallets.push(wallet.clone());
-                    let _ = other_section_wallets.push(wallet);
-                }
-                let section_keys = generate_section_keys(section_index as u8, replicas_per_section);
-                let section =
-                    setup_section(section_index as u8, section_keys, next_section_wallets);
-                all_sections.push(section);
-            }
-        }
-
-        let wallets = wallets_in_genesis_section
-            .into_iter()
-            .chain(other_section_wallets.into_iter())
-            .collect();
-        let actors = get_test_actors(wallets, all_sections.clone())?;
-
-        Ok(Network {
-            genesis_credit,
-            sections: all_sections,
+    /// A client for each of the balances, in a network of a single section with a single Elder.
+    fn setup_single_elder(balances: &[u64]) -> Result<SingleElder> {
+        let mut network = Simulator::new(rand::random(), 1, 1)?;
+        let keys = balances
+            .iter()
+            .map(|balance| network.add_client(0, Token::from_nano(*balance)))
+            .collect::<Result<Vec<_>>>()?;
+        let actors = keys
+            .iter()
+            .map(|key| {
+                network
+                    .actor(key)
+                    .cloned()
+                    .ok_or(Error::WalletNotFound(*key))
+            })
+            .collect::<Result<_>>()?;
+        let section = &network.sections()[0];
+        let elder = &section.elders()[0];
+        Ok(SingleElder {
             actors,
+            store: elder.store().clone(),
+            signing: elder.signing().clone(),
+            known_sections: network.known_sections().clone(),
+            section_key: section.key_set().public_key(),
         })
     }
-
-    fn get_test_actors(wallets: Vec<TestWallet>, sections: Vec<Section>) -> Result<Vec<TestActor>> {
-        let mut actors = vec![];
-        for wallet in wallets {
-            actors.push(setup_actor(wallet.clone(), &sections)?);
-        }
-        Ok(actors)
-    }
-
-    fn generate_section_keys(section_index: u8, replica_count: u8) -> SectionKeys {
-        //
-        let mut rng = rand::thread_rng();
-        let threshold = std::cmp::max(1, 2 * replica_count / 3) - 1;
-        let bls_secret_key = SecretKeySet::random(threshold as usize, &mut rng);
-        let peers = bls_secret_key.public_keys();
-        let mut shares = vec![];
-        for j in 0..replica_count {
-            let share = bls_secret_key.secret_key_share(j as usize);
-            shares.push((share, j as usize));
-        }
-        SectionKeys {
-            index: section_index,
-            id: peers,
-            keys: shares,
-        }
-    }
-
-    fn setup_section(
-        section_index: u8,
-        section_keys: SectionKeys,
-        wallets: Vec<TestWallet>,
-    ) -> Section {
-        /// ----
-        let mut elders = vec![];
-        let peer_replicas = section_keys.id.clone();
-        for (secret_key, key_index) in &section_keys.keys {
-            let wallets = wallets.clone();
-            let mut wallet_replicas = hashmap![];
-            for wallet in wallets.into_iter() {
-                let wallet_id = wallet.wallet.id();
-                let pending_proposals = Default::default();
-                let wallet_replica = WalletReplica::from_snapshot(
-                    wallet_id.clone(),
-                    secret_key.public_key_share(),
-                    *key_index,
-                    peer_replicas.clone(),
-                    wallet.wallet.clone(),
-                    pending_proposals,
-                    None,
-                );
-                let _ = wallet_replicas.insert(wallet_id.public_key(), wallet_replica);
-            }
-            elders.push(Elder {
-                id: secret_key.public_key_share(),
-                replicas: wallet_replicas,
-                signing: ReplicaSigning::new(secret_key.clone(), *key_index, peer_replicas.clone()),
-            });
-        }
-        Section {
-            index: section_index,
-            id: peer_replicas,
-            elders,
-        }
-    }
-
-    fn setup_genesis_section(
-        replica_count: u8,
-        wallets: Vec<TestWallet>,
-    ) -> Result<((CreditAgreementProof, TestWallet), Section)> {
-        // setup genesis wallet
-        let threshold = (replica_count - 1) as usize;
-        let balance = u32::MAX as u64 * 1_000_000_000;
-        let mut rng = rand::thread_rng();
-        let bls_secret_key = SecretKeySet::random(threshold, &mut rng);
-        let peer_replicas = bls_secret_key.public_keys();
-        let id = PublicKey::Bls(peer_replicas.public_key());
-        let keypair = sn_data_types::Keypair::new_bls_share(
-            0,
-            bls_secret_key.secret_key_share(0),
-            peer_replicas.clone(),
-        );
-        let owner = OwnerType::Multi(peer_replicas.clone());
-        let empty_genesis_wallet = setup_wallet(0, 0, keypair, Wallet::new(owner))?;
-        let genesis_credit = get_multi_genesis(balance, id, bls_secret_key.clone())?;
-
-        let mut wallets = wallets;
-        wallets.insert(0, empty_genesis_wallet.clone());
-
-        // setup genesis replicas
-        let mut elders = vec![];
-        for key_index in 0..replica_count as usize {
-            // copy over all wallets to the replicas
-            for wallet in &wallets {
-                let secret_key = bls_secret_key.secret_key_share(key_index);
-                let peer_replicas = bls_secret_key.public_keys();
-                let mut wallet_replicas = hashmap![];
-                let wallet_id = wallet.wallet.id();
-                let pending_proposals = Default::default();
-                let wallet_replica = WalletReplica::from_snapshot(
-                    wallet_id.clone(),
-                    secret_key.public_key_share(),
-                    key_index,
-                    peer_replicas.clone(),
-                    wallet.wallet.clone(),
-                    pending_proposals,
-                    None,
-                );
-                let _ = wallet_replicas.insert(wallet_id.public_key(), wallet_replica);
-                elders.push(Elder {
-                    id: secret_key.public_key_share(),
-                    replicas: wallet_replicas,
-                    signing: ReplicaSigning::new(
-                        secret_key.clone(),
-                        key_index,
-                        peer_replicas.clone(),
-                    ),
-                });
-            }
-        }
-        Ok((
-            (genesis_credit, empty_genesis_wallet),
-            Section {
-                index: 0,
-                id: peer_replicas,
-                elders,
-            },
-        ))
-    }
-
-    // ------------------------------------------------------------------------
-    // ------------------------ Structs ---------------------------------------
-    // ------------------------------------------------------------------------
 }
@@ -0,0 +1,538 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! The Actor of one of the co-owners of a wallet owned by a BLS key set ([OwnerType::Multi]).
+//! A transfer is proposed by one of the co-owners, as a transfer signed with its key share.
+//! The share is sent to the other co-owners, who co-sign the same transfer with their shares.
+//! Each co-owner accumulates the shares it receives, and once more than the threshold
+//! of the key set have signed, combines them into a transfer signed by the wallet key,
+//! which is then validated by the Replicas as any other transfer.
+//! The debit shares combined into the wallet signature are kept with the transfer,
+//! for the Replicas to count its signers against the spending policy of the wallet,
+//! and a co-owner may still add its share to an agreed transfer, for a higher tier.
+//!
+//! Since the debit and credit of a transfer share must be of the same amount,
+//! transfers of multisig wallets cannot include a fee.
+//!
+//! [OwnerType::Multi]: sn_data_types::OwnerType::Multi
+
+use super::{wallet::Wallet, Error, Outcome, Result, TernaryResult};
+use crdts::Dot;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    Credit, Debit, DebitId, OwnerType, PublicKey, Signature, SignatureShare, SignedCredit,
+    SignedCreditShare, SignedDebit, SignedDebitShare, SignedTransfer, SignedTransferShare, Signing,
+    Token,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use threshold_crypto::PublicKeySet;
+
+/// The Actor of a co-owner of a multisig wallet.
+#[derive(Clone)]
+pub struct MultisigActor<S: Signing> {
+    /// The PK Set of the co-owners, i.e. the wallet key.
+    owners: PublicKeySet,
+    /// The number of co-owners, i.e. of key shares.
+    owner_count: usize,
+    /// The index of our key share.
+    owner_index: usize,
+    /// The signing of our key share.
+    signing: S,
+    /// The wallet, as of the last update from the Replicas.
+    wallet: Wallet,
+    /// Shares of the proposed transfers, by debit counter and key share index.
+    accumulating: BTreeMap<u64, BTreeMap<usize, SignedTransferShare>>,
+    /// Transfers signed by a quorum of the co-owners, by debit counter.
+    agreed: BTreeMap<u64, MultisigTransfer>,
+}
+
+/// A transfer signed by the wallet key, with the shares
+/// of its debit that were combined into the wallet signature.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct MultisigTransfer {
+    /// The transfer signed by the wallet key.
+    pub signed_transfer: SignedTransfer,
+    /// The debit shares of the co-owners which signed it, by key share index.
+    pub debit_shares: Vec<SignatureShare>,
+}
+
+impl MultisigTransfer {
+    /// The indices of the co-owners which signed the transfer.
+    pub fn signers(&self) -> BTreeSet<usize> {
+        self.debit_shares.iter().map(|s| s.index).collect()
+    }
+}
+
+/// Raised when a share of a proposed transfer has been signed, or received from a co-owner.
+/// Holds the transfer signed by the wallet key when the share completes a quorum.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct TransferShareAccumulated {
+    /// The transfer share.
+    pub signed_transfer: SignedTransferShare,
+    /// The transfer signed by the wallet key, once agreed by a quorum of the co-owners.
+    pub agreed_transfer: Option<MultisigTransfer>,
+}
+
+impl<S: Signing> MultisigActor<S> {
+    /// Pass in the signing of our key share, the number of co-owners, and the wallet.
+    pub fn new(signing: S, owner_count: usize, wallet: Wallet) -> Result<MultisigActor<S>> {
+        let owners = match signing.id() {
+            OwnerType::Multi(owners) => owners,
+            OwnerType::Single(_) => return Err(Error::InvalidOwner),
+        };
+        if wallet.id().public_key() != PublicKey::Bls(owners.public_key())
+            || owner_count <= owners.threshold()
+        {
+            return Err(Error::InvalidOwner);
+        }
+        let owner_index = sign_share(&signing, &wallet.id().public_key())?.index;
+        if owner_index >= owner_count {
+            return Err(Error::InvalidOwner);
+        }
+        Ok(MultisigActor {
+            owners,
+            owner_count,
+            owner_index,
+            signing,
+            wallet,
+            accumulating: Default::default(),
+            agreed: Default::default(),
+        })
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Queries ----------------------------------
+    /// -----------------------------------------------------------------
+
+    /// Query for the id of the wallet.
+    pub fn id(&self) -> PublicKey {
+        PublicKey::Bls(self.owners.public_key())
+    }
+
+    /// Query for the PK Set of the co-owners.
+    pub fn owners(&self) -> &PublicKeySet {
+        &self.owners
+    }
+
+    /// Query for the index of our key share.
+    pub fn owner_index(&self) -> usize {
+        self.owner_index
+    }
+
+    /// Query for the balance of the wallet.
+    pub fn balance(&self) -> Token {
+        self.wallet.balance()
+    }
+
+    /// The ids of the proposed transfers not yet signed by a quorum of the co-owners.
+    pub fn pending_transfers(&self) -> Vec<DebitId> {
+        self.accumulating
+            .keys()
+            .map(|counter| Dot::new(self.id(), *counter))
+            .collect()
+    }
+
+    /// The indices of the co-owners which have not yet signed the proposed transfer.
+    /// Returns None if the transfer is not awaiting signatures.
+    pub fn missing_signers(&self, id: &DebitId) -> Option<Vec<usize>> {
+        let shares = self.accumulating.get(&id.counter)?;
+        Some(
+            (0..self.owner_count)
+                .filter(|index| !shares.contains_key(index))
+                .collect(),
+        )
+    }
+
+    /// The transfer signed by the wallet key, once agreed by a quorum of the co-owners.
+    pub fn agreed_transfer(&self, id: &DebitId) -> Option<&MultisigTransfer> {
+        self.agreed.get(&id.counter)
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Cmds -------------------------------------
+    /// -----------------------------------------------------------------
+
+    /// Step 1. Propose a transfer to the co-owners, signed with our key share.
+    pub fn propose(
+        &self,
+        amount: Token,
+        recipient: PublicKey,
+        msg: String,
+    ) -> Outcome<TransferShareAccumulated> {
+        if recipient == self.id() {
+            return Outcome::rejected(Error::SameSenderAndRecipient);
+        } else if amount == Token::zero() {
+            return Outcome::rejected(Error::ZeroValueTransfer);
+        } else if amount > self.available_balance() {
+            return Outcome::rejected(Error::InsufficientBalance);
+        }
+
+        let debit = Debit {
+            id: Dot::new(self.id(), self.next_debit()),
+            amount,
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient,
+            amount,
+            msg,
+        };
+        let signed_transfer = self.sign(debit, credit)?;
+        self.accumulate(signed_transfer)
+    }
+
+    /// Step 2. Co-sign a transfer proposed by a co-owner, with our key share.
+    /// An agreed transfer can be co-signed, adding us to its signers.
+    pub fn co_sign(&self, id: &DebitId) -> Outcome<TransferShareAccumulated> {
+        if let Some(agreed) = self.agreed.get(&id.counter) {
+            if agreed.signers().contains(&self.owner_index) {
+                return Outcome::no_change();
+            }
+            let signed_transfer = self.sign(
+                agreed.signed_transfer.debit.debit.clone(),
+                agreed.signed_transfer.credit.credit.clone(),
+            )?;
+            return self.accumulate(signed_transfer);
+        }
+        let shares = match self.accumulating.get(&id.counter) {
+            Some(shares) => shares,
+            None => return Outcome::rejected(Error::PendingTransferNotFound),
+        };
+        if shares.contains_key(&self.owner_index) {
+            return Outcome::no_change();
+        }
+        let proposal = match shares.values().next() {
+            Some(proposal) => proposal,
+            None => return Outcome::rejected(Error::PendingTransferNotFound),
+        };
+        let signed_transfer = self.sign(
+            proposal.debit().debit.clone(),
+            proposal.credit().credit.clone(),
+        )?;
+        self.accumulate(signed_transfer)
+    }
+
+    /// Step 2. Receive a share of a transfer signed by a co-owner.
+    pub fn receive_share(
+        &self,
+        signed_transfer: SignedTransferShare,
+    ) -> Outcome<TransferShareAccumulated> {
+        // Always verify signature first! (as to not leak any information).
+        if let Err(e) = self.verify_share(&signed_transfer) {
+            return Outcome::rejected(e);
+        }
+        let counter = signed_transfer.id().counter;
+        if counter < self.wallet.next_debit() {
+            return Outcome::no_change();
+        }
+        self.accumulate(signed_transfer)
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Mutation ---------------------------------
+    /// -----------------------------------------------------------------
+
+    /// Mutation of state.
+    /// There is no validation of an event, it is assumed to have
+    /// been properly validated before raised, and thus anything that breaks is a bug.
+    pub fn apply(&mut self, event: TransferShareAccumulated) -> Result<()> {
+        let counter = event.signed_transfer.id().counter;
+        match event.agreed_transfer {
+            Some(agreed_transfer) => {
+                let _ = self.accumulating.remove(&counter);
+                let _ = self.agreed.insert(counter, agreed_transfer);
+            }
+            None => {
+                let index = event.signed_transfer.share_index();
+                let _ = self
+                    .accumulating
+                    .entry(counter)
+                    .or_default()
+                    .insert(index, event.signed_transfer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the wallet from the Replicas, dropping
+    /// the proposed and agreed transfers it already holds.
+    pub fn update_wallet(&mut self, wallet: Wallet) -> Result<()> {
+        if wallet.id().public_key() != self.id() {
+            return Err(Error::InvalidOwner);
+        }
+        let next_debit = wallet.next_debit();
+        self.accumulating
+            .retain(|counter, _| *counter >= next_debit);
+        self.agreed.retain(|counter, _| *counter >= next_debit);
+        self.wallet = wallet;
+        Ok(())
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Private methods --------------------------
+    /// -----------------------------------------------------------------
+
+    fn next_debit(&self) -> u64 {
+        let last_proposed = self
+            .accumulating
+            .keys()
+            .chain(self.agreed.keys())
+            .max()
+            .map(|counter| counter + 1)
+            .unwrap_or_default();
+        last_proposed.max(self.wallet.next_debit())
+    }
+
+    fn available_balance(&self) -> Token {
+        let pending = self
+            .accumulating
+            .values()
+            .filter_map(|shares| shares.values().next())
+            .map(|s| s.debit().amount())
+            .chain(
+                self.agreed
+                    .values()
+                    .map(|t| t.signed_transfer.debit.amount()),
+            )
+            .fold(0, |sum, amount| sum + amount.as_nano());
+        Token::from_nano(
+            self.wallet
+                .unlocked_balance(u64::MAX)
+                .as_nano()
+                .saturating_sub(pending),
+        )
+    }
+
+    fn sign(&self, debit: Debit, credit: Credit) -> Result<SignedTransferShare> {
+        let debit_share = SignedDebitShare {
+            actor_signature: sign_share(&self.signing, &debit)?,
+            debit,
+        };
+        let credit_share = SignedCreditShare {
+            actor_signature: sign_share(&self.signing, &credit)?,
+            credit,
+        };
+        Ok(SignedTransferShare::new(
+            debit_share,
+            credit_share,
+            self.owners.clone(),
+        )?)
+    }
+
+    fn verify_share(&self, signed_transfer: &SignedTransferShare) -> Result<()> {
+        let debit = &signed_transfer.debit().debit;
+        let credit = &signed_transfer.credit().credit;
+        if signed_transfer.actors() != &self.owners
+            || signed_transfer.share_index() >= self.owner_count
+        {
+            return Err(Error::InvalidOwner);
+        } else if debit.sender() != self.id() {
+            return Err(Error::NoSuchSender);
+        } else if credit.id() != &debit.credit_id()? {
+            return Err(Error::CreditDebitIdMismatch);
+        }
+        let key_share = self.owners.public_key_share(signed_transfer.share_index());
+        let debit_sig = &signed_transfer.debit().actor_signature;
+        let credit_sig = &signed_transfer.credit().actor_signature;
+        if debit_sig.index == credit_sig.index
+            && key_share.verify(&debit_sig.share, serialise(debit)?)
+            && key_share.verify(&credit_sig.share, serialise(credit)?)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+
+    /// Combines the shares into a transfer signed by the wallet key,
+    /// if the share completes a quorum, or adds the share to the agreed transfer.
+    fn accumulate(
+        &self,
+        signed_transfer: SignedTransferShare,
+    ) -> Outcome<TransferShareAccumulated> {
+        let counter = signed_transfer.id().counter;
+        if let Some(agreed) = self.agreed.get(&counter) {
+            if agreed.signed_transfer.debit.debit != signed_transfer.debit().debit
+                || agreed.signed_transfer.credit.credit != signed_transfer.credit().credit
+            {
+                return Outcome::rejected(Error::DebitEquivocation);
+            } else if agreed.signers().contains(&signed_transfer.share_index()) {
+                return Outcome::no_change();
+            }
+            let mut agreed = agreed.clone();
+            agreed
+                .debit_shares
+                .push(signed_transfer.debit().actor_signature.clone());
+            agreed.debit_shares.sort_by_key(|s| s.index);
+            return Outcome::success(TransferShareAccumulated {
+                signed_transfer,
+                agreed_transfer: Some(agreed),
+            });
+        }
+        let no_shares = BTreeMap::new();
+        let shares = self.accumulating.get(&counter).unwrap_or(&no_shares);
+        if shares.contains_key(&signed_transfer.share_index()) {
+            return Outcome::no_change();
+        }
+        if let Some(proposal) = shares.values().next() {
+            if proposal.debit().debit != signed_transfer.debit().debit
+                || proposal.credit().credit != signed_transfer.credit().credit
+            {
+                return Outcome::rejected(Error::DebitEquivocation);
+            }
+        }
+
+        if shares.len() < self.owners.threshold() {
+            debug!(
+                "Transfer share accumulated, {} of {} signed.",
+                shares.len() + 1,
+                self.owners.threshold() + 1
+            );
+            return Outcome::success(TransferShareAccumulated {
+                signed_transfer,
+                agreed_transfer: None,
+            });
+        }
+
+        let all_shares = || shares.values().chain(std::iter::once(&signed_transfer));
+        let mut debit_shares: Vec<_> = all_shares()
+            .map(|s| s.debit().actor_signature.clone())
+            .collect();
+        debit_shares.sort_by_key(|s| s.index);
+        let debit_sig_shares: BTreeMap<_, _> = debit_shares
+            .iter()
+            .map(|s| (s.index, s.share.clone()))
+            .collect();
+        let credit_sig_shares: BTreeMap<_, _> = all_shares()
+            .map(|s| s.credit().actor_signature.clone())
+            .map(|s| (s.index, s.share))
+            .collect();
+        let debit_sig = self
+            .owners
+            .combine_signatures(&debit_sig_shares)
+            .map_err(|_| Error::CannotAggregate)?;
+        let credit_sig = self
+            .owners
+            .combine_signatures(&credit_sig_shares)
+            .map_err(|_| Error::CannotAggregate)?;
+
+        let agreed_transfer = MultisigTransfer {
+            signed_transfer: SignedTransfer {
+                debit: SignedDebit {
+                    debit: signed_transfer.debit().debit.clone(),
+                    actor_signature: Signature::Bls(debit_sig),
+                },
+                credit: SignedCredit {
+                    credit: signed_transfer.credit().credit.clone(),
+                    actor_signature: Signature::Bls(credit_sig),
+                },
+            },
+            debit_shares,
+        };
+        Outcome::success(TransferShareAccumulated {
+            signed_transfer,
+            agreed_transfer: Some(agreed_transfer),
+        })
+    }
+}
+
+fn sign_share<S: Signing, T: Serialize>(signing: &S, item: &T) -> Result<SignatureShare> {
+    match signing.sign(item)? {
+        Signature::BlsShare(share) => Ok(share),
+        _ => Err(Error::InvalidOwner),
+    }
+}
+
+fn serialise<T: Serialize>(item: &T) -> Result<Vec<u8>> {
+    bincode::serialize(item).map_err(|_| Error::Serialisation("Could not serialise item".into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::MultisigActor;
+    use crate::{Error, Result, Wallet};
+    use sn_data_types::{Credit, Keypair, OwnerType, PublicKey, Token};
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn co_owners_sign_a_transfer_by_quorum() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let owners = SecretKeySet::random(1, &mut rng);
+        let wallet_key = PublicKey::Bls(owners.public_keys().public_key());
+        let mut wallet = Wallet::new(OwnerType::Multi(owners.public_keys()));
+        wallet.apply_credit(Credit {
+            id: Default::default(),
+            recipient: wallet_key,
+            amount: Token::from_nano(10),
+            msg: "".to_string(),
+        })?;
+        let mut co_owners = (0..3)
+            .map(|index| {
+                let keypair = Keypair::new_bls_share(
+                    index,
+                    owners.secret_key_share(index),
+                    owners.public_keys(),
+                );
+                MultisigActor::new(keypair, 3, wallet.clone())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let recipient: PublicKey = Keypair::new_ed25519(&mut rng).public_key();
+
+        assert_eq!(
+            co_owners[0].propose(Token::from_nano(11), recipient, "".to_string()),
+            Err(Error::InsufficientBalance)
+        );
+        let proposed = co_owners[0]
+            .propose(Token::from_nano(7), recipient, "".to_string())?
+            .expect("share");
+        let id = proposed.signed_transfer.id();
+        assert_eq!(proposed.agreed_transfer, None);
+        co_owners[0].apply(proposed.clone())?;
+        assert_eq!(co_owners[0].missing_signers(&id), Some(vec![1, 2]));
+
+        // the proposal reaches co-owner 2, which co-signs it
+        let received = co_owners[2]
+            .receive_share(proposed.signed_transfer.clone())?
+            .expect("share");
+        co_owners[2].apply(received)?;
+        assert_eq!(co_owners[2].missing_signers(&id), Some(vec![1, 2]));
+        let co_signed = co_owners[2].co_sign(&id)?.expect("co-signed");
+        assert!(co_signed.agreed_transfer.is_some());
+        co_owners[2].apply(co_signed.clone())?;
+        assert_eq!(co_owners[2].missing_signers(&id), None);
+
+        // a conflicting proposal, by co-owner 1, is rejected
+        let conflicting = co_owners[1]
+            .propose(Token::from_nano(7), recipient, "other".to_string())?
+            .expect("share");
+        assert_eq!(
+            co_owners[0].receive_share(conflicting.signed_transfer),
+            Err(Error::DebitEquivocation)
+        );
+
+        // the proposer agrees on the same transfer, once it receives the co-signed share
+        let agreed = co_owners[0]
+            .receive_share(co_signed.signed_transfer)?
+            .expect("agreed");
+        assert_eq!(agreed.agreed_transfer, co_signed.agreed_transfer);
+        co_owners[0].apply(agreed)?;
+        let agreed = co_owners[0].agreed_transfer(&id).expect("agreed");
+        assert_eq!(agreed.signers(), vec![0, 2].into_iter().collect());
+        let transfer = &agreed.signed_transfer;
+        assert_eq!(transfer.debit.sender(), wallet_key);
+        assert!(wallet_key
+            .verify(
+                &transfer.debit.actor_signature,
+                bincode::serialize(&transfer.debit.debit).unwrap()
+            )
+            .is_ok());
+        assert_eq!(co_owners[0].pending_transfers(), vec![]);
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Receipts of registered transfers, for the sender to hand to the recipient.
+//! A receipt holds the transfer signed by the sender, and the signatures
+//! of the debiting Replicas, so that it can be verified offline by anyone
+//! knowing the section key. It is shared as bytes, or as z-base-32 text.
+
+use crate::{
+    actor::verify_agreement_signatures,
+    batch_credit_id,
+    versioned::{deserialise, split_version, to_versioned_bytes},
+    BatchTransferAgreementProof, Error, KnownSections, Result,
+};
+use multibase::Base;
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    DebitId, PublicKey, Signature, SignedCredit, SignedDebit, Token, TransferAgreementProof,
+};
+
+/// The current version of the Receipt format.
+pub const RECEIPT_VERSION: u16 = 1;
+
+/// The proof that a transfer was registered by the Replicas of the sender.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct Receipt {
+    /// The debit, signed by the sender.
+    signed_debit: SignedDebit,
+    /// The credit, signed by the sender.
+    signed_credit: SignedCredit,
+    /// The signature of the debiting Replicas over the signed debit.
+    debit_sig: Signature,
+    /// The signature of the debiting Replicas over the signed credit.
+    credit_sig: Signature,
+    /// The section key of the debiting Replicas.
+    section_key: threshold_crypto::PublicKey,
+    /// The position of the credit in a batch transfer, none for a single transfer.
+    batch_index: Option<usize>,
+}
+
+impl Receipt {
+    /// The receipts of a batch transfer, one for each of its credits.
+    pub fn of_batch(proof: &BatchTransferAgreementProof) -> Vec<Self> {
+        proof
+            .signed_credits
+            .iter()
+            .zip(&proof.credit_sigs)
+            .enumerate()
+            .map(|(index, (signed_credit, credit_sig))| Self {
+                signed_debit: proof.signed_debit.clone(),
+                signed_credit: signed_credit.clone(),
+                debit_sig: proof.debit_sig.clone(),
+                credit_sig: credit_sig.clone(),
+                section_key: proof.debiting_replicas_keys.public_key(),
+                batch_index: Some(index),
+            })
+            .collect()
+    }
+
+    /// Get the debit id
+    pub fn debit_id(&self) -> DebitId {
+        self.signed_debit.id()
+    }
+
+    /// Get the sender
+    pub fn sender(&self) -> PublicKey {
+        self.signed_debit.sender()
+    }
+
+    /// Get the recipient
+    pub fn recipient(&self) -> PublicKey {
+        self.signed_credit.recipient()
+    }
+
+    /// Get the amount credited to the recipient
+    pub fn amount(&self) -> Token {
+        self.signed_credit.amount()
+    }
+
+    /// Get the msg to the recipient
+    pub fn msg(&self) -> &str {
+        &self.signed_credit.credit.msg
+    }
+
+    /// Get the section key of the debiting Replicas
+    pub fn section_key(&self) -> threshold_crypto::PublicKey {
+        self.section_key
+    }
+
+    /// Verifies that the transfer was signed by the sender,
+    /// and agreed by the Replicas of a known section.
+    pub fn verify<K: KnownSections>(&self, known_sections: &K) -> Result<()> {
+        let sender = self.sender();
+        let verify = |sig, item: Result<Vec<u8>>| {
+            sender
+                .verify(sig, &item?)
+                .map_err(|_| Error::InvalidSignature)
+        };
+        verify(
+            &self.signed_debit.actor_signature,
+            serialise(&self.signed_debit.debit),
+        )?;
+        verify(
+            &self.signed_credit.actor_signature,
+            serialise(&self.signed_credit.credit),
+        )?;
+        let credit_id = match self.batch_index {
+            Some(index) => batch_credit_id(&self.debit_id(), index)?,
+            None => self.signed_debit.credit_id()?,
+        };
+        if self.signed_credit.id() != &credit_id {
+            return Err(Error::CreditDebitIdMismatch);
+        }
+        verify_agreement_signatures(
+            &self.section_key,
+            &self.signed_debit,
+            &self.signed_credit,
+            &self.debit_sig,
+            &self.credit_sig,
+        )?;
+        if known_sections.is_known(&self.section_key) {
+            Ok(())
+        } else {
+            Err(Error::SectionKeyNeverExisted)
+        }
+    }
+
+    /// The receipt as bytes, prefixed by the format version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        to_versioned_bytes(RECEIPT_VERSION, self)
+    }
+
+    /// The receipt from its bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match split_version(bytes)? {
+            (RECEIPT_VERSION, receipt) => deserialise(receipt),
+            (version, _) => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+
+    /// The receipt as z-base-32 text.
+    pub fn encode(&self) -> Result<String> {
+        Ok(multibase::encode(Base::Base32Z, self.to_bytes()?))
+    }
+
+    /// The receipt from its z-base-32 text.
+    pub fn decode(encoded: &str) -> Result<Self> {
+        match multibase::decode(encoded) {
+            Ok((Base::Base32Z, bytes)) => Self::from_bytes(&bytes),
+            _ => Err(Error::Serialisation("Expected z-base-32 encoding".into())),
+        }
+    }
+}
+
+impl From<&TransferAgreementProof> for Receipt {
+    fn from(proof: &TransferAgreementProof) -> Self {
+        Self {
+            signed_debit: proof.signed_debit.clone(),
+            signed_credit: proof.signed_credit.clone(),
+            debit_sig: proof.debit_sig.clone(),
+            credit_sig: proof.credit_sig.clone(),
+            section_key: proof.debiting_replicas_keys.public_key(),
+            batch_index: None,
+        }
+    }
+}
+
+fn serialise<T: Serialize>(item: &T) -> Result<Vec<u8>> {
+    bincode::serialize(item).map_err(|_| Error::Serialisation("Could not serialise item".into()))
+}
@@ -0,0 +1,175 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{htlc::HtlcClaim, Error, Result};
+use serde::Serialize;
+use sn_data_types::{SignatureShare, SignedCredit, SignedDebit, SignedTransfer};
+use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKeyShare};
+
+/// The signing of validated transfers, done by a Replica
+/// with its share of the section key set.
+pub trait ReplicaSigning {
+    /// The public key share of this Replica.
+    fn id(&self) -> PublicKeyShare;
+
+    /// The index of this Replica key share, in the group set.
+    fn key_index(&self) -> usize;
+
+    /// The PK set of our peer Replicas.
+    fn replicas_pk_set(&self) -> &PublicKeySet;
+
+    /// Signs the debit and the credit of a validated transfer.
+    fn sign_transfer(
+        &self,
+        signed_transfer: &SignedTransfer,
+    ) -> Result<(SignatureShare, SignatureShare)>;
+
+    /// Signs the debit of a validated transfer.
+    fn sign_validated_debit(&self, debit: &SignedDebit) -> Result<SignatureShare>;
+
+    /// Signs the credit of a validated transfer.
+    fn sign_validated_credit(&self, credit: &SignedCredit) -> Result<SignatureShare>;
+
+    /// Signs the claim of a hash-time-locked credit, made before its deadline.
+    fn sign_htlc_claim(&self, claim: &HtlcClaim) -> Result<SignatureShare>;
+}
+
+/// An impl of ReplicaSigning, holding the secret key share in memory.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ReplicaSigningImpl {
+    /// The public key share of this Replica.
+    id: PublicKeyShare,
+    /// Secret key share.
+    secret_key: SecretKeyShare,
+    /// The index of this Replica key share, in the group set.
+    key_index: usize,
+    /// The PK set of our peer Replicas.
+    peer_replicas: PublicKeySet,
+}
+
+impl ReplicaSigningImpl {
+    /// A new instance
+    pub fn new(secret_key: SecretKeyShare, key_index: usize, peer_replicas: PublicKeySet) -> Self {
+        let id = secret_key.public_key_share();
+        Self {
+            id,
+            secret_key,
+            key_index,
+            peer_replicas,
+        }
+    }
+
+    fn sign<T: Serialize>(&self, item: &T, name: &str) -> Result<SignatureShare> {
+        match bincode::serialize(item) {
+            Err(_) => Err(Error::Serialisation(format!(
+                "Could not serialise {}",
+                name
+            ))),
+            Ok(data) => Ok(SignatureShare {
+                index: self.key_index,
+                share: self.secret_key.sign(data),
+            }),
+        }
+    }
+}
+
+impl ReplicaSigning for ReplicaSigningImpl {
+    fn id(&self) -> PublicKeyShare {
+        self.id
+    }
+
+    fn key_index(&self) -> usize {
+        self.key_index
+    }
+
+    fn replicas_pk_set(&self) -> &PublicKeySet {
+        &self.peer_replicas
+    }
+
+    fn sign_transfer(
+        &self,
+        signed_transfer: &SignedTransfer,
+    ) -> Result<(SignatureShare, SignatureShare)> {
+        let replica_debit_sig = self.sign_validated_debit(&signed_transfer.debit)?;
+        let replica_credit_sig = self.sign_validated_credit(&signed_transfer.credit)?;
+        Ok((replica_debit_sig, replica_credit_sig))
+    }
+
+    fn sign_validated_debit(&self, debit: &SignedDebit) -> Result<SignatureShare> {
+        self.sign(debit, "debit")
+    }
+
+    fn sign_validated_credit(&self, credit: &SignedCredit) -> Result<SignatureShare> {
+        self.sign(credit, "credit")
+    }
+
+    fn sign_htlc_claim(&self, claim: &HtlcClaim) -> Result<SignatureShare> {
+        self.sign(claim, "claim")
+    }
+}
+
+impl std::fmt::Debug for ReplicaSigningImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ReplicaSigningImpl {{ id: {:?}, key_index: {:?}, peer_replicas: PkSet {{ public_key: {:?} }} }}",
+            self.id,
+            self.key_index,
+            self.peer_replicas.public_key()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ReplicaSigning, ReplicaSigningImpl};
+    use crate::Result;
+    use crdts::Dot;
+    use sn_data_types::{Credit, Debit, Keypair, SignedCredit, SignedDebit, Signing, Token};
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn signs_validated_transfer_with_key_share() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(1, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(2), 2, sk_set.public_keys());
+
+        let keypair = Keypair::new_ed25519(&mut rng);
+        let debit = Debit {
+            id: Dot::new(keypair.public_key(), 0),
+            amount: Token::from_nano(10),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient: Keypair::new_ed25519(&mut rng).public_key(),
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        let signed_debit = SignedDebit {
+            actor_signature: Signing::sign(&keypair, &debit)?,
+            debit,
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: Signing::sign(&keypair, &credit)?,
+            credit,
+        };
+
+        let debit_sig = signing.sign_validated_debit(&signed_debit)?;
+        let credit_sig = signing.sign_validated_credit(&signed_credit)?;
+
+        assert_eq!(debit_sig.index, 2);
+        assert_eq!(credit_sig.index, 2);
+        let key_share = sk_set.public_keys().public_key_share(2);
+        assert!(key_share.verify(&debit_sig.share, bincode::serialize(&signed_debit).unwrap()));
+        assert!(key_share.verify(
+            &credit_sig.share,
+            bincode::serialize(&signed_credit).unwrap()
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    spending_policy::Spend,
+    versioned::{deserialise, split_version, to_versioned_bytes},
+    EquivocationProof, Error, LockedCredit, Result, SignedBatchTransfer, SpendingPolicy,
+};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{
+    CreditId, OwnerType, PublicKey, SignedTransfer, Token, TransferValidationProposed,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use threshold_crypto::{PublicKeySet, PublicKeyShare};
+
+/// The current version of the WalletReplicaSnapshot format.
+pub const REPLICA_SNAPSHOT_VERSION: u16 = 1;
+
+/// The full state of a WalletReplica, at some point in its history.
+/// Replaying the events applied after that point, on the
+/// replica restored from the snapshot, gives the current state.
+///
+/// A snapshot is stored as its [bytes](WalletReplicaSnapshot::to_bytes),
+/// which are prefixed by the version of their format.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletReplicaSnapshot {
+    /// The owner of the Wallet.
+    pub(crate) id: SnapshotOwner,
+    /// The public key share of the Replica.
+    pub(crate) replica_id: PublicKeyShare,
+    /// The index of the Replica key share, in the group set.
+    pub(crate) key_index: usize,
+    /// The PK set of the peer Replicas.
+    pub(crate) peer_replicas: PublicKeySet,
+    /// The balance of the Wallet.
+    pub(crate) balance: Token,
+    /// The number of debits of the Wallet.
+    pub(crate) debit_version: u64,
+    /// The credits received by the Wallet.
+    pub(crate) credit_ids: HashSet<CreditId>,
+    /// The conditional credits of the Wallet, not yet released or refunded.
+    pub(crate) locked: BTreeMap<CreditId, LockedCredit>,
+    /// For multisig validations.
+    pub(crate) pending_proposals: HashMap<u64, HashMap<usize, TransferValidationProposed>>,
+    /// The last validated debit.
+    pub(crate) pending_debit: Option<u64>,
+    /// Validated transfers not yet registered, by debit counter.
+    pub(crate) pending_transfers: BTreeMap<u64, SignedTransfer>,
+    /// Validated batch transfers not yet registered, by debit counter.
+    pub(crate) pending_batches: BTreeMap<u64, SignedBatchTransfer>,
+    /// The registered transfers, and batches, by debit counter.
+    pub(crate) registered_transfers: BTreeMap<u64, SignedBatchTransfer>,
+    /// The evidence of equivocation by the Actor, if the wallet is frozen.
+    pub(crate) equivocation: Option<EquivocationProof>,
+    /// The spending policy of a multisig Wallet, if declared.
+    pub(crate) spending_policy: Option<SpendingPolicy>,
+    /// The validated debits of a Wallet with a spending policy, by debit counter.
+    pub(crate) spends: BTreeMap<u64, Spend>,
+}
+
+impl WalletReplicaSnapshot {
+    /// The snapshot as bytes, prefixed by the format version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        to_versioned_bytes(REPLICA_SNAPSHOT_VERSION, self)
+    }
+
+    /// The snapshot from its bytes, decoded as per the format version they are prefixed by.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        // A change of the format adds the decoder of the previous version here.
+        match split_version(bytes)? {
+            (REPLICA_SNAPSHOT_VERSION, snapshot) => deserialise(snapshot),
+            (version, _) => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+
+    /// The key of the Wallet.
+    pub fn wallet_key(&self) -> PublicKey {
+        OwnerType::from(self.id.clone()).public_key()
+    }
+}
+
+impl fmt::Debug for WalletReplicaSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "WalletReplicaSnapshot {{ wallet: {:?}, replica_id: {:?}, balance: {:?}, debit_version: {:?}, pending_debit: {:?} }}",
+            self.wallet_key(),
+            self.replica_id,
+            self.balance,
+            self.debit_version,
+            self.pending_debit
+        )
+    }
+}
+
+/// A serialisable mirror of OwnerType.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SnapshotOwner {
+    Single(PublicKey),
+    Multi(PublicKeySet),
+}
+
+impl From<OwnerType> for SnapshotOwner {
+    fn from(owner: OwnerType) -> Self {
+        match owner {
+            OwnerType::Single(key) => Self::Single(key),
+            OwnerType::Multi(key_set) => Self::Multi(key_set),
+        }
+    }
+}
+
+impl From<SnapshotOwner> for OwnerType {
+    fn from(owner: SnapshotOwner) -> Self {
+        match owner {
+            SnapshotOwner::Single(key) => Self::Single(key),
+            SnapshotOwner::Multi(key_set) => Self::Multi(key_set),
+        }
+    }
+}
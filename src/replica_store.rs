@@ -0,0 +1,1404 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    batch::{BatchTransferAgreementProof, BatchTransferRegistered, BatchTransferValidated},
+    equivocation::EquivocationProof,
+    escrow::{EscrowRefunded, EscrowReleased},
+    fees::FeePolicy,
+    htlc::HtlcClaimed,
+    known_sections::KnownSections,
+    multisig_actor::MultisigTransfer,
+    replica_signing::ReplicaSigning,
+    replica_snapshot::WalletReplicaSnapshot,
+    spending_policy::{
+        MultisigBatchValidated, MultisigTransferValidated, SpendingPolicy, SpendingPolicyDeclared,
+    },
+    wallet_replica::WalletReplica,
+    EquivocationDetected, FeeCredited, Outcome, WalletReplicaEvent,
+};
+use crate::{Error, Result};
+use sn_data_types::{
+    CreditAgreementProof, CreditId, OwnerType, PublicKey, ReplicaEvent, Signature, SignatureShare,
+    SignedCredit, SignedDebit, SignedTransferShare, Token, TransferAgreementProof,
+    TransferRegistered, TransferValidated, TransferValidationProposed,
+};
+use std::collections::HashMap;
+use std::fmt;
+use threshold_crypto::{PublicKeySet, PublicKeyShare};
+
+/// The store of all the wallets that a Replica
+/// validates transfers for, in its section.
+/// Cmds and events are routed to the WalletReplica of the
+/// key they concern, and a wallet is created at its first credit.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TransferReplicaStore {
+    /// The public key share of this Replica.
+    replica_id: PublicKeyShare,
+    /// The index of this Replica key share, in the group set.
+    key_index: usize,
+    /// The PK set of our peer Replicas.
+    peer_replicas: PublicKeySet,
+    /// The wallets of this Replica, by key.
+    wallets: HashMap<PublicKey, WalletReplica>,
+    /// The fee policy of the section.
+    fee_policy: FeePolicy,
+    /// The wallet of the section, to which fees are credited.
+    fee_wallet: Option<PublicKey>,
+    /// The section time, by which conditional credits are unlocked.
+    epoch: u64,
+}
+
+impl TransferReplicaStore {
+    /// A new, empty, store.
+    pub fn new(replica_id: PublicKeyShare, key_index: usize, peer_replicas: PublicKeySet) -> Self {
+        Self {
+            replica_id,
+            key_index,
+            peer_replicas,
+            wallets: Default::default(),
+            fee_policy: Default::default(),
+            fee_wallet: None,
+            epoch: 0,
+        }
+    }
+
+    /// A store restored from a history of events,
+    /// such as those replayed from an `EventLog` of the section.
+    pub fn from_history(
+        replica_id: PublicKeyShare,
+        key_index: usize,
+        peer_replicas: PublicKeySet,
+        events: Vec<ReplicaEvent>,
+    ) -> Result<Self> {
+        let mut instance = Self::new(replica_id, key_index, peer_replicas);
+        for e in events {
+            instance.apply(e)?;
+        }
+        Ok(instance)
+    }
+
+    /// A store restored from the snapshots of its wallets.
+    pub fn from_snapshots(
+        replica_id: PublicKeyShare,
+        key_index: usize,
+        peer_replicas: PublicKeySet,
+        snapshots: Vec<WalletReplicaSnapshot>,
+    ) -> Result<Self> {
+        let mut instance = Self::new(replica_id, key_index, peer_replicas);
+        for snapshot in snapshots {
+            instance.insert(WalletReplica::from_replica_snapshot(snapshot)?)?;
+        }
+        Ok(instance)
+    }
+
+    /// Adds a wallet, such as one restored from its history.
+    pub fn insert(&mut self, mut wallet: WalletReplica) -> Result<()> {
+        let key = wallet.id().public_key();
+        if self.wallets.contains_key(&key) {
+            return Err(Error::KeyExists);
+        }
+        wallet.set_fee_policy(self.fee_policy);
+        wallet.set_epoch(self.epoch);
+        let _ = self.wallets.insert(key, wallet);
+        Ok(())
+    }
+
+    /// Adds a new, empty, wallet for the owner.
+    /// Used for wallets which are not to be created by a
+    /// first credit, such as those with multiple owners.
+    pub fn add_wallet(&mut self, id: OwnerType) -> Result<()> {
+        let wallet = self.new_wallet(id)?;
+        self.insert(wallet)
+    }
+
+    /// Sets the fee policy of the section, for all wallets,
+    /// and the wallet to which the fees are credited.
+    pub fn set_fees(&mut self, fee_policy: FeePolicy, fee_wallet: PublicKey) {
+        self.fee_policy = fee_policy;
+        self.fee_wallet = Some(fee_wallet);
+        for wallet in self.wallets.values_mut() {
+            wallet.set_fee_policy(fee_policy);
+        }
+    }
+
+    /// Sets the section time, for all wallets.
+    pub fn set_epoch(&mut self, epoch: u64) {
+        self.epoch = epoch;
+        for wallet in self.wallets.values_mut() {
+            wallet.set_epoch(epoch);
+        }
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Queries ----------------------------------
+    /// -----------------------------------------------------------------
+
+    /// Whether there is a wallet for the key.
+    pub fn contains(&self, key: &PublicKey) -> bool {
+        self.wallets.contains_key(key)
+    }
+
+    /// The wallet of the key.
+    pub fn get(&self, key: &PublicKey) -> Option<&WalletReplica> {
+        self.wallets.get(key)
+    }
+
+    /// The keys of all the wallets in the store.
+    pub fn keys(&self) -> impl Iterator<Item = &PublicKey> {
+        self.wallets.keys()
+    }
+
+    /// The balance of the wallet of the key.
+    pub fn balance(&self, key: &PublicKey) -> Result<Token> {
+        Ok(self.wallet(key)?.balance())
+    }
+
+    /// Full snapshots of the state of all the wallets in the store.
+    pub fn snapshot(&self) -> Vec<WalletReplicaSnapshot> {
+        self.wallets.values().map(WalletReplica::snapshot).collect()
+    }
+
+    /// The sum of the balances of all the wallets in the store.
+    pub fn total_supply(&self) -> Result<Token> {
+        self.wallets
+            .values()
+            .try_fold(Token::zero(), |total, wallet| {
+                total
+                    .checked_add(wallet.balance())
+                    .ok_or_else(|| Error::AdditionOverflow(total, wallet.balance()))
+            })
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Cmds -------------------------------------
+    /// -----------------------------------------------------------------
+
+    /// This is the one and only infusion of money to the system. Ever.
+    /// See `WalletReplica::genesis`.
+    pub fn genesis<K: KnownSections>(
+        &self,
+        credit_proof: &CreditAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<()> {
+        match self.wallets.get(&credit_proof.recipient()) {
+            Some(wallet) => wallet.genesis(credit_proof, known_sections),
+            None => self
+                .new_wallet(OwnerType::Single(credit_proof.recipient()))?
+                .genesis(credit_proof, known_sections),
+        }
+    }
+
+    /// Step 0. Proposal of a transfer share by an owner of a multisig wallet, at the wallet.
+    /// See `WalletReplica::propose_validation`.
+    pub fn propose_validation(
+        &self,
+        signed_transfer: &SignedTransferShare,
+    ) -> Outcome<TransferValidationProposed> {
+        self.wallet(&signed_transfer.debit().sender())?
+            .propose_validation(signed_transfer)
+    }
+
+    /// Step 1. Validation of a debit, at the wallet of the sender.
+    /// See `WalletReplica::validate`.
+    pub fn validate(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+    ) -> Outcome<()> {
+        self.wallet(&signed_debit.sender())?
+            .validate(signed_debit, signed_credit)
+    }
+
+    /// Step 1, followed by the signing of the validated transfer.
+    /// See `WalletReplica::validate_and_sign`.
+    pub fn validate_and_sign<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        signing: &R,
+    ) -> Outcome<TransferValidated> {
+        self.wallet(&signed_debit.sender())?
+            .validate_and_sign(signed_debit, signed_credit, signing)
+    }
+
+    /// Step 1, for a batch, at the wallet of the sender.
+    /// See `WalletReplica::validate_and_sign_batch`.
+    pub fn validate_and_sign_batch<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        signing: &R,
+    ) -> Outcome<BatchTransferValidated> {
+        self.wallet(&signed_debit.sender())?
+            .validate_and_sign_batch(signed_debit, signed_credits, signing)
+    }
+
+    /// Step 1, for a transfer of a multisig wallet, at the wallet.
+    /// See `WalletReplica::validate_and_sign_multisig`.
+    pub fn validate_and_sign_multisig<R: ReplicaSigning>(
+        &self,
+        transfer: &MultisigTransfer,
+        signing: &R,
+    ) -> Outcome<MultisigTransferValidated> {
+        self.wallet(&transfer.signed_transfer.debit.sender())?
+            .validate_and_sign_multisig(transfer, signing)
+    }
+
+    /// Step 1, for a batch of a multisig wallet, at the wallet.
+    /// See `WalletReplica::validate_and_sign_multisig_batch`.
+    pub fn validate_and_sign_multisig_batch<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        debit_shares: &[SignatureShare],
+        signing: &R,
+    ) -> Outcome<MultisigBatchValidated> {
+        self.wallet(&signed_debit.sender())?
+            .validate_and_sign_multisig_batch(signed_debit, signed_credits, debit_shares, signing)
+    }
+
+    /// Step 2. Registration of an agreed transfer, at the wallet of the sender.
+    /// See `WalletReplica::register`.
+    pub fn register(&self, transfer_proof: &TransferAgreementProof) -> Outcome<TransferRegistered> {
+        self.wallet(&transfer_proof.sender())?
+            .register(transfer_proof)
+    }
+
+    /// Step 2, for a batch, at the wallet of the sender.
+    /// See `WalletReplica::register_batch`.
+    pub fn register_batch(
+        &self,
+        transfer_proof: &BatchTransferAgreementProof,
+    ) -> Outcome<BatchTransferRegistered> {
+        self.wallet(&transfer_proof.sender())?
+            .register_batch(transfer_proof)
+    }
+
+    /// Detection of equivocation, at the wallet of the sender.
+    /// See `WalletReplica::detect_equivocation`.
+    pub fn detect_equivocation(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+    ) -> Outcome<EquivocationDetected> {
+        self.wallet(&signed_debit.sender())?
+            .detect_equivocation(signed_debit, signed_credits)
+    }
+
+    /// Receipt of evidence of equivocation, at the wallet of the sender.
+    /// See `WalletReplica::receive_equivocation_proof`.
+    pub fn receive_equivocation_proof(
+        &self,
+        proof: &EquivocationProof,
+    ) -> Outcome<EquivocationDetected> {
+        self.wallet(&proof.sender())?
+            .receive_equivocation_proof(proof)
+    }
+
+    /// Release of an escrowed credit, at the wallet holding it.
+    /// See `WalletReplica::release_escrow`.
+    pub fn release_escrow(
+        &self,
+        wallet: &PublicKey,
+        credit_id: &CreditId,
+        arbiter_signature: &Signature,
+    ) -> Outcome<EscrowReleased> {
+        self.wallet(wallet)?
+            .release_escrow(credit_id, arbiter_signature)
+    }
+
+    /// Claim of a hash-time-locked credit, at the wallet holding it.
+    /// See `WalletReplica::claim_htlc`.
+    pub fn claim_htlc<R: ReplicaSigning>(
+        &self,
+        wallet: &PublicKey,
+        credit_id: &CreditId,
+        preimage: &[u8],
+        signing: &R,
+    ) -> Outcome<HtlcClaimed> {
+        self.wallet(wallet)?
+            .claim_htlc(credit_id, preimage, signing)
+    }
+
+    /// Refund of an escrowed credit, at the wallet holding it.
+    /// See `WalletReplica::refund_escrow`.
+    pub fn refund_escrow<R: ReplicaSigning>(
+        &self,
+        wallet: &PublicKey,
+        credit_id: &CreditId,
+        refund_signature: &Signature,
+        signing: &R,
+    ) -> Outcome<EscrowRefunded> {
+        self.wallet(wallet)?
+            .refund_escrow(credit_id, refund_signature, signing)
+    }
+
+    /// Declaration of the spending policy of a multisig wallet, at the wallet.
+    /// See `WalletReplica::declare_spending_policy`.
+    pub fn declare_spending_policy(
+        &self,
+        wallet: &PublicKey,
+        policy: &SpendingPolicy,
+        signatures: &[SignatureShare],
+    ) -> Outcome<SpendingPolicyDeclared> {
+        self.wallet(wallet)?
+            .declare_spending_policy(policy, signatures)
+    }
+
+    /// Step 3. Receiving of a propagated credit, at the wallet of the recipient.
+    /// A recipient without a wallet in the store is validated as a new wallet,
+    /// which is created when the resulting event is applied.
+    /// See `WalletReplica::receive_propagated`.
+    pub fn receive_propagated<K: KnownSections>(
+        &self,
+        credit_proof: &CreditAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<()> {
+        match self.wallets.get(&credit_proof.recipient()) {
+            Some(wallet) => wallet.receive_propagated(credit_proof, known_sections),
+            None => self
+                .new_wallet(OwnerType::Single(credit_proof.recipient()))?
+                .receive_propagated(credit_proof, known_sections),
+        }
+    }
+
+    /// Crediting of the fee paid by a registered transfer, at the fee wallet.
+    pub fn receive_fee<K: KnownSections>(
+        &self,
+        transfer_proof: &TransferAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<FeeCredited> {
+        let key = self.fee_wallet.ok_or(Error::InvalidOperation)?;
+        match self.wallets.get(&key) {
+            Some(wallet) => wallet.receive_fee(transfer_proof, known_sections),
+            None => self
+                .new_wallet(OwnerType::Single(key))?
+                .receive_fee(transfer_proof, known_sections),
+        }
+    }
+
+    /// Crediting of the fee paid by a registered batch, at the fee wallet.
+    pub fn receive_batch_fee<K: KnownSections>(
+        &self,
+        transfer_proof: &BatchTransferAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<FeeCredited> {
+        let key = self.fee_wallet.ok_or(Error::InvalidOperation)?;
+        match self.wallets.get(&key) {
+            Some(wallet) => wallet.receive_batch_fee(transfer_proof, known_sections),
+            None => self
+                .new_wallet(OwnerType::Single(key))?
+                .receive_batch_fee(transfer_proof, known_sections),
+        }
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Mutation ---------------------------------
+    /// -----------------------------------------------------------------
+
+    /// Mutation of state.
+    /// The event is applied to the wallet it concerns.
+    /// A propagated credit to a key without a wallet creates the wallet.
+    pub fn apply(&mut self, event: ReplicaEvent) -> Result<()> {
+        let key = match &event {
+            ReplicaEvent::TransferValidationProposed(e) => e.sender(),
+            ReplicaEvent::TransferValidated(e) => e.sender(),
+            ReplicaEvent::TransferRegistered(e) => e.sender(),
+            ReplicaEvent::TransferPropagated(e) => {
+                let recipient = e.recipient();
+                if !self.wallets.contains_key(&recipient) {
+                    let wallet = self.new_wallet(OwnerType::Single(recipient))?;
+                    let _ = self.wallets.insert(recipient, wallet);
+                }
+                recipient
+            }
+        };
+        self.wallets
+            .get_mut(&key)
+            .ok_or(Error::WalletNotFound(key))?
+            .apply(event)
+    }
+
+    /// Mutation of state, by the events of this crate.
+    /// A batch, multisig or equivocation event is applied to the wallet of the sender,
+    /// an escrow or htlc event to the wallet holding the credit,
+    /// a spending policy to the wallet it is declared for,
+    /// and a fee credit to the fee wallet, which is created at its first fee.
+    pub fn apply_wallet_event(&mut self, event: WalletReplicaEvent) -> Result<()> {
+        let key = match &event {
+            WalletReplicaEvent::BatchTransferValidated(e) => e.sender(),
+            WalletReplicaEvent::BatchTransferRegistered(e) => e.sender(),
+            WalletReplicaEvent::MultisigTransferValidated(e) => e.validated.signed_debit.sender(),
+            WalletReplicaEvent::MultisigBatchValidated(e) => e.validated.sender(),
+            WalletReplicaEvent::EquivocationDetected(e) => e.sender(),
+            WalletReplicaEvent::EscrowReleased(e) => e.wallet,
+            WalletReplicaEvent::EscrowRefunded(e) => e.wallet,
+            WalletReplicaEvent::HtlcClaimed(e) => e.claim.wallet,
+            WalletReplicaEvent::SpendingPolicyDeclared(e) => e.wallet,
+            WalletReplicaEvent::FeeCredited(e) => {
+                let recipient = e.credit.recipient;
+                if !self.wallets.contains_key(&recipient) {
+                    let wallet = self.new_wallet(OwnerType::Single(recipient))?;
+                    let _ = self.wallets.insert(recipient, wallet);
+                }
+                recipient
+            }
+        };
+        self.wallets
+            .get_mut(&key)
+            .ok_or(Error::WalletNotFound(key))?
+            .apply_wallet_event(event)
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Private methods --------------------------
+    /// -----------------------------------------------------------------
+
+    fn wallet(&self, key: &PublicKey) -> Result<&WalletReplica> {
+        self.wallets.get(key).ok_or(Error::WalletNotFound(*key))
+    }
+
+    fn new_wallet(&self, id: OwnerType) -> Result<WalletReplica> {
+        let mut wallet = WalletReplica::from_history(
+            id,
+            self.replica_id,
+            self.key_index,
+            self.peer_replicas.clone(),
+            vec![],
+        )?;
+        wallet.set_fee_policy(self.fee_policy);
+        wallet.set_epoch(self.epoch);
+        Ok(wallet)
+    }
+}
+
+impl fmt::Debug for TransferReplicaStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TransferReplicaStore {{ replica_id: {:?}, key_index: {:?}, peer_replicas: PkSet {{ public_key: {:?} }}, wallets: {:?} }}",
+            self.replica_id,
+            self.key_index,
+            self.peer_replicas.public_key(),
+            self.wallets.keys().collect::<Vec<_>>()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TransferReplicaStore;
+    use crate::{
+        batch_credit_id, escrow_refund, hash_lock, refund_proof, test_utils::get_genesis,
+        CreditCondition, EquivocationProof, Error, EscrowRelease, FeePolicy, InMemoryKnownSections,
+        MultisigActor, MultisigTransfer, ReplicaSigning, ReplicaSigningImpl, Result,
+        SpendingPolicy, SpendingTier, Wallet, WalletReplicaEvent,
+    };
+    use sn_data_types::{
+        Credit, CreditAgreementProof, Debit, Keypair, OwnerType, PublicKey, ReplicaEvent,
+        Signature, SignatureShare, SignedCredit, SignedCreditShare, SignedDebit, SignedDebitShare,
+        SignedTransferShare, Signing, Token, TransferAgreementProof, TransferPropagated,
+        TransferValidated,
+    };
+    use std::collections::BTreeMap;
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn routes_transfer_to_wallets_and_creates_recipient() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+
+        // genesis creates the sender wallet
+        let sender = Keypair::new_ed25519(&mut rng);
+        let genesis = get_genesis(
+            100,
+            sender.public_key(),
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        store
+            .genesis(&genesis, &known_sections)?
+            .ok_or(Error::GenesisFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis,
+        }))?;
+        assert_eq!(store.balance(&sender.public_key())?, Token::from_nano(100));
+
+        // a transfer to a key without a wallet
+        let recipient = Keypair::new_ed25519(&mut rng).public_key();
+        let (signed_debit, signed_credit) = get_transfer(&sender, recipient, 10)?;
+        let validated = store
+            .validate_and_sign(&signed_debit, &signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply(ReplicaEvent::TransferValidated(validated.clone()))?;
+
+        let transfer_proof = TransferAgreementProof {
+            debit_sig: combine(&sk_set, validated.replica_debit_sig.share),
+            credit_sig: combine(&sk_set, validated.replica_credit_sig.share),
+            signed_debit,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+        let registered = store
+            .register(&transfer_proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        store.apply(ReplicaEvent::TransferRegistered(registered))?;
+
+        let credit_proof = transfer_proof.credit_proof();
+        assert!(!store.contains(&recipient));
+        store
+            .receive_propagated(&credit_proof, &known_sections)?
+            .ok_or(Error::ReceivePropagationFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof,
+        }))?;
+
+        assert!(store.contains(&recipient));
+        assert_eq!(store.balance(&sender.public_key())?, Token::from_nano(90));
+        assert_eq!(store.balance(&recipient)?, Token::from_nano(10));
+        assert_eq!(store.total_supply()?, Token::from_nano(100));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_debit_from_unknown_wallet() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+
+        let sender = Keypair::new_ed25519(&mut rng);
+        let recipient = Keypair::new_ed25519(&mut rng).public_key();
+        let (signed_debit, signed_credit) = get_transfer(&sender, recipient, 10)?;
+
+        let result = store.validate_and_sign(&signed_debit, &signed_credit, &signing);
+        assert_eq!(result, Err(Error::WalletNotFound(sender.public_key())));
+        assert_eq!(store.total_supply()?, Token::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_credit_proof_with_empty_key_set() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let store = TransferReplicaStore::new(
+            sk_set.public_keys().public_key_share(0),
+            0,
+            sk_set.public_keys(),
+        );
+
+        let recipient = Keypair::new_ed25519(&mut rng).public_key();
+        let mut credit_proof = get_genesis(
+            10,
+            recipient,
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        // as decoded from a message with no keys in the set
+        credit_proof.debiting_replicas_keys =
+            bincode::deserialize(&[0; 8]).map_err(|e| Error::Serialisation(e.to_string()))?;
+
+        let result = store.receive_propagated(&credit_proof, &known_sections);
+        assert_eq!(result, Err(Error::InvalidSignature));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_fee_proof_with_empty_key_set() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let mut store = TransferReplicaStore::new(
+            sk_set.public_keys().public_key_share(0),
+            0,
+            sk_set.public_keys(),
+        );
+        let fee_wallet = Keypair::new_ed25519(&mut rng).public_key();
+        store.set_fees(FeePolicy::Flat(Token::from_nano(1)), fee_wallet);
+
+        let sender = Keypair::new_ed25519(&mut rng);
+        let recipient = Keypair::new_ed25519(&mut rng).public_key();
+        let (signed_debit, signed_credit) = get_transfer(&sender, recipient, 10)?;
+        let signature = Signing::sign(&sender, &signed_debit)?;
+        let transfer_proof = TransferAgreementProof {
+            signed_debit,
+            signed_credit,
+            debit_sig: signature.clone(),
+            credit_sig: signature,
+            // as decoded from a message with no keys in the set
+            debiting_replicas_keys: bincode::deserialize(&[0; 8])
+                .map_err(|e| Error::Serialisation(e.to_string()))?,
+        };
+
+        let result = store.receive_fee(&transfer_proof, &known_sections);
+        assert_eq!(result, Err(Error::InvalidSignature));
+        Ok(())
+    }
+
+    #[test]
+    fn equivocation_freezes_wallet_at_all_replicas() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let sender = Keypair::new_ed25519(&mut rng);
+        let genesis = get_genesis(
+            100,
+            sender.public_key(),
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        let mut stores = vec![];
+        for _ in 0..2 {
+            let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+            store
+                .genesis(&genesis, &known_sections)?
+                .ok_or(Error::GenesisFailed)?;
+            store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+                credit_proof: genesis.clone(),
+            }))?;
+            stores.push(store);
+        }
+        let mut other = stores.remove(1);
+        let mut store = stores.remove(0);
+
+        // the same debit counter is signed twice, for the same amount, to different recipients
+        let first = Keypair::new_ed25519(&mut rng).public_key();
+        let second = Keypair::new_ed25519(&mut rng).public_key();
+        let (signed_debit, signed_credit) = get_transfer(&sender, first, 10)?;
+        let validated = store
+            .validate_and_sign(&signed_debit, &signed_credit, &signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply(ReplicaEvent::TransferValidated(validated))?;
+        let (conflicting_debit, conflicting_credit) = get_transfer(&sender, second, 10)?;
+        assert_eq!(conflicting_debit.debit, signed_debit.debit);
+        let result = store.validate_and_sign(&conflicting_debit, &conflicting_credit, &signing);
+        assert_eq!(result, Err(Error::DebitEquivocation));
+
+        let conflicting_credits = vec![conflicting_credit];
+        let detected = store
+            .detect_equivocation(&conflicting_debit, &conflicting_credits)?
+            .ok_or(Error::DebitEquivocation)?;
+        assert_eq!(detected.proof.first.credits, vec![signed_credit.clone()]);
+        assert_eq!(detected.proof.second.credits, conflicting_credits);
+        store.apply_wallet_event(WalletReplicaEvent::EquivocationDetected(detected.clone()))?;
+        assert!(store
+            .detect_equivocation(&conflicting_debit, &conflicting_credits)?
+            .is_none());
+        let result = store.validate_and_sign(&signed_debit, &signed_credit, &signing);
+        assert_eq!(result, Err(Error::WalletFrozen));
+
+        // the evidence is verified by, and freezes the wallet at, the other Replica
+        let bytes =
+            bincode::serialize(&detected.proof).map_err(|e| Error::Serialisation(e.to_string()))?;
+        let proof: EquivocationProof =
+            bincode::deserialize(&bytes).map_err(|e| Error::Serialisation(e.to_string()))?;
+        let received = other
+            .receive_equivocation_proof(&proof)?
+            .ok_or(Error::DebitEquivocation)?;
+        other.apply_wallet_event(WalletReplicaEvent::EquivocationDetected(received))?;
+        let frozen = other
+            .get(&sender.public_key())
+            .ok_or(Error::WalletNotFound(sender.public_key()))?;
+        assert_eq!(frozen.equivocation(), Some(&proof));
+        assert!(other.receive_equivocation_proof(&proof)?.is_none());
+        let result = other.validate_and_sign(&signed_debit, &signed_credit, &signing);
+        assert_eq!(result, Err(Error::WalletFrozen));
+        Ok(())
+    }
+
+    #[test]
+    fn equivocation_is_detected_after_registration() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+        let sender = Keypair::new_ed25519(&mut rng);
+        let genesis = get_genesis(
+            100,
+            sender.public_key(),
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        propagate(&mut store, &known_sections, genesis)?;
+
+        let first = Keypair::new_ed25519(&mut rng).public_key();
+        let second = Keypair::new_ed25519(&mut rng).public_key();
+        let (signed_debit, signed_credit) = get_transfer(&sender, first, 10)?;
+        let _ = register_transfer(&mut store, &signing, &sk_set, signed_debit, signed_credit)?;
+
+        // a re-sent transfer is out of order, but a conflicting one is equivocation
+        let (signed_debit, signed_credit) = get_transfer(&sender, first, 10)?;
+        let result = store.validate_and_sign(&signed_debit, &signed_credit, &signing);
+        assert_eq!(result, Err(Error::OperationOutOfOrder(0, 1)));
+        let (conflicting_debit, conflicting_credit) = get_transfer(&sender, second, 10)?;
+        let result = store.validate_and_sign(&conflicting_debit, &conflicting_credit, &signing);
+        assert_eq!(result, Err(Error::DebitEquivocation));
+        let detected = store
+            .detect_equivocation(&conflicting_debit, &[conflicting_credit])?
+            .ok_or(Error::DebitEquivocation)?;
+        detected.proof.verify()?;
+        assert_eq!(detected.proof.first.credits[0].recipient(), first);
+        assert_eq!(detected.proof.second.credits[0].recipient(), second);
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_credits_are_locked_until_met() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+        let sender = Keypair::new_ed25519(&mut rng);
+        let genesis = get_genesis(
+            100,
+            sender.public_key(),
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        store
+            .genesis(&genesis, &known_sections)?
+            .ok_or(Error::GenesisFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis,
+        }))?;
+
+        // a time-locked credit cannot be debited before its epoch
+        let saver = Keypair::new_ed25519(&mut rng);
+        let time_lock = CreditCondition::TimeLock { unlock_epoch: 5 };
+        let msg = time_lock.encode("savings")?;
+        let (debit, credit) = get_transfer_with(&sender, 0, saver.public_key(), 10, msg)?;
+        complete_transfer(
+            &mut store,
+            &signing,
+            &sk_set,
+            &known_sections,
+            debit,
+            credit,
+        )?;
+        let (debit, credit) = get_transfer(&saver, sender.public_key(), 5)?;
+        let result = store.validate_and_sign(&debit, &credit, &signing);
+        assert_eq!(result, Err(Error::InsufficientBalance));
+        store.set_epoch(5);
+        assert!(store
+            .validate_and_sign(&debit, &credit, &signing)?
+            .is_some());
+
+        // an escrowed credit is released by its arbiter
+        let seller = Keypair::new_ed25519(&mut rng);
+        let arbiter = Keypair::new_ed25519(&mut rng);
+        let escrow = CreditCondition::Escrow {
+            arbiter: arbiter.public_key(),
+            refund_to: sender.public_key(),
+            refund_epoch: 8,
+        };
+        let msg = escrow.encode("goods")?;
+        let (debit, credit) = get_transfer_with(&sender, 1, seller.public_key(), 20, msg)?;
+        let released_id = *credit.id();
+        complete_transfer(
+            &mut store,
+            &signing,
+            &sk_set,
+            &known_sections,
+            debit,
+            credit,
+        )?;
+        let release = EscrowRelease {
+            credit_id: released_id,
+        };
+        let forged = Signing::sign(&seller, &release)?;
+        let result = store.release_escrow(&seller.public_key(), &released_id, &forged);
+        assert_eq!(result, Err(Error::InvalidSignature));
+        let signature = Signing::sign(&arbiter, &release)?;
+        let released = store
+            .release_escrow(&seller.public_key(), &released_id, &signature)?
+            .ok_or(Error::InvalidOperation)?;
+        store.apply_wallet_event(WalletReplicaEvent::EscrowReleased(released))?;
+        let wallet = store
+            .get(&seller.public_key())
+            .ok_or(Error::WalletNotFound(seller.public_key()))?;
+        assert_eq!(wallet.locked_balance(), Token::zero());
+
+        // an escrowed credit not released is refunded at the refund epoch
+        let msg = escrow.encode("more goods")?;
+        let (debit, credit) = get_transfer_with(&sender, 2, seller.public_key(), 30, msg)?;
+        let refunded_id = *credit.id();
+        complete_transfer(
+            &mut store,
+            &signing,
+            &sk_set,
+            &known_sections,
+            debit,
+            credit,
+        )?;
+        assert_eq!(store.balance(&sender.public_key())?, Token::from_nano(40));
+        let wallet = store
+            .get(&seller.public_key())
+            .ok_or(Error::WalletNotFound(seller.public_key()))?;
+        assert_eq!(wallet.locked_balance(), Token::from_nano(30));
+        let locked = wallet
+            .wallet()
+            .and_then(|w| w.locked.get(&refunded_id).cloned())
+            .ok_or(Error::InvalidOperation)?;
+        let refund = escrow_refund(&refunded_id, &locked)?;
+        let signature = Signing::sign(&sender, &refund)?;
+        let result = store.refund_escrow(&seller.public_key(), &refunded_id, &signature, &signing);
+        assert_eq!(result, Err(Error::InvalidOperation));
+        store.set_epoch(8);
+        // nor can it be released at, or after, the refund epoch
+        let release = EscrowRelease {
+            credit_id: refunded_id,
+        };
+        let arbiter_signature = Signing::sign(&arbiter, &release)?;
+        let result = store.release_escrow(&seller.public_key(), &refunded_id, &arbiter_signature);
+        assert_eq!(result, Err(Error::InvalidOperation));
+        let refunded = store
+            .refund_escrow(&seller.public_key(), &refunded_id, &signature, &signing)?
+            .ok_or(Error::InvalidOperation)?;
+        store.apply_wallet_event(WalletReplicaEvent::EscrowRefunded(refunded.clone()))?;
+        assert_eq!(store.balance(&seller.public_key())?, Token::from_nano(20));
+
+        let credit_proof = refund_proof(&[refunded])?.ok_or(Error::CannotAggregate)?;
+        store
+            .receive_propagated(&credit_proof, &known_sections)?
+            .ok_or(Error::ReceivePropagationFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof,
+        }))?;
+        assert_eq!(store.balance(&sender.public_key())?, Token::from_nano(70));
+        assert_eq!(store.total_supply()?, Token::from_nano(100));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_time_locked_transfers_swap_across_sections() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let mut sections = vec![];
+        let mut owners = vec![];
+        for _ in 0..2 {
+            let sk_set = SecretKeySet::random(0, &mut rng);
+            let signing =
+                ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+            let known_sections =
+                InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+            let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+            let owner = Keypair::new_ed25519(&mut rng);
+            let genesis = get_genesis(
+                100,
+                owner.public_key(),
+                sk_set.public_keys(),
+                sk_set.secret_key_share(0),
+            )?;
+            store
+                .genesis(&genesis, &known_sections)?
+                .ok_or(Error::GenesisFailed)?;
+            store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+                credit_proof: genesis,
+            }))?;
+            sections.push((store, signing, sk_set));
+            owners.push(owner);
+        }
+        let known_sections = InMemoryKnownSections::new(
+            sections
+                .iter()
+                .map(|(_, _, sk_set)| sk_set.public_keys().public_key()),
+        );
+        let (mut bob_store, bob_signing, bob_sk_set) = sections.remove(1);
+        let (mut alice_store, alice_signing, alice_sk_set) = sections.remove(0);
+        let bob = owners.remove(1);
+        let alice = owners.remove(0);
+
+        // Alice, who knows the secret, locks her tokens with the later deadline.
+        let secret = b"swap secret";
+        let condition = CreditCondition::HashLock {
+            hash: hash_lock(secret),
+            refund_to: alice.public_key(),
+            deadline_epoch: 10,
+        };
+        let msg = condition.encode("swap")?;
+        let (debit, credit) = get_transfer_with(&alice, 0, bob.public_key(), 10, msg)?;
+        let to_bob = *credit.id();
+        let proof = register_transfer(
+            &mut alice_store,
+            &alice_signing,
+            &alice_sk_set,
+            debit,
+            credit,
+        )?;
+        propagate(&mut bob_store, &known_sections, proof.credit_proof())?;
+
+        // Bob locks his tokens with the same hash, and an earlier deadline.
+        let condition = CreditCondition::HashLock {
+            hash: hash_lock(secret),
+            refund_to: bob.public_key(),
+            deadline_epoch: 5,
+        };
+        let msg = condition.encode("swap")?;
+        let (debit, credit) = get_transfer_with(&bob, 0, alice.public_key(), 20, msg)?;
+        let to_alice = *credit.id();
+        let proof = register_transfer(&mut bob_store, &bob_signing, &bob_sk_set, debit, credit)?;
+        propagate(&mut alice_store, &known_sections, proof.credit_proof())?;
+
+        // Alice claims, revealing the secret.
+        let result =
+            alice_store.claim_htlc(&alice.public_key(), &to_alice, b"guess", &alice_signing);
+        assert_eq!(result, Err(Error::InvalidPreimage));
+        let claimed = alice_store
+            .claim_htlc(&alice.public_key(), &to_alice, secret, &alice_signing)?
+            .ok_or(Error::InvalidOperation)?;
+        alice_store.apply_wallet_event(WalletReplicaEvent::HtlcClaimed(claimed.clone()))?;
+
+        // Bob claims with the revealed secret, before his deadline.
+        let mut late = bob_store.clone();
+        late.set_epoch(10);
+        let preimage = claimed.claim.preimage;
+        let result = late.claim_htlc(&bob.public_key(), &to_bob, &preimage, &bob_signing);
+        assert_eq!(result, Err(Error::InvalidOperation));
+        let claimed = bob_store
+            .claim_htlc(&bob.public_key(), &to_bob, &preimage, &bob_signing)?
+            .ok_or(Error::InvalidOperation)?;
+        bob_store.apply_wallet_event(WalletReplicaEvent::HtlcClaimed(claimed))?;
+
+        for (store, key, balance) in [
+            (&alice_store, alice.public_key(), 110),
+            (&bob_store, bob.public_key(), 90),
+        ] {
+            let wallet = store.get(&key).ok_or(Error::WalletNotFound(key))?;
+            assert_eq!(wallet.balance(), Token::from_nano(balance));
+            assert_eq!(wallet.locked_balance(), Token::zero());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spending_policy_decides_the_signers_needed() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+
+        // a wallet of three owners, any one of which can sign for the wallet key
+        let owners = SecretKeySet::random(0, &mut rng);
+        let wallet = PublicKey::Bls(owners.public_keys().public_key());
+        store.add_wallet(OwnerType::Multi(owners.public_keys()))?;
+        let genesis = get_genesis(
+            100,
+            wallet,
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        store
+            .genesis(&genesis, &known_sections)?
+            .ok_or(Error::GenesisFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis,
+        }))?;
+
+        // a single owner may spend up to 10 within 5 epochs, above that needs two
+        let policy = SpendingPolicy::new(
+            5,
+            vec![
+                SpendingTier {
+                    signers: 1,
+                    limit: Token::from_nano(10),
+                },
+                SpendingTier {
+                    signers: 2,
+                    limit: Token::from_nano(u64::MAX),
+                },
+            ],
+        )?;
+        let policy_bytes = bincode::serialize(&policy).unwrap();
+        let sign_policy = |index| SignatureShare {
+            index,
+            share: owners.secret_key_share(index).sign(&policy_bytes),
+        };
+        assert_eq!(
+            store.declare_spending_policy(&wallet, &policy, &[sign_policy(0)]),
+            Err(Error::SpendingPolicyNotMet)
+        );
+        let declared = store
+            .declare_spending_policy(&wallet, &policy, &[sign_policy(0), sign_policy(2)])?
+            .ok_or(Error::InvalidOperation)?;
+        store.apply_wallet_event(WalletReplicaEvent::SpendingPolicyDeclared(declared))?;
+
+        let recipient = Keypair::new_ed25519(&mut rng).public_key();
+        let propose = |store: &mut TransferReplicaStore, index, counter| -> Result<_> {
+            let share = get_transfer_share(&owners, index, counter, recipient, 6)?;
+            let proposed = store
+                .propose_validation(&share)?
+                .ok_or(Error::InvalidOperation)?;
+            store.apply(ReplicaEvent::TransferValidationProposed(proposed.clone()))?;
+            Ok(proposed.agreed_transfer)
+        };
+
+        // within the limit of a single owner
+        let agreed = propose(&mut store, 0, 0)?.ok_or(Error::InvalidOperation)?;
+        let _ = register_transfer(&mut store, &signing, &sk_set, agreed.debit, agreed.credit)?;
+
+        // above it, the same owner needs another, even though its share signs for the wallet key
+        assert_eq!(propose(&mut store, 0, 1)?, None);
+        let (signed_debit, signed_credit) = get_transfer_signed_by(&owners, 1, recipient, 6)?;
+        assert_eq!(
+            store.validate(&signed_debit, &signed_credit),
+            Err(Error::SpendingPolicyNotMet)
+        );
+        let agreed = propose(&mut store, 1, 1)?.ok_or(Error::InvalidOperation)?;
+        let _ = register_transfer(&mut store, &signing, &sk_set, agreed.debit, agreed.credit)?;
+
+        // until the window has passed
+        assert_eq!(propose(&mut store, 0, 2)?, None);
+        store.set_epoch(5);
+        let (signed_debit, signed_credit) = get_transfer_signed_by(&owners, 2, recipient, 6)?;
+        let _ = register_transfer(&mut store, &signing, &sk_set, signed_debit, signed_credit)?;
+        assert_eq!(store.balance(&wallet)?, Token::from_nano(82));
+        Ok(())
+    }
+
+    #[test]
+    fn multisig_actor_meets_spending_policy() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let signing = ReplicaSigningImpl::new(sk_set.secret_key_share(0), 0, sk_set.public_keys());
+        let known_sections = InMemoryKnownSections::new(vec![sk_set.public_keys().public_key()]);
+        let mut store = TransferReplicaStore::new(signing.id(), 0, sk_set.public_keys());
+
+        // a wallet of three owners, any one of which can sign for the wallet key
+        let owners = SecretKeySet::random(0, &mut rng);
+        let wallet = PublicKey::Bls(owners.public_keys().public_key());
+        store.add_wallet(OwnerType::Multi(owners.public_keys()))?;
+        let genesis = get_genesis(
+            100,
+            wallet,
+            sk_set.public_keys(),
+            sk_set.secret_key_share(0),
+        )?;
+        store
+            .genesis(&genesis, &known_sections)?
+            .ok_or(Error::GenesisFailed)?;
+        let mut owned = Wallet::new(OwnerType::Multi(owners.public_keys()));
+        owned.apply_credit(genesis.signed_credit.credit.clone())?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: genesis,
+        }))?;
+
+        // a single owner may spend up to 10 within 5 epochs, above that needs two
+        let policy = SpendingPolicy::new(
+            5,
+            vec![
+                SpendingTier {
+                    signers: 1,
+                    limit: Token::from_nano(10),
+                },
+                SpendingTier {
+                    signers: 2,
+                    limit: Token::from_nano(u64::MAX),
+                },
+            ],
+        )?;
+        let policy_bytes = bincode::serialize(&policy).unwrap();
+        let sign_policy = |index| SignatureShare {
+            index,
+            share: owners.secret_key_share(index).sign(&policy_bytes),
+        };
+        let declared = store
+            .declare_spending_policy(&wallet, &policy, &[sign_policy(0), sign_policy(2)])?
+            .ok_or(Error::InvalidOperation)?;
+        store.apply_wallet_event(WalletReplicaEvent::SpendingPolicyDeclared(declared))?;
+
+        let mut co_owners = (0..3)
+            .map(|index| {
+                let keypair = Keypair::new_bls_share(
+                    index,
+                    owners.secret_key_share(index),
+                    owners.public_keys(),
+                );
+                MultisigActor::new(keypair, 3, owned.clone())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let recipient = Keypair::new_ed25519(&mut rng).public_key();
+
+        // within the limit of a single owner, its proposal alone is agreed and validated
+        let proposed = co_owners[0]
+            .propose(Token::from_nano(6), recipient, "".to_string())?
+            .ok_or(Error::InvalidOperation)?;
+        let agreed = proposed
+            .agreed_transfer
+            .clone()
+            .ok_or(Error::InvalidOperation)?;
+        co_owners[0].apply(proposed)?;
+        let _ = register_multisig(&mut store, &signing, &sk_set, &agreed)?;
+
+        // above it, the owner needs another
+        let proposed = co_owners[0]
+            .propose(Token::from_nano(6), recipient, "".to_string())?
+            .ok_or(Error::InvalidOperation)?;
+        let agreed = proposed
+            .agreed_transfer
+            .clone()
+            .ok_or(Error::InvalidOperation)?;
+        assert_eq!(
+            store.validate_and_sign_multisig(&agreed, &signing),
+            Err(Error::SpendingPolicyNotMet)
+        );
+        // which cannot be claimed without a share of the debit
+        let mut forged = agreed.clone();
+        forged.debit_shares.push(SignatureShare {
+            index: 1,
+            share: owners.secret_key_share(1).sign(b"not the debit"),
+        });
+        assert_eq!(
+            store.validate_and_sign_multisig(&forged, &signing),
+            Err(Error::InvalidSignature)
+        );
+        let id = proposed.signed_transfer.id();
+        let received = co_owners[1]
+            .receive_share(proposed.signed_transfer)?
+            .ok_or(Error::InvalidOperation)?;
+        co_owners[1].apply(received)?;
+        let co_signed = co_owners[1].co_sign(&id)?.ok_or(Error::InvalidOperation)?;
+        let agreed = co_signed
+            .agreed_transfer
+            .clone()
+            .ok_or(Error::InvalidOperation)?;
+        assert_eq!(agreed.signers(), vec![0, 1].into_iter().collect());
+        co_owners[1].apply(co_signed)?;
+        assert_eq!(co_owners[1].co_sign(&id)?, None);
+        let _ = register_multisig(&mut store, &signing, &sk_set, &agreed)?;
+        assert_eq!(store.balance(&wallet)?, Token::from_nano(88));
+
+        // a batch is held to the policy the same way
+        let debit = Debit {
+            id: crdts::Dot::new(wallet, 2),
+            amount: Token::from_nano(6),
+        };
+        let credit = Credit {
+            id: batch_credit_id(&debit.id, 0)?,
+            recipient,
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        let share = |index, item: &[u8]| SignatureShare {
+            index,
+            share: owners.secret_key_share(index).sign(item),
+        };
+        let debit_bytes = bincode::serialize(&debit).unwrap();
+        let credit_bytes = bincode::serialize(&credit).unwrap();
+        let signed_debit = SignedDebit {
+            actor_signature: combine(&owners, share(0, &debit_bytes).share),
+            debit,
+        };
+        let signed_credits = vec![SignedCredit {
+            actor_signature: combine(&owners, share(0, &credit_bytes).share),
+            credit,
+        }];
+        assert_eq!(
+            store.validate_and_sign_batch(&signed_debit, &signed_credits, &signing),
+            Err(Error::SpendingPolicyNotMet)
+        );
+        let debit_shares = [share(0, &debit_bytes), share(2, &debit_bytes)];
+        let validated = store
+            .validate_and_sign_multisig_batch(
+                &signed_debit,
+                &signed_credits,
+                &debit_shares,
+                &signing,
+            )?
+            .ok_or(Error::ValidationFailed)?;
+        assert_eq!(validated.signers, vec![0, 2].into_iter().collect());
+        store.apply_wallet_event(WalletReplicaEvent::MultisigBatchValidated(validated))?;
+        Ok(())
+    }
+
+    fn complete_transfer(
+        store: &mut TransferReplicaStore,
+        signing: &ReplicaSigningImpl,
+        sk_set: &SecretKeySet,
+        known_sections: &InMemoryKnownSections,
+        signed_debit: SignedDebit,
+        signed_credit: SignedCredit,
+    ) -> Result<()> {
+        let transfer_proof =
+            register_transfer(store, signing, sk_set, signed_debit, signed_credit)?;
+        propagate(store, known_sections, transfer_proof.credit_proof())
+    }
+
+    fn register_transfer(
+        store: &mut TransferReplicaStore,
+        signing: &ReplicaSigningImpl,
+        sk_set: &SecretKeySet,
+        signed_debit: SignedDebit,
+        signed_credit: SignedCredit,
+    ) -> Result<TransferAgreementProof> {
+        let validated = store
+            .validate_and_sign(&signed_debit, &signed_credit, signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply(ReplicaEvent::TransferValidated(validated.clone()))?;
+        register_validated(store, sk_set, validated)
+    }
+
+    fn register_multisig(
+        store: &mut TransferReplicaStore,
+        signing: &ReplicaSigningImpl,
+        sk_set: &SecretKeySet,
+        transfer: &MultisigTransfer,
+    ) -> Result<TransferAgreementProof> {
+        let validated = store
+            .validate_and_sign_multisig(transfer, signing)?
+            .ok_or(Error::ValidationFailed)?;
+        store.apply_wallet_event(WalletReplicaEvent::MultisigTransferValidated(
+            validated.clone(),
+        ))?;
+        register_validated(store, sk_set, validated.validated)
+    }
+
+    fn register_validated(
+        store: &mut TransferReplicaStore,
+        sk_set: &SecretKeySet,
+        validated: TransferValidated,
+    ) -> Result<TransferAgreementProof> {
+        let transfer_proof = TransferAgreementProof {
+            debit_sig: combine(sk_set, validated.replica_debit_sig.share),
+            credit_sig: combine(sk_set, validated.replica_credit_sig.share),
+            signed_debit: validated.signed_debit,
+            signed_credit: validated.signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+        let registered = store
+            .register(&transfer_proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        store.apply(ReplicaEvent::TransferRegistered(registered))?;
+        Ok(transfer_proof)
+    }
+
+    fn propagate(
+        store: &mut TransferReplicaStore,
+        known_sections: &InMemoryKnownSections,
+        credit_proof: CreditAgreementProof,
+    ) -> Result<()> {
+        store
+            .receive_propagated(&credit_proof, known_sections)?
+            .ok_or(Error::ReceivePropagationFailed)?;
+        store.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof,
+        }))
+    }
+
+    fn get_transfer(
+        sender: &Keypair,
+        recipient: PublicKey,
+        amount: u64,
+    ) -> Result<(SignedDebit, SignedCredit)> {
+        get_transfer_with(sender, 0, recipient, amount, "asdf".to_string())
+    }
+
+    fn get_transfer_with(
+        sender: &Keypair,
+        counter: u64,
+        recipient: PublicKey,
+        amount: u64,
+        msg: String,
+    ) -> Result<(SignedDebit, SignedCredit)> {
+        let debit = Debit {
+            id: crdts::Dot::new(sender.public_key(), counter),
+            amount: Token::from_nano(amount),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient,
+            amount: debit.amount(),
+            msg,
+        };
+        let signed_debit = SignedDebit {
+            actor_signature: Signing::sign(sender, &debit)?,
+            debit,
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: Signing::sign(sender, &credit)?,
+            credit,
+        };
+        Ok((signed_debit, signed_credit))
+    }
+
+    fn get_transfer_share(
+        owners: &SecretKeySet,
+        index: usize,
+        counter: u64,
+        recipient: PublicKey,
+        amount: u64,
+    ) -> Result<SignedTransferShare> {
+        let (signed_debit, signed_credit) =
+            get_transfer_signed_by(owners, counter, recipient, amount)?;
+        let sign = |bytes: Vec<u8>| SignatureShare {
+            index,
+            share: owners.secret_key_share(index).sign(bytes),
+        };
+        let debit = SignedDebitShare {
+            actor_signature: sign(bincode::serialize(&signed_debit.debit).unwrap()),
+            debit: signed_debit.debit,
+        };
+        let credit = SignedCreditShare {
+            actor_signature: sign(bincode::serialize(&signed_credit.credit).unwrap()),
+            credit: signed_credit.credit,
+        };
+        Ok(SignedTransferShare::new(
+            debit,
+            credit,
+            owners.public_keys(),
+        )?)
+    }
+
+    /// A transfer of a multisig wallet, signed by the wallet key.
+    fn get_transfer_signed_by(
+        owners: &SecretKeySet,
+        counter: u64,
+        recipient: PublicKey,
+        amount: u64,
+    ) -> Result<(SignedDebit, SignedCredit)> {
+        let debit = Debit {
+            id: crdts::Dot::new(PublicKey::Bls(owners.public_keys().public_key()), counter),
+            amount: Token::from_nano(amount),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient,
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        // with a threshold of 0, the share of any owner combines into the signature
+        let sign = |bytes: Vec<u8>| {
+            let mut shares = BTreeMap::new();
+            let _ = shares.insert(0, owners.secret_key_share(0).sign(bytes));
+            Signature::Bls(owners.public_keys().combine_signatures(&shares).unwrap())
+        };
+        Ok((
+            SignedDebit {
+                actor_signature: sign(bincode::serialize(&debit).unwrap()),
+                debit,
+            },
+            SignedCredit {
+                actor_signature: sign(bincode::serialize(&credit).unwrap()),
+                credit,
+            },
+        ))
+    }
+
+    fn combine(sk_set: &SecretKeySet, share: threshold_crypto::SignatureShare) -> Signature {
+        let mut shares = BTreeMap::new();
+        let _ = shares.insert(0, share);
+        Signature::Bls(sk_set.public_keys().combine_signatures(&shares).unwrap())
+    }
+}
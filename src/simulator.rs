@@ -0,0 +1,1096 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A deterministic, in-process, simulation of a network of sections, for testing.
+//!
+//! The Elders of each section hold a [TransferReplicaStore] of the wallets of the section,
+//! and the clients an [Actor](crate::TransferActor) each. All the messages between them go
+//! through a queue, and are delivered one at a time, in an order which is either chosen
+//! by the simulation, from its seed, or by the test, so that any interleaving can be replayed.
+//! A transfer goes through its full lifecycle: validation at the Elders of the sender,
+//! registration of the agreed transfer, and propagation of the credit to the Elders of the
+//! recipient, which the recipient then synchs from.
+//!
+//! Messages rejected by their receiver are recorded, along with the error, as they would be
+//! by a node logging them, and do not stop the simulation.
+//!
+//! A client resends its transfers in flight, and its agreed transfers until their debits are
+//! applied by all the Elders of its section, and the Elders the credits they have propagated,
+//! until these are held by all the Elders of the recipient. Acknowledgements are not simulated:
+//! an agreed transfer, or a credit, is resent only to the Elders which do not hold it yet.
+//!
+//! Faults can be injected, both in the network, where sent messages are dropped, duplicated
+//! or delayed, with the probabilities of the [NetworkFaults], and at the Elders, which can be
+//! set to sign garbage, to equivocate, or to not send anything, by their [ElderBehaviour].
+//! With fewer faulty Elders in a section than its threshold, transfers are still agreed,
+//! and no tokens are created or lost by the honest Elders.
+
+use crate::{
+    actor::Actor, replica_store::TransferReplicaStore, ActorEvent, Error, InFlight,
+    InMemoryKnownSections, ReplicaSigningImpl, Result, TransferInitiated,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::Serialize;
+use sn_data_types::{
+    ActorHistory, Credit, CreditAgreementProof, DebitId, Keypair, OwnerType, PublicKey,
+    ReplicaEvent, SectionElders, Signature, SignatureShare, SignedCredit, Token,
+    TransferAgreementProof, TransferPropagated, TransferValidated,
+};
+use std::collections::{BTreeMap, VecDeque};
+use std::{fmt, iter};
+use threshold_crypto::{PublicKeySet, SecretKey, SecretKeySet, SecretKeyShare};
+
+/// The id of an Elder in the simulation.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ElderId {
+    /// The index of the section.
+    pub section: usize,
+    /// The index of the Elder in the section.
+    pub elder: usize,
+}
+
+impl ElderId {
+    /// The id of the Elder of the index, in the section of the index.
+    pub fn new(section: usize, elder: usize) -> Self {
+        Self { section, elder }
+    }
+}
+
+/// A message between the clients and the Elders of the simulation.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Message {
+    /// A transfer, from its sender to an Elder of its section, for validation.
+    Validate {
+        /// The receiving Elder.
+        elder: ElderId,
+        /// The transfer initiated by the sender.
+        transfer: TransferInitiated,
+    },
+    /// A validation, from an Elder to the sender of the transfer.
+    Validation {
+        /// The receiving client.
+        client: PublicKey,
+        /// The validation by the Elder.
+        validation: TransferValidated,
+    },
+    /// An agreed transfer, from its sender to an Elder of its section, for registration.
+    Register {
+        /// The receiving Elder.
+        elder: ElderId,
+        /// The proof of agreement.
+        proof: TransferAgreementProof,
+    },
+    /// A registered credit, to an Elder of the section of the recipient.
+    Propagate {
+        /// The receiving Elder.
+        elder: ElderId,
+        /// The proof of the credit.
+        credit_proof: CreditAgreementProof,
+    },
+}
+
+/// The order in which queued messages are delivered.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum DeliveryOrder {
+    /// In the order they were sent.
+    Fifo,
+    /// The most recently sent first.
+    Lifo,
+    /// In a random order, drawn from the seed of the simulation.
+    Random,
+}
+
+/// The probabilities of a sent message being dropped, duplicated or delayed.
+/// A delayed message is held back until all the messages sent before it have been delivered.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct NetworkFaults {
+    /// The probability of a message being lost.
+    pub drop: f64,
+    /// The probability of a message being delivered twice.
+    pub duplicate: f64,
+    /// The probability of a message being delayed.
+    pub delay: f64,
+}
+
+/// The behaviour of an Elder of the simulation.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ElderBehaviour {
+    /// Follows the protocol.
+    Honest,
+    /// Signs its validations with a key which is not its share,
+    /// and propagates credits with a signature which is not its section's.
+    GarbageSignatures,
+    /// Sends, along with its validation, a validation of a conflicting credit.
+    Equivocating,
+    /// Applies what it receives, but sends nothing.
+    Silent,
+}
+
+/// An Elder, holding the wallets of its section.
+#[derive(Clone, Debug)]
+pub struct SimElder {
+    secret_key: SecretKeyShare,
+    signing: ReplicaSigningImpl,
+    store: TransferReplicaStore,
+    behaviour: ElderBehaviour,
+    /// The credits propagated by the Elder, not yet held by all the Elders of their recipient.
+    propagating: Vec<CreditAgreementProof>,
+}
+
+impl SimElder {
+    /// The wallets held by the Elder.
+    pub fn store(&self) -> &TransferReplicaStore {
+        &self.store
+    }
+
+    /// The signing of the Elder.
+    pub fn signing(&self) -> &ReplicaSigningImpl {
+        &self.signing
+    }
+
+    /// The behaviour of the Elder.
+    pub fn behaviour(&self) -> ElderBehaviour {
+        self.behaviour
+    }
+}
+
+/// A section, and its Elders.
+#[derive(Clone, Debug)]
+pub struct SimSection {
+    key_set: PublicKeySet,
+    elders: Vec<SimElder>,
+}
+
+impl SimSection {
+    /// The key set of the section.
+    pub fn key_set(&self) -> &PublicKeySet {
+        &self.key_set
+    }
+
+    /// The Elders of the section.
+    pub fn elders(&self) -> &[SimElder] {
+        &self.elders
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SimClient {
+    section: usize,
+    actor: Actor<Keypair>,
+    /// The credits and debits of the wallet, as recorded by its Elders.
+    history: ActorHistory,
+    /// The agreed transfers sent for registration, not yet registered by all the Elders.
+    registering: Vec<TransferAgreementProof>,
+}
+
+/// A simulated network of sections, and of clients with wallets in them.
+pub struct Simulator {
+    rng: StdRng,
+    /// Signs the initial balances of the clients, like genesis.
+    mint: SecretKeySet,
+    minted: Token,
+    known_sections: InMemoryKnownSections,
+    sections: Vec<SimSection>,
+    clients: BTreeMap<PublicKey, SimClient>,
+    queue: VecDeque<Message>,
+    order: DeliveryOrder,
+    faults: NetworkFaults,
+    delayed: Vec<Message>,
+    dropped: Vec<Message>,
+    delivered: usize,
+    rejected: Vec<(Message, Error)>,
+}
+
+impl Simulator {
+    /// A network of the number of sections, with the number of Elders in each,
+    /// generated from the seed. The same seed, and the same calls, give the same simulation.
+    pub fn new(seed: u64, section_count: usize, elders_per_section: usize) -> Result<Self> {
+        if section_count == 0 || elders_per_section == 0 {
+            return Err(Error::InvalidOperation);
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mint = SecretKeySet::random(0, &mut rng);
+        let mut known_sections = InMemoryKnownSections::new(vec![mint.public_keys().public_key()]);
+        let threshold = std::cmp::max(1, 2 * elders_per_section / 3) - 1;
+        let sections = (0..section_count)
+            .map(|_| {
+                let secret_key_set = SecretKeySet::random(threshold, &mut rng);
+                let key_set = secret_key_set.public_keys();
+                let _ = known_sections.add(key_set.public_key());
+                let elders = (0..elders_per_section)
+                    .map(|index| {
+                        let secret_key = secret_key_set.secret_key_share(index);
+                        SimElder {
+                            store: TransferReplicaStore::new(
+                                secret_key.public_key_share(),
+                                index,
+                                key_set.clone(),
+                            ),
+                            signing: ReplicaSigningImpl::new(
+                                secret_key.clone(),
+                                index,
+                                key_set.clone(),
+                            ),
+                            secret_key,
+                            behaviour: ElderBehaviour::Honest,
+                            propagating: vec![],
+                        }
+                    })
+                    .collect();
+                SimSection { key_set, elders }
+            })
+            .collect();
+        Ok(Self {
+            rng,
+            mint,
+            minted: Token::zero(),
+            known_sections,
+            sections,
+            clients: Default::default(),
+            queue: Default::default(),
+            order: DeliveryOrder::Fifo,
+            faults: Default::default(),
+            delayed: vec![],
+            dropped: vec![],
+            delivered: 0,
+            rejected: vec![],
+        })
+    }
+
+    /// Adds a client with a wallet in the section, credited with the balance, and returns its key.
+    pub fn add_client(&mut self, section: usize, balance: Token) -> Result<PublicKey> {
+        let key_set = self
+            .sections
+            .get(section)
+            .ok_or(Error::CouldNotFindGroup)?
+            .key_set
+            .clone();
+        let keypair = Keypair::new_ed25519(&mut self.rng);
+        let key = keypair.public_key();
+        let replicas = SectionElders {
+            prefix: Default::default(),
+            names: Default::default(),
+            key_set,
+        };
+        let actor = Actor::new(keypair, replicas);
+        let _ = self.clients.insert(
+            key,
+            SimClient {
+                section,
+                actor,
+                history: ActorHistory {
+                    credits: vec![],
+                    debits: vec![],
+                },
+                registering: vec![],
+            },
+        );
+        for elder in &mut self.sections[section].elders {
+            elder.store.add_wallet(OwnerType::Single(key))?;
+        }
+        if balance > Token::zero() {
+            let credit_proof = self.mint_credit(key, balance)?;
+            self.minted = self
+                .minted
+                .checked_add(balance)
+                .ok_or(Error::AdditionOverflow(self.minted, balance))?;
+            for elder in 0..self.sections[section].elders.len() {
+                self.receive_propagated(ElderId { section, elder }, &credit_proof)?;
+            }
+            self.synch(&key)?;
+        }
+        Ok(key)
+    }
+
+    /// Sets the order in which the queued messages are delivered.
+    pub fn set_delivery_order(&mut self, order: DeliveryOrder) {
+        self.order = order;
+    }
+
+    /// Sets the faults of the network, for the messages sent from now on.
+    /// Fails if any of the probabilities is not between 0 and 1.
+    pub fn set_network_faults(&mut self, faults: NetworkFaults) -> Result<()> {
+        let valid = |p: f64| (0.0..=1.0).contains(&p);
+        if !(valid(faults.drop) && valid(faults.duplicate) && valid(faults.delay)) {
+            return Err(Error::InvalidOperation);
+        }
+        self.faults = faults;
+        Ok(())
+    }
+
+    /// Sets the behaviour of the Elder.
+    pub fn set_behaviour(&mut self, elder: ElderId, behaviour: ElderBehaviour) -> Result<()> {
+        self.elder_mut(elder)?.behaviour = behaviour;
+        Ok(())
+    }
+
+    // -----------------------------------------------------------------
+    // ---------------------- Queries ----------------------------------
+    // -----------------------------------------------------------------
+
+    /// The sections of the network.
+    pub fn sections(&self) -> &[SimSection] {
+        &self.sections
+    }
+
+    /// The keys of the section keys, and of the mint of the initial balances.
+    pub fn known_sections(&self) -> &InMemoryKnownSections {
+        &self.known_sections
+    }
+
+    /// The total of the initial balances of the clients.
+    /// Transfers between the clients do not change it.
+    pub fn minted(&self) -> Token {
+        self.minted
+    }
+
+    /// The total of the balances of the wallets of all clients, as held by the honest Elders
+    /// of the index in each section, or None if the Elders of the index are not all honest.
+    /// With all messages delivered, it is the minted total, at every index.
+    pub fn supply_at(&self, elder: usize) -> Result<Option<Token>> {
+        let mut supply = Token::zero();
+        for (key, client) in &self.clients {
+            let elder = self.sections[client.section]
+                .elders
+                .get(elder)
+                .ok_or(Error::CouldNotFindGroup)?;
+            if elder.behaviour != ElderBehaviour::Honest {
+                return Ok(None);
+            }
+            let balance = elder.store.balance(key)?;
+            supply = supply
+                .checked_add(balance)
+                .ok_or(Error::AdditionOverflow(supply, balance))?;
+        }
+        Ok(Some(supply))
+    }
+
+    /// The keys of the clients.
+    pub fn clients(&self) -> impl Iterator<Item = &PublicKey> {
+        self.clients.keys()
+    }
+
+    /// The Actor of the client.
+    pub fn actor(&self, client: &PublicKey) -> Option<&Actor<Keypair>> {
+        self.clients.get(client).map(|c| &c.actor)
+    }
+
+    /// The credits and debits of the wallet of the client, as the client would
+    /// query them from the Elders of its section, to synch an Actor from history.
+    pub fn history(&self, client: &PublicKey) -> Option<ActorHistory> {
+        self.clients.get(client).map(|c| c.history.clone())
+    }
+
+    /// The balances of the wallet of the client, at each of the Elders of its section.
+    pub fn replica_balances(&self, client: &PublicKey) -> Result<Vec<Token>> {
+        let section = self.section_of(client)?;
+        self.sections[section]
+            .elders
+            .iter()
+            .map(|elder| elder.store.balance(client))
+            .collect()
+    }
+
+    /// The messages not yet delivered, in the order they were sent.
+    pub fn pending(&self) -> impl Iterator<Item = &Message> {
+        self.queue.iter()
+    }
+
+    /// The number of messages delivered so far.
+    pub fn delivered(&self) -> usize {
+        self.delivered
+    }
+
+    /// The messages held back by the network, to be delivered after all the pending messages.
+    pub fn delayed(&self) -> &[Message] {
+        &self.delayed
+    }
+
+    /// The messages lost by the network.
+    pub fn dropped(&self) -> &[Message] {
+        &self.dropped
+    }
+
+    /// The messages rejected by their receivers, and why.
+    pub fn rejected(&self) -> &[(Message, Error)] {
+        &self.rejected
+    }
+
+    // -----------------------------------------------------------------
+    // ---------------------- Cmds -------------------------------------
+    // -----------------------------------------------------------------
+
+    /// Initiates a transfer at the Actor of the sender, and sends it
+    /// to the Elders of its section for validation.
+    pub fn transfer(
+        &mut self,
+        sender: &PublicKey,
+        recipient: PublicKey,
+        amount: Token,
+    ) -> Result<DebitId> {
+        let client = self
+            .clients
+            .get_mut(sender)
+            .ok_or(Error::WalletNotFound(*sender))?;
+        let transfer = client
+            .actor
+            .transfer(amount, recipient, "simulated".to_string())?
+            .ok_or(Error::TransferCreationFailed)?;
+        client
+            .actor
+            .apply(ActorEvent::TransferInitiated(transfer.clone()))?;
+        let section = client.section;
+        let id = transfer.id();
+        for elder in 0..self.sections[section].elders.len() {
+            self.send(Message::Validate {
+                elder: ElderId { section, elder },
+                transfer: transfer.clone(),
+            });
+        }
+        Ok(id)
+    }
+
+    /// Sends again the transfers of the client which are in flight, and its agreed transfers
+    /// to the Elders of its section which have not registered them yet, as a client would
+    /// after a timeout, and returns the number of transfers.
+    /// A transfer registered by all the Elders is not resent.
+    pub fn resend(&mut self, key: &PublicKey) -> Result<usize> {
+        let client = self.clients.get(key).ok_or(Error::WalletNotFound(*key))?;
+        let section = client.section;
+        let in_flight = client.actor.in_flight();
+        for transfer in &in_flight {
+            for elder in 0..self.sections[section].elders.len() {
+                let elder = ElderId { section, elder };
+                match transfer {
+                    InFlight::Validation(transfer) => self.send(Message::Validate {
+                        elder,
+                        transfer: transfer.clone(),
+                    }),
+                    InFlight::Registration(proof) => self.send(Message::Register {
+                        elder,
+                        proof: proof.clone(),
+                    }),
+                    // batches are not sent by the simulation
+                    InFlight::BatchValidation(_) | InFlight::BatchRegistration(_) => (),
+                }
+            }
+        }
+        let registering = std::mem::take(&mut self.client_mut(key)?.registering);
+        let mut unregistered = vec![];
+        for proof in registering {
+            let missing = self.missing_debit(&proof)?;
+            if missing.is_empty() {
+                continue;
+            }
+            for elder in missing {
+                self.send(Message::Register {
+                    elder,
+                    proof: proof.clone(),
+                });
+            }
+            unregistered.push(proof);
+        }
+        let resent = in_flight.len() + unregistered.len();
+        self.client_mut(key)?.registering = unregistered;
+        Ok(resent)
+    }
+
+    /// Sends again the credits propagated by the Elders to the Elders of their recipients
+    /// which do not hold them yet, as the Elders would after a timeout, and returns the
+    /// number of messages sent. A credit held by all the Elders of its recipient is not resent.
+    pub fn resend_propagations(&mut self) -> Result<usize> {
+        let mut messages = vec![];
+        for section in 0..self.sections.len() {
+            for elder in 0..self.sections[section].elders.len() {
+                let propagating =
+                    std::mem::take(&mut self.sections[section].elders[elder].propagating);
+                let mut unacknowledged = vec![];
+                for credit_proof in propagating {
+                    let missing = self.missing_credit(&credit_proof)?;
+                    if missing.is_empty() {
+                        continue;
+                    }
+                    messages.extend(missing.into_iter().map(|elder| Message::Propagate {
+                        elder,
+                        credit_proof: credit_proof.clone(),
+                    }));
+                    unacknowledged.push(credit_proof);
+                }
+                self.sections[section].elders[elder].propagating = unacknowledged;
+            }
+        }
+        let sent = messages.len();
+        for message in messages {
+            self.send(message);
+        }
+        Ok(sent)
+    }
+
+    /// Synchs the Actor of the client with the state of its wallet at the first Elder of its section.
+    pub fn synch(&mut self, client: &PublicKey) -> Result<()> {
+        let section = self.section_of(client)?;
+        let wallet = self.sections[section].elders[0]
+            .store
+            .get(client)
+            .and_then(|w| w.wallet())
+            .ok_or(Error::WalletNotFound(*client))?;
+        let actor = &mut self
+            .clients
+            .get_mut(client)
+            .ok_or(Error::WalletNotFound(*client))?
+            .actor;
+        if let Some(synched) =
+            actor.synch(wallet.balance, wallet.debit_version, wallet.credit_ids)?
+        {
+            actor.apply(ActorEvent::StateSynched(synched))?;
+        }
+        Ok(())
+    }
+
+    /// Delivers the next message, in the delivery order, and returns it.
+    pub fn step(&mut self) -> Option<Message> {
+        if self.queue.is_empty() {
+            self.queue.extend(self.delayed.drain(..));
+        }
+        let index = match self.order {
+            DeliveryOrder::Fifo => 0,
+            DeliveryOrder::Lifo => self.queue.len().saturating_sub(1),
+            DeliveryOrder::Random => self.rng.gen_range(0, self.queue.len().max(1)),
+        };
+        self.deliver(index)
+    }
+
+    /// Delivers the message at the index of the pending messages, and returns it.
+    /// A message rejected by its receiver is recorded.
+    pub fn deliver(&mut self, index: usize) -> Option<Message> {
+        let message = self.queue.remove(index)?;
+        self.delivered += 1;
+        if let Err(error) = self.handle(message.clone()) {
+            self.rejected.push((message.clone(), error));
+        }
+        Some(message)
+    }
+
+    /// Delivers messages until there are none left, and returns the number delivered.
+    pub fn run(&mut self) -> usize {
+        let delivered = self.delivered;
+        while self.step().is_some() {}
+        self.delivered - delivered
+    }
+
+    /// Drops the pending messages, for which the predicate is true, and returns them.
+    pub fn drop_messages<F: FnMut(&Message) -> bool>(&mut self, mut predicate: F) -> Vec<Message> {
+        let (dropped, kept) = self.queue.drain(..).partition(|m| predicate(m));
+        self.queue = kept;
+        dropped.into_iter().collect()
+    }
+
+    /// Shuffles the pending messages.
+    pub fn shuffle(&mut self) {
+        let mut messages: Vec<_> = self.queue.drain(..).collect();
+        messages.shuffle(&mut self.rng);
+        self.queue = messages.into();
+    }
+
+    // -----------------------------------------------------------------
+    // ---------------------- Private methods --------------------------
+    // -----------------------------------------------------------------
+
+    /// Sends the message through the network, which may drop, duplicate or delay it.
+    fn send(&mut self, message: Message) {
+        let faults = self.faults;
+        let mut occurs = |p: f64| p > 0.0 && self.rng.gen_bool(p);
+        if occurs(faults.drop) {
+            self.dropped.push(message);
+        } else if occurs(faults.delay) {
+            self.delayed.push(message);
+        } else {
+            if occurs(faults.duplicate) {
+                self.queue.push_back(message.clone());
+            }
+            self.queue.push_back(message);
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Validate { elder, transfer } => self.validate(elder, &transfer),
+            Message::Validation { client, validation } => self.receive(&client, validation),
+            Message::Register { elder, proof } => self.register(elder, &proof),
+            Message::Propagate {
+                elder,
+                credit_proof,
+            } => self.receive_propagated(elder, &credit_proof),
+        }
+    }
+
+    fn validate(&mut self, id: ElderId, transfer: &TransferInitiated) -> Result<()> {
+        let elder = self.elder_mut(id)?;
+        let validation = elder
+            .store
+            .validate_and_sign(
+                &transfer.signed_debit,
+                &transfer.signed_credit,
+                &elder.signing,
+            )?
+            .ok_or(Error::ValidationFailed)?;
+        elder
+            .store
+            .apply(ReplicaEvent::TransferValidated(validation.clone()))?;
+        let behaviour = elder.behaviour;
+        let secret_key = elder.secret_key.clone();
+        let client = transfer.signed_debit.sender();
+        let index = validation.replica_debit_sig.index;
+        match behaviour {
+            ElderBehaviour::Honest => self.send(Message::Validation { client, validation }),
+            ElderBehaviour::Silent => (),
+            ElderBehaviour::GarbageSignatures => {
+                let garbage = SecretKeySet::random(0, &mut self.rng).secret_key_share(0);
+                let mut validation = validation;
+                validation.replica_debit_sig =
+                    sign_share(&garbage, index, &validation.signed_debit)?;
+                validation.replica_credit_sig =
+                    sign_share(&garbage, index, &validation.signed_credit)?;
+                self.send(Message::Validation { client, validation });
+            }
+            ElderBehaviour::Equivocating => {
+                let mut conflicting = validation.clone();
+                conflicting.signed_credit.credit.recipient =
+                    Keypair::new_ed25519(&mut self.rng).public_key();
+                conflicting.replica_credit_sig =
+                    sign_share(&secret_key, index, &conflicting.signed_credit)?;
+                self.send(Message::Validation {
+                    client,
+                    validation: conflicting,
+                });
+                self.send(Message::Validation { client, validation });
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, key: &PublicKey, validation: TransferValidated) -> Result<()> {
+        let client = self
+            .clients
+            .get_mut(key)
+            .ok_or(Error::WalletNotFound(*key))?;
+        if !client.actor.pending_debits().contains(&validation.id()) {
+            // a validation arriving after the agreement is not needed
+            return Ok(());
+        }
+        let received = client
+            .actor
+            .receive(validation)?
+            .ok_or(Error::ReceiveValidationFailed)?;
+        client
+            .actor
+            .apply(ActorEvent::TransferValidationReceived(received.clone()))?;
+        let proof = match received.proof {
+            Some(proof) => proof,
+            None => return Ok(()),
+        };
+        let registration = client
+            .actor
+            .register(proof.clone())?
+            .ok_or(Error::RegisterProofFailed)?;
+        client
+            .actor
+            .apply(ActorEvent::TransferRegistrationSent(registration))?;
+        client.registering.push(proof.clone());
+        let section = client.section;
+        for elder in 0..self.sections[section].elders.len() {
+            self.send(Message::Register {
+                elder: ElderId { section, elder },
+                proof: proof.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn register(&mut self, id: ElderId, proof: &TransferAgreementProof) -> Result<()> {
+        let elder = self.elder_mut(id)?;
+        let registered = elder
+            .store
+            .register(proof)?
+            .ok_or(Error::RegisterProofFailed)?;
+        elder
+            .store
+            .apply(ReplicaEvent::TransferRegistered(registered))?;
+        let behaviour = elder.behaviour;
+        if let Some(client) = self.clients.get_mut(&proof.sender()) {
+            let debits = &mut client.history.debits;
+            if !debits.iter().any(|d| d.id() == proof.id()) {
+                debits.push(proof.clone());
+            }
+        }
+        if behaviour == ElderBehaviour::Silent {
+            return Ok(());
+        }
+        // credits to keys without a client in the simulation are not propagated
+        if let Ok(section) = self.section_of(&proof.recipient()) {
+            let mut credit_proof = proof.credit_proof();
+            if behaviour == ElderBehaviour::GarbageSignatures {
+                let garbage: SecretKey = self.rng.gen();
+                let bytes = serialise(&credit_proof.signed_credit)?;
+                credit_proof.debiting_replicas_sig = Signature::Bls(garbage.sign(bytes));
+            }
+            for elder in 0..self.sections[section].elders.len() {
+                self.send(Message::Propagate {
+                    elder: ElderId { section, elder },
+                    credit_proof: credit_proof.clone(),
+                });
+            }
+            self.elder_mut(id)?.propagating.push(credit_proof);
+        }
+        Ok(())
+    }
+
+    fn receive_propagated(
+        &mut self,
+        id: ElderId,
+        credit_proof: &CreditAgreementProof,
+    ) -> Result<()> {
+        let known_sections = self.known_sections.clone();
+        let elder = self.elder_mut(id)?;
+        // the same credit is propagated by every Elder of the debiting section
+        if elder
+            .store
+            .receive_propagated(credit_proof, &known_sections)?
+            .is_some()
+        {
+            elder
+                .store
+                .apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+                    credit_proof: credit_proof.clone(),
+                }))?;
+            if let Some(client) = self.clients.get_mut(&credit_proof.recipient()) {
+                let credits = &mut client.history.credits;
+                if !credits.iter().any(|c| c.id() == credit_proof.id()) {
+                    credits.push(credit_proof.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The Elders of the section of the recipient of the credit which do not hold it.
+    fn missing_credit(&self, credit_proof: &CreditAgreementProof) -> Result<Vec<ElderId>> {
+        let recipient = credit_proof.recipient();
+        let section = self.section_of(&recipient)?;
+        let mut missing = vec![];
+        for (elder, sim_elder) in self.sections[section].elders.iter().enumerate() {
+            let held = sim_elder
+                .store
+                .get(&recipient)
+                .and_then(|wallet| wallet.wallet())
+                .is_some_and(|wallet| wallet.credit_ids.contains(credit_proof.id()));
+            if !held {
+                missing.push(ElderId { section, elder });
+            }
+        }
+        Ok(missing)
+    }
+
+    /// The Elders of the section of the sender of the transfer which have not applied its debit.
+    fn missing_debit(&self, proof: &TransferAgreementProof) -> Result<Vec<ElderId>> {
+        let sender = proof.sender();
+        let section = self.section_of(&sender)?;
+        let mut missing = vec![];
+        for (elder, sim_elder) in self.sections[section].elders.iter().enumerate() {
+            let applied = sim_elder
+                .store
+                .get(&sender)
+                .and_then(|wallet| wallet.wallet())
+                .is_some_and(|wallet| wallet.debit_version > proof.id().counter);
+            if !applied {
+                missing.push(ElderId { section, elder });
+            }
+        }
+        Ok(missing)
+    }
+
+    fn client_mut(&mut self, client: &PublicKey) -> Result<&mut SimClient> {
+        self.clients
+            .get_mut(client)
+            .ok_or(Error::WalletNotFound(*client))
+    }
+
+    fn elder_mut(&mut self, id: ElderId) -> Result<&mut SimElder> {
+        self.sections
+            .get_mut(id.section)
+            .and_then(|s| s.elders.get_mut(id.elder))
+            .ok_or(Error::CouldNotFindGroup)
+    }
+
+    fn section_of(&self, client: &PublicKey) -> Result<usize> {
+        self.clients
+            .get(client)
+            .map(|c| c.section)
+            .ok_or(Error::WalletNotFound(*client))
+    }
+
+    /// A credit of the initial balance of a client, signed by the mint.
+    fn mint_credit(&mut self, recipient: PublicKey, amount: Token) -> Result<CreditAgreementProof> {
+        let credit = Credit {
+            id: self.rng.gen(),
+            amount,
+            recipient,
+            msg: "initial balance".to_string(),
+        };
+        let actor_signature = self.mint_signature(&credit)?;
+        let signed_credit = SignedCredit {
+            credit,
+            actor_signature,
+        };
+        Ok(CreditAgreementProof {
+            debiting_replicas_sig: self.mint_signature(&signed_credit)?,
+            signed_credit,
+            debiting_replicas_keys: self.mint.public_keys(),
+        })
+    }
+
+    fn mint_signature<T: Serialize>(&self, item: &T) -> Result<Signature> {
+        let share = self.mint.secret_key_share(0).sign(serialise(item)?);
+        self.mint
+            .public_keys()
+            .combine_signatures(iter::once((0, &share)))
+            .map(Signature::Bls)
+            .map_err(|_| Error::CannotAggregate)
+    }
+}
+
+fn sign_share<T: Serialize>(
+    secret_key: &SecretKeyShare,
+    index: usize,
+    item: &T,
+) -> Result<SignatureShare> {
+    Ok(SignatureShare {
+        index,
+        share: secret_key.sign(serialise(item)?),
+    })
+}
+
+fn serialise<T: Serialize>(item: &T) -> Result<Vec<u8>> {
+    bincode::serialize(item).map_err(|_| Error::Serialisation("Could not serialise item".into()))
+}
+
+impl fmt::Debug for Simulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Simulator {{ sections: {:?}, clients: {:?}, pending: {:?}, delivered: {:?}, order: {:?} }}",
+            self.sections.len(),
+            self.clients.len(),
+            self.queue.len(),
+            self.delivered,
+            self.order
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeliveryOrder, ElderBehaviour, ElderId, Message, NetworkFaults, Simulator};
+    use crate::{Error, Result};
+    use sn_data_types::Token;
+    use std::iter;
+
+    #[test]
+    fn same_seed_gives_same_simulation() -> Result<()> {
+        let simulate = |seed| -> Result<_> {
+            let mut simulator = Simulator::new(seed, 2, 4)?;
+            simulator.set_delivery_order(DeliveryOrder::Random);
+            let alice = simulator.add_client(0, Token::from_nano(100))?;
+            let bob = simulator.add_client(1, Token::from_nano(10))?;
+            let _ = simulator.transfer(&alice, bob, Token::from_nano(30))?;
+            let delivered: Vec<Message> = iter::from_fn(|| simulator.step()).collect();
+            simulator.synch(&bob)?;
+            assert_eq!(
+                simulator.replica_balances(&alice)?,
+                vec![Token::from_nano(70); 4]
+            );
+            assert_eq!(
+                simulator.replica_balances(&bob)?,
+                vec![Token::from_nano(40); 4]
+            );
+            let balance = |key| simulator.actor(&key).map(|a| a.balance());
+            assert_eq!(balance(alice), Some(Token::from_nano(70)));
+            assert_eq!(balance(bob), Some(Token::from_nano(40)));
+            Ok((alice, delivered))
+        };
+        let (alice, delivered) = simulate(7)?;
+        assert_eq!((alice, delivered.clone()), simulate(7)?);
+        let (other_alice, other_delivered) = simulate(8)?;
+        assert_ne!(alice, other_alice);
+        assert_ne!(delivered, other_delivered);
+        Ok(())
+    }
+
+    #[test]
+    fn faulty_elders_below_threshold_do_not_break_agreement() -> Result<()> {
+        let mut simulator = Simulator::new(1, 2, 7)?;
+        simulator.set_delivery_order(DeliveryOrder::Random);
+        simulator.set_network_faults(NetworkFaults {
+            drop: 0.0,
+            duplicate: 0.2,
+            delay: 0.2,
+        })?;
+        // fewer than the threshold of 3, in each section
+        let faulty = [
+            (ElderId::new(0, 0), ElderBehaviour::GarbageSignatures),
+            (ElderId::new(0, 1), ElderBehaviour::Equivocating),
+            (ElderId::new(1, 2), ElderBehaviour::Silent),
+            (ElderId::new(1, 3), ElderBehaviour::GarbageSignatures),
+        ];
+        for (elder, behaviour) in faulty.iter() {
+            simulator.set_behaviour(*elder, *behaviour)?;
+        }
+        let alice = simulator.add_client(0, Token::from_nano(100))?;
+        let bob = simulator.add_client(1, Token::from_nano(10))?;
+
+        let _ = simulator.transfer(&alice, bob, Token::from_nano(30))?;
+        let _ = simulator.run();
+        simulator.synch(&bob)?;
+        let _ = simulator.transfer(&bob, alice, Token::from_nano(5))?;
+        let _ = simulator.run();
+        simulator.synch(&alice)?;
+
+        // the faults were exercised
+        assert!(!simulator.rejected().is_empty());
+        let balance = |key| simulator.actor(&key).map(|a| a.balance());
+        assert_eq!(balance(alice), Some(Token::from_nano(75)));
+        assert_eq!(balance(bob), Some(Token::from_nano(35)));
+        for elder in 0..7 {
+            let honest = simulator
+                .sections()
+                .iter()
+                .all(|section| section.elders()[elder].behaviour() == ElderBehaviour::Honest);
+            assert_eq!(honest, faulty.iter().all(|(id, _)| id.elder != elder));
+            let supply = simulator.supply_at(elder)?;
+            assert_eq!(supply.is_some(), honest);
+            if honest {
+                assert_eq!(supply, Some(simulator.minted()));
+                assert_eq!(
+                    simulator.replica_balances(&alice)?[elder],
+                    Token::from_nano(75)
+                );
+                assert_eq!(
+                    simulator.replica_balances(&bob)?[elder],
+                    Token::from_nano(35)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_controls_the_interleaving() -> Result<()> {
+        let mut simulator = Simulator::new(3, 1, 4)?;
+        let alice = simulator.add_client(0, Token::from_nano(100))?;
+        let bob = simulator.add_client(0, Token::from_nano(10))?;
+        assert_eq!(simulator.clients().count(), 2);
+
+        // the transfer reaches only two of the Elders, as many as needed for the agreement
+        let _ = simulator.transfer(&alice, bob, Token::from_nano(30))?;
+        let lost = simulator
+            .drop_messages(|m| matches!(m, Message::Validate { elder, .. } if elder.elder >= 2));
+        assert_eq!(lost.len(), 2);
+        simulator.shuffle();
+        assert_eq!(simulator.pending().count(), 2);
+
+        // the validations are held back by the network, until all else is delivered
+        simulator.set_network_faults(NetworkFaults {
+            delay: 1.0,
+            ..Default::default()
+        })?;
+        while let Some(last) = simulator.pending().count().checked_sub(1) {
+            let _ = simulator.deliver(last);
+        }
+        assert_eq!(simulator.delayed().len(), 2);
+        assert!(simulator
+            .delayed()
+            .iter()
+            .all(|m| matches!(m, Message::Validation { .. })));
+        simulator.set_network_faults(NetworkFaults::default())?;
+
+        // 2 validations, then 4 registrations, each propagated to the 4 Elders
+        assert_eq!(simulator.run(), 22);
+        assert_eq!(simulator.delivered(), 24);
+        assert!(simulator.delayed().is_empty());
+        simulator.synch(&bob)?;
+        assert_eq!(
+            simulator.replica_balances(&alice)?,
+            vec![Token::from_nano(70); 4]
+        );
+        assert_eq!(
+            simulator.replica_balances(&bob)?,
+            vec![Token::from_nano(40); 4]
+        );
+        let balance = |key| simulator.actor(&key).map(|a| a.balance());
+        assert_eq!(balance(alice), Some(Token::from_nano(70)));
+        assert_eq!(balance(bob), Some(Token::from_nano(40)));
+        Ok(())
+    }
+
+    #[test]
+    fn dropped_messages_never_create_tokens() -> Result<()> {
+        let mut simulator = Simulator::new(2, 2, 4)?;
+        simulator.set_delivery_order(DeliveryOrder::Random);
+        simulator.set_network_faults(NetworkFaults {
+            drop: 0.3,
+            duplicate: 0.1,
+            delay: 0.1,
+        })?;
+        let alice = simulator.add_client(0, Token::from_nano(100))?;
+        let bob = simulator.add_client(1, Token::from_nano(10))?;
+
+        let _ = simulator.transfer(&alice, bob, Token::from_nano(30))?;
+        let _ = simulator.run();
+        // the client resends until its transfer is registered by all the Elders of its section,
+        // and the Elders until the credit is held by all the Elders of the recipient
+        for _ in 0..20 {
+            if simulator.resend(&alice)? == 0 {
+                break;
+            }
+            let _ = simulator.run();
+        }
+        for _ in 0..20 {
+            if simulator.resend_propagations()? == 0 {
+                break;
+            }
+            let _ = simulator.run();
+        }
+        assert_eq!(simulator.resend(&alice)?, 0);
+        assert_eq!(simulator.resend_propagations()?, 0);
+        simulator.synch(&bob)?;
+
+        let dropped = simulator.dropped();
+        assert!(dropped
+            .iter()
+            .any(|m| matches!(m, Message::Register { .. })));
+        assert!(dropped
+            .iter()
+            .any(|m| matches!(m, Message::Propagate { .. })));
+        let actor_balance = |key| simulator.actor(&key).map(|a| a.balance());
+        assert_eq!(actor_balance(alice), Some(Token::from_nano(70)));
+        assert_eq!(actor_balance(bob), Some(Token::from_nano(40)));
+        for balance in simulator.replica_balances(&alice)? {
+            assert_eq!(balance, Token::from_nano(70));
+        }
+        for balance in simulator.replica_balances(&bob)? {
+            assert_eq!(balance, Token::from_nano(40));
+        }
+        let bob_balance = actor_balance(bob).ok_or(Error::WalletNotFound(bob))?;
+        assert_eq!(
+            Token::from_nano(70).checked_add(bob_balance),
+            Some(simulator.minted())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Spending policies of wallets owned by a BLS key set ([OwnerType::Multi]),
+//! such as "any single owner may spend up to 100 tokens per day, above that needs 3 of 5".
+//! A policy is a number of tiers, each allowing debits signed by a number of the
+//! owners, as long as what each of them has signed within a rolling window
+//! of epochs, including the debit, stays within the limit of the tier.
+//!
+//! The Replicas enforce the policy on the shares of the owners, accumulated on
+//! proposal of a transfer (see [WalletReplica::propose_validation]), or combined
+//! by the owners into the signature of the debit (see [MultisigTransfer]).
+//! Since the transfer is only signed by the wallet key once the shares are combined,
+//! the threshold of the owner key set is the lowest number of signers of any tier,
+//! i.e. for a single owner to be able to spend, the key set threshold must be 0.
+//! Note that the shares of a key set of threshold 0 are all the same key, so that
+//! the signers of such a wallet are told apart by the index the owners sign with.
+//!
+//! [OwnerType::Multi]: sn_data_types::OwnerType::Multi
+//! [WalletReplica::propose_validation]: crate::WalletReplica::propose_validation
+//! [MultisigTransfer]: crate::MultisigTransfer
+
+use crate::{BatchTransferValidated, Error, Result};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{PublicKey, Token, TransferValidated};
+use std::collections::BTreeSet;
+
+/// A tier of a spending policy.
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct SpendingTier {
+    /// The number of owners which must sign the debit.
+    pub signers: usize,
+    /// The most each of the signers may have signed within the window, including the debit.
+    pub limit: Token,
+}
+
+/// The spending policy of a multisig wallet.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct SpendingPolicy {
+    /// The length, in epochs, of the rolling window.
+    window: u64,
+    /// The tiers, by ascending number of signers.
+    tiers: Vec<SpendingTier>,
+}
+
+impl SpendingPolicy {
+    /// A policy of the tiers, over a rolling window of the number of epochs.
+    /// Returns an error if there are no tiers, or a tier without signers.
+    pub fn new(window: u64, mut tiers: Vec<SpendingTier>) -> Result<Self> {
+        if tiers.is_empty() || tiers.iter().any(|t| t.signers == 0) {
+            return Err(Error::InvalidOperation);
+        }
+        tiers.sort_by_key(|t| (t.signers, t.limit.as_nano()));
+        Ok(Self { window, tiers })
+    }
+
+    /// The length, in epochs, of the rolling window.
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// The tiers, by ascending number of signers.
+    pub fn tiers(&self) -> &[SpendingTier] {
+        &self.tiers
+    }
+
+    /// The number of signers of the highest tier,
+    /// which is also required to change the policy.
+    pub fn max_signers(&self) -> usize {
+        self.tiers.last().map(|t| t.signers).unwrap_or_default()
+    }
+
+    /// Whether a debit of the amount, signed by the owners of the indices,
+    /// is allowed at the epoch, given the earlier spends of the wallet.
+    pub(crate) fn allows<'a>(
+        &self,
+        amount: Token,
+        signers: &BTreeSet<usize>,
+        spends: impl Iterator<Item = &'a Spend> + Clone,
+        epoch: u64,
+    ) -> bool {
+        let spent = signers
+            .iter()
+            .map(|signer| {
+                spends
+                    .clone()
+                    .filter(|s| s.epoch.saturating_add(self.window) > epoch)
+                    .filter(|s| s.signers.contains(signer))
+                    .map(|s| s.amount.as_nano())
+                    .fold(0, u64::saturating_add)
+            })
+            .max()
+            .unwrap_or_default()
+            .saturating_add(amount.as_nano());
+        self.tiers
+            .iter()
+            .any(|t| t.signers <= signers.len() && spent <= t.limit.as_nano())
+    }
+}
+
+/// A validated debit of a wallet with a spending policy.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub(crate) struct Spend {
+    /// The epoch at which the debit was validated.
+    pub(crate) epoch: u64,
+    /// The amount of the debit.
+    pub(crate) amount: Token,
+    /// The indices of the owners which signed the debit.
+    pub(crate) signers: BTreeSet<usize>,
+}
+
+/// The Replica event raised when a transfer of a multisig wallet,
+/// signed by the owners of the indices, has been validated.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct MultisigTransferValidated {
+    /// The validated transfer.
+    pub validated: TransferValidated,
+    /// The indices of the owners which signed the debit.
+    pub signers: BTreeSet<usize>,
+}
+
+/// The Replica event raised when a batch transfer of a multisig wallet,
+/// signed by the owners of the indices, has been validated.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct MultisigBatchValidated {
+    /// The validated batch.
+    pub validated: BatchTransferValidated,
+    /// The indices of the owners which signed the debit.
+    pub signers: BTreeSet<usize>,
+}
+
+/// The Replica event raised when the owners
+/// of a multisig wallet have declared its spending policy.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct SpendingPolicyDeclared {
+    /// The wallet.
+    pub wallet: PublicKey,
+    /// The policy.
+    pub policy: SpendingPolicy,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Spend, SpendingPolicy, SpendingTier};
+    use crate::{Error, Result};
+    use sn_data_types::Token;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn allows_single_signer_up_to_limit_in_window() -> Result<()> {
+        let policy = SpendingPolicy::new(
+            10,
+            vec![
+                SpendingTier {
+                    signers: 3,
+                    limit: Token::from_nano(u64::MAX),
+                },
+                SpendingTier {
+                    signers: 1,
+                    limit: Token::from_nano(100),
+                },
+            ],
+        )?;
+        assert_eq!(policy.max_signers(), 3);
+
+        let signers = |indices: &[usize]| indices.iter().copied().collect::<BTreeSet<_>>();
+        let mut spends = BTreeMap::new();
+        let _ = spends.insert(
+            0,
+            Spend {
+                epoch: 5,
+                amount: Token::from_nano(60),
+                signers: signers(&[0]),
+            },
+        );
+        let amount = Token::from_nano(50);
+        let allows = |indices: &[usize], epoch| {
+            policy.allows(amount, &signers(indices), spends.values(), epoch)
+        };
+        assert!(!allows(&[0], 14));
+        assert!(allows(&[0], 15));
+        assert!(allows(&[1], 14));
+        assert!(!allows(&[0, 1], 14));
+        assert!(allows(&[0, 1, 2], 14));
+
+        assert_eq!(
+            SpendingPolicy::new(10, vec![]),
+            Err(Error::InvalidOperation)
+        );
+        Ok(())
+    }
+}
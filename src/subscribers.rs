@@ -0,0 +1,92 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::Error;
+use sn_data_types::{CreditId, DebitId, PublicKey, Token};
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A notification of a change to a wallet,
+/// sent to the subscribers of its Actor or Replica.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Notification {
+    /// A credit has been added to the wallet.
+    CreditReceived {
+        /// The wallet.
+        wallet: PublicKey,
+        /// The id of the credit.
+        credit_id: CreditId,
+        /// The amount of the credit.
+        amount: Token,
+    },
+    /// A debit of the wallet has been registered.
+    DebitCompleted {
+        /// The wallet.
+        wallet: PublicKey,
+        /// The id of the debit.
+        debit_id: DebitId,
+        /// The amount of the debit.
+        amount: Token,
+    },
+    /// The balance of the wallet has changed.
+    BalanceChanged {
+        /// The wallet.
+        wallet: PublicKey,
+        /// The balance after the change.
+        balance: Token,
+    },
+    /// The validation of a debit of the wallet has been rejected.
+    ValidationRejected {
+        /// The wallet.
+        wallet: PublicKey,
+        /// The id of the debit.
+        debit_id: DebitId,
+        /// Why the validation was rejected.
+        error: Error,
+    },
+}
+
+/// The channels to the subscribers of notifications.
+/// Notifications are sent once the event causing them has been applied,
+/// and unsubscribing is done by dropping the receiver.
+/// Subscribers are not part of the state, so they are
+/// ignored when comparing, and shared by clones.
+#[derive(Clone, Default)]
+pub(crate) struct Subscribers(Vec<Sender<Notification>>);
+
+impl Subscribers {
+    /// A new subscription to the notifications.
+    pub(crate) fn subscribe(&mut self) -> Receiver<Notification> {
+        let (sender, receiver) = channel();
+        self.0.push(sender);
+        receiver
+    }
+
+    /// Sends the notification to all subscribers.
+    pub(crate) fn notify(&self, notification: Notification) {
+        for subscriber in &self.0 {
+            // a subscriber which has unsubscribed does not fail the others
+            let _ = subscriber.send(notification.clone());
+        }
+    }
+}
+
+impl PartialEq for Subscribers {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Subscribers {}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Subscribers({})", self.0.len())
+    }
+}
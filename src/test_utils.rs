@@ -6,19 +6,17 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{Error, Result, TransferActor as Actor, Wallet, WalletReplica};
+use crate::{Error, Result, TransferActor as Actor, TransferInitiated, Wallet};
+use crdts::Dot;
 use sn_data_types::{
-    Credit, CreditAgreementProof, Keypair, PublicKey, SignatureShare, SignedCredit, SignedDebit,
-    SignedTransfer, Token,
+    Credit, CreditAgreementProof, Debit, Keypair, OwnerType, PublicKey, SectionElders,
+    SignatureShare, SignedCredit, Token, TransferValidated,
 };
-use std::{
-    collections::{BTreeMap, HashMap},
-    sync::Arc,
-};
-use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKeySet, SecretKeyShare};
+use std::collections::BTreeMap;
+use threshold_crypto::{PublicKeySet, SecretKey, SecretKeySet, SecretKeyShare};
+use xor_name::Prefix;
 
 /// Produces a genesis balance for a new network.
-#[allow(unused)]
 pub fn get_random_genesis(balance: u64, id: PublicKey) -> Result<CreditAgreementProof> {
     let threshold = 0;
     // Nothing comes before genesis, it is a paradox
@@ -98,183 +96,101 @@ pub fn get_genesis(
     })
 }
 
-/// Test only. Produces a genesis balance for a new network.
-pub fn get_multi_genesis(
-    balance: u64,
-    id: PublicKey,
-    secret_key_set: SecretKeySet,
-) -> Result<CreditAgreementProof> {
-    let credit = Credit {
-        id: Default::default(),
-        amount: Token::from_nano(balance),
-        recipient: id,
-        msg: "genesis".to_string(),
-    };
-
-    // actor instances' signatures over > credit <
-
-    let serialised_credit = bincode::serialize(&credit)
-        .map_err(|_| Error::Serialisation("Could not serialise credit".to_string()))?;
-
-    let mut credit_sig_shares = BTreeMap::new();
-
-    for i in 0..secret_key_set.threshold() + 1 {
-        let secret_key = secret_key_set.secret_key_share(i);
-        let credit_sig_share = secret_key.sign(serialised_credit.clone());
-        let _ = credit_sig_shares.insert(0, credit_sig_share);
-    }
-
-    let peer_replicas = secret_key_set.public_keys();
-
-    // Combine shares to produce the main signature.
-    let actor_signature = sn_data_types::Signature::Bls(
-        peer_replicas
-            .combine_signatures(&credit_sig_shares)
-            .map_err(|_| Error::CannotAggregate)?,
-    );
-
-    let signed_credit = SignedCredit {
-        credit,
-        actor_signature,
+/// An Actor with the balance, and the key set of its Replicas, of threshold 1 and 7 shares.
+pub fn get_actor_and_replicas_sk_set(amount: u64) -> Result<(Actor<Keypair>, SecretKeySet)> {
+    let mut rng = rand::thread_rng();
+    let keypair = Keypair::new_ed25519(&mut rng);
+    let client_pubkey = keypair.public_key();
+    let bls_secret_key = SecretKeySet::random(1, &mut rng);
+    let replicas_id = bls_secret_key.public_keys();
+    let balance = Token::from_nano(amount);
+    let sender = Dot::new(PublicKey::from(SecretKey::random().public_key()), 0);
+    let credit = get_credit(sender, client_pubkey, balance)?;
+    let mut wallet = Wallet::new(OwnerType::Single(credit.recipient()));
+    wallet.apply_credit(credit)?;
+
+    let replicas = SectionElders {
+        prefix: Prefix::default(),
+        names: Default::default(),
+        key_set: replicas_id,
     };
 
-    // replicas signatures over > signed_credit <
-
-    let serialised_credit = bincode::serialize(&signed_credit)
-        .map_err(|_| Error::Serialisation("Could not serialise signed_credit".to_string()))?;
-
-    let mut credit_sig_shares = BTreeMap::new();
-
-    for i in 0..secret_key_set.threshold() + 1 {
-        let secret_key = secret_key_set.secret_key_share(i);
-        let credit_sig_share = secret_key.sign(serialised_credit.clone());
-        let _ = credit_sig_shares.insert(0, credit_sig_share);
-    }
-
-    let debiting_replicas_sig = sn_data_types::Signature::Bls(
-        peer_replicas
-            .combine_signatures(&credit_sig_shares)
-            .map_err(|_| Error::CannotAggregate)?,
-    );
-
-    Ok(CreditAgreementProof {
-        signed_credit,
-        debiting_replicas_sig,
-        debiting_replicas_keys: peer_replicas,
-    })
+    let actor = Actor::from_snapshot(wallet, keypair, replicas);
+    Ok((actor, bls_secret_key))
 }
 
-pub struct Network {
-    pub genesis_credit: CreditAgreementProof,
-    pub sections: Vec<Section>,
-    pub actors: Vec<TestActor>,
+/// A transfer of 10 nanos, by the Actor, to a random recipient.
+pub fn get_debit(actor: &Actor<Keypair>) -> Result<TransferInitiated> {
+    let event = actor
+        .transfer(
+            Token::from_nano(10),
+            PublicKey::from(SecretKey::random().public_key()),
+            "asdf".to_string(),
+        )?
+        .ok_or(Error::TransferCreationFailed)?;
+    Ok(event)
 }
 
-#[derive(Debug, Clone)]
-pub struct TestWallet {
-    pub wallet: Wallet,
-    pub keypair: Keypair,
-    pub section: u8,
-}
-
-#[derive(Debug, Clone)]
-pub struct TestActor {
-    pub actor: Actor<Keypair>,
-    pub section: Section,
-}
-#[derive(Debug, Clone)]
-pub struct Elder {
-    pub id: PublicKeyShare,
-    pub replicas: HashMap<PublicKey, WalletReplica>,
-    pub signing: ReplicaSigning,
-}
-
-#[derive(Debug, Clone)]
-pub struct Section {
-    pub index: u8,
-    pub id: PublicKeySet,
-    pub elders: Vec<Elder>,
-}
-
-#[derive(Debug, Clone)]
-pub struct SectionKeys {
-    pub index: u8,
-    pub id: PublicKeySet,
-    pub keys: Vec<(SecretKeyShare, usize)>,
-}
+/// The validations of the transfer, by each of the 7 Replicas of the key set.
+pub fn get_transfer_validation_vec(
+    transfer: TransferInitiated,
+    sk_set: &SecretKeySet,
+) -> Result<Vec<TransferValidated>> {
+    let signed_debit = transfer.signed_debit;
+    let signed_credit = transfer.signed_credit;
+    let serialized_signed_debit = serialise(&signed_debit)?;
+    let serialized_signed_credit = serialise(&signed_credit)?;
+
+    let sk_shares: Vec<_> = (0..7).map(|i| sk_set.secret_key_share(i)).collect();
+    let pk_set = sk_set.public_keys();
+
+    let debit_sig_shares: BTreeMap<_, _> = (0..7)
+        .map(|i| (i, sk_shares[i].sign(serialized_signed_debit.clone())))
+        .collect();
+    let credit_sig_shares: BTreeMap<_, _> = (0..7)
+        .map(|i| (i, sk_shares[i].sign(serialized_signed_credit.clone())))
+        .collect();
+
+    let mut validated_transfers = vec![];
+
+    for i in 0..7 {
+        let debit_sig_share = &debit_sig_shares[&i];
+        let credit_sig_share = &credit_sig_shares[&i];
+        assert!(pk_set
+            .public_key_share(i)
+            .verify(debit_sig_share, serialized_signed_debit.clone()));
+        assert!(pk_set
+            .public_key_share(i)
+            .verify(credit_sig_share, serialized_signed_credit.clone()));
+        validated_transfers.push(TransferValidated {
+            signed_debit: signed_debit.clone(),
+            signed_credit: signed_credit.clone(),
+            replica_debit_sig: SignatureShare {
+                index: i,
+                share: debit_sig_share.clone(),
+            },
+            replica_credit_sig: SignatureShare {
+                index: i,
+                share: credit_sig_share.clone(),
+            },
+            replicas: pk_set.clone(),
+        })
+    }
 
-#[derive(Debug, Clone)]
-pub struct TestSigning {
-    pub keypair: Arc<Keypair>,
+    Ok(validated_transfers)
 }
 
-/// An impl of ReplicaSigningTrait.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ReplicaSigning {
-    /// The public key share of this Replica.
-    id: PublicKeyShare,
-    /// Secret key share.
-    secret_key: SecretKeyShare,
-    /// The index of this Replica key share, in the group set.
-    key_index: usize,
-    /// The PK set of our peer Replicas.
-    peer_replicas: PublicKeySet,
+/// A credit of the amount, as made by the debit of the sender.
+pub fn get_credit(from: Dot<PublicKey>, recipient: PublicKey, amount: Token) -> Result<Credit> {
+    let debit = Debit { id: from, amount };
+    Ok(Credit {
+        id: debit.credit_id()?,
+        recipient,
+        amount,
+        msg: "asdf".to_string(),
+    })
 }
 
-impl ReplicaSigning {
-    /// A new instance
-    pub fn new(secret_key: SecretKeyShare, key_index: usize, peer_replicas: PublicKeySet) -> Self {
-        let id = secret_key.public_key_share();
-        Self {
-            id,
-            secret_key,
-            key_index,
-            peer_replicas,
-        }
-    }
-
-    /// Get the replica's PK set
-    #[allow(unused)]
-    pub fn replicas_pk_set(&self) -> &PublicKeySet {
-        &self.peer_replicas
-    }
-
-    #[allow(unused)]
-    pub fn try_genesis(&self, balance: u64) -> Result<CreditAgreementProof> {
-        get_genesis(
-            balance,
-            PublicKey::Bls(self.peer_replicas.public_key()),
-            self.peer_replicas.clone(),
-            self.secret_key.clone(),
-        )
-    }
-
-    pub fn sign_transfer(
-        &self,
-        signed_transfer: &SignedTransfer,
-    ) -> Result<(SignatureShare, SignatureShare)> {
-        let replica_debit_sig = self.sign_validated_debit(&signed_transfer.debit)?;
-        let replica_credit_sig = self.sign_validated_credit(&signed_transfer.credit)?;
-        Ok((replica_debit_sig, replica_credit_sig))
-    }
-
-    pub fn sign_validated_debit(&self, debit: &SignedDebit) -> Result<SignatureShare> {
-        match bincode::serialize(debit) {
-            Err(_) => Err(Error::Serialisation("Could not serialise debit".into())),
-            Ok(data) => Ok(SignatureShare {
-                index: self.key_index,
-                share: self.secret_key.sign(data),
-            }),
-        }
-    }
-
-    pub fn sign_validated_credit(&self, credit: &SignedCredit) -> Result<SignatureShare> {
-        match bincode::serialize(credit) {
-            Err(_) => Err(Error::Serialisation("Could not serialise credit".into())),
-            Ok(data) => Ok(SignatureShare {
-                index: self.key_index,
-                share: self.secret_key.sign(data),
-            }),
-        }
-    }
+fn serialise<T: serde::Serialize>(item: &T) -> Result<Vec<u8>> {
+    bincode::serialize(item).map_err(|_| Error::Serialisation("Could not serialise item".into()))
 }
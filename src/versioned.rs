@@ -0,0 +1,41 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! The format version that the bytes of snapshots, receipts and wallet files are prefixed by.
+//!
+//! Every format starts at version 1. Bytes of any version other than the current one
+//! are rejected with `Error::UnsupportedVersion`, until a released format is changed,
+//! and the decoder of its previous version added.
+
+use crate::{Error, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The item as bytes, prefixed by the version of its format.
+pub(crate) fn to_versioned_bytes<T: Serialize>(version: u16, item: &T) -> Result<Vec<u8>> {
+    let mut bytes = version.to_le_bytes().to_vec();
+    bytes.extend(
+        bincode::serialize(item)
+            .map_err(|_| Error::Serialisation("Could not serialise item".into()))?,
+    );
+    Ok(bytes)
+}
+
+/// The format version the bytes are prefixed by, and the bytes following it.
+pub(crate) fn split_version(bytes: &[u8]) -> Result<(u16, &[u8])> {
+    if bytes.len() < 2 {
+        return Err(Error::Serialisation("Missing format version".into()));
+    }
+    let (version, item) = bytes.split_at(2);
+    Ok((u16::from_le_bytes([version[0], version[1]]), item))
+}
+
+/// The item from the bytes following the format version.
+pub(crate) fn deserialise<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    bincode::deserialize(bytes)
+        .map_err(|_| Error::Serialisation("Could not deserialise item".into()))
+}
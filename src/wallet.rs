@@ -6,16 +6,20 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{Error, Result};
+use crate::{
+    escrow::{CreditCondition, LockedCredit},
+    Error, Result,
+};
 use log::debug;
 use sn_data_types::{Credit, CreditId, Debit, OwnerType, Token};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 #[derive(Debug, Clone)]
 pub struct WalletSnapshot {
     pub balance: Token,
     pub debit_version: u64,
     pub credit_ids: HashSet<CreditId>,
+    pub locked: BTreeMap<CreditId, LockedCredit>,
 }
 
 impl From<Wallet> for WalletSnapshot {
@@ -24,17 +28,21 @@ impl From<Wallet> for WalletSnapshot {
             balance: other.balance,
             debit_version: other.debit_version,
             credit_ids: other.credit_ids,
+            locked: other.locked,
         }
     }
 }
 
 /// The balance and history of transfers for a wallet.
+/// The balance includes conditional credits, which are locked
+/// until their condition is met.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Wallet {
     id: OwnerType,
     balance: Token,
     debit_version: u64,
     credit_ids: HashSet<CreditId>,
+    locked: BTreeMap<CreditId, LockedCredit>,
 }
 
 impl Wallet {
@@ -45,6 +53,7 @@ impl Wallet {
             balance: Token::zero(),
             debit_version: 0,
             credit_ids: Default::default(),
+            locked: Default::default(),
         }
     }
 
@@ -60,9 +69,16 @@ impl Wallet {
             balance,
             debit_version,
             credit_ids,
+            locked: Default::default(),
         }
     }
 
+    /// Sets the locked credits, of a wallet created from existing state.
+    pub(crate) fn with_locked(mut self, locked: BTreeMap<CreditId, LockedCredit>) -> Self {
+        self.locked = locked;
+        self
+    }
+
     /// Get the id of the wallet.
     pub fn id(&self) -> &OwnerType {
         &self.id
@@ -78,6 +94,29 @@ impl Wallet {
         self.balance
     }
 
+    /// Query for the part of the balance which cannot be debited at the epoch.
+    pub fn locked_balance(&self, epoch: u64) -> Token {
+        let locked = self
+            .locked
+            .values()
+            .filter(|c| c.condition.is_locked_at(epoch))
+            .map(|c| c.amount.as_nano())
+            .sum();
+        Token::from_nano(locked)
+    }
+
+    /// Query for the part of the balance which can be debited at the epoch.
+    pub fn unlocked_balance(&self, epoch: u64) -> Token {
+        self.balance
+            .checked_sub(self.locked_balance(epoch))
+            .unwrap_or_else(Token::zero)
+    }
+
+    /// Query for a conditional credit, which has not been released or refunded.
+    pub fn locked(&self, id: &CreditId) -> Option<&LockedCredit> {
+        self.locked.get(id)
+    }
+
     /// Query for already received credit.
     pub fn contains(&self, id: &CreditId) -> bool {
         self.credit_ids.contains(id)
@@ -107,12 +146,42 @@ impl Wallet {
                 None => return Err(Error::AdditionOverflow(self.balance, credit.amount)),
             }
             let _ = self.credit_ids.insert(credit.id);
+            if let Ok(Some((condition, _))) = CreditCondition::decode(&credit.msg) {
+                let _ = self.locked.insert(
+                    credit.id,
+                    LockedCredit {
+                        amount: credit.amount,
+                        condition,
+                    },
+                );
+            }
             Ok(())
         } else {
             Err(Error::CreditDoesNotBelong(self.id().public_key(), credit))
         }
     }
 
+    /// Mutates state.
+    /// The released credit is no longer locked.
+    pub fn apply_release(&mut self, id: &CreditId) -> Result<()> {
+        match self.locked.remove(id) {
+            Some(_) => Ok(()),
+            None => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// Mutates state.
+    /// The refunded credit leaves the balance.
+    pub fn apply_refund(&mut self, id: &CreditId) -> Result<()> {
+        let locked = self.locked.get(id).ok_or(Error::InvalidOperation)?;
+        match self.balance.checked_sub(locked.amount) {
+            Some(amount) => self.balance = amount,
+            None => return Err(Error::SubtractionOverflow(locked.amount, self.balance)),
+        }
+        let _ = self.locked.remove(id);
+        Ok(())
+    }
+
     /// Test-helper API to simulate Client Transfers.
     #[cfg(feature = "simulated-payouts")]
     pub fn simulated_credit(&mut self, credit: Credit) -> Result<()> {
@@ -0,0 +1,519 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! An encrypted file of the state of an Actor, and of its signing keys, for storing a wallet at rest.
+//!
+//! The key is derived from a password by Argon2id (RFC 9106), with a random salt.
+//! The contents are encrypted by XChaCha20-Poly1305 under a random nonce, fresh at every write,
+//! with the header as associated data, so that a wrong password and a tampered file
+//! are told apart from a valid file before anything is deserialised.
+//! The file, and the temporary file it is written from, are readable by their owner only.
+//!
+//! The file is the format version, followed by the header (the KDF parameters, the salt
+//! and the nonce) and the ciphertext, the authentication tag of which ends it.
+
+use crate::{
+    actor::Actor,
+    actor_store::InFlight,
+    actor_store::StoredActor,
+    event_log::write_atomic_private,
+    subscribers::Notification,
+    versioned::{deserialise, split_version, to_versioned_bytes},
+    ActorEvent, Error, FeePolicy, Result,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sn_data_types::Signing;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use zeroize::Zeroizing;
+
+/// The current version of the wallet file format.
+pub const WALLET_FILE_VERSION: u16 = 1;
+
+/// The most memory, in KiB, that a wallet file may require
+/// for deriving its key (1 GiB), so that a tampered header cannot exhaust it.
+const MAX_MEMORY_KIB: u32 = 1 << 20;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+/// The parameters of the derivation of the key from the password by Argon2id.
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct KdfParams {
+    /// The memory used, in KiB.
+    pub memory_kib: u32,
+    /// The number of passes over the memory.
+    pub time_cost: u32,
+    /// The number of lanes the memory is split into.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// 19 MiB, over 2 passes and a single lane, as recommended by OWASP.
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    fn validate(&self) -> Result<()> {
+        self.argon2().map(|_| ())
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>> {
+        if self.memory_kib > MAX_MEMORY_KIB {
+            return Err(Error::InvalidOperation);
+        }
+        let params = Params::new(
+            self.memory_kib,
+            self.time_cost,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|_| Error::InvalidOperation)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// The state of an Actor, and its signing, in an encrypted file.
+///
+/// Like the [ActorStore](crate::ActorStore), the file is written atomically
+/// after every applied event, and the transfers in flight are returned when it is opened.
+/// The key derived from the password is kept while the wallet is open,
+/// so that writes do not derive it again, and erased when it is locked.
+pub struct WalletFile<S: Signing> {
+    path: PathBuf,
+    kdf: KdfParams,
+    salt: [u8; SALT_LEN],
+    key: Zeroizing<[u8; KEY_LEN]>,
+    stored: StoredActor<S>,
+}
+
+impl<S: Signing + Clone + Serialize + DeserializeOwned> WalletFile<S> {
+    /// Creates the wallet file of the Actor at the path, encrypted with the password,
+    /// replacing any existing file.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        actor: Actor<S>,
+        password: &str,
+        kdf: KdfParams,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let salt: [u8; SALT_LEN] = rand::random();
+        let key = derive_key(password.as_bytes(), &salt, kdf)?;
+        let stored = StoredActor::new(actor, |actor, snapshot| {
+            write_sealed(&path, &key, kdf, salt, actor, snapshot)
+        })?;
+        Ok(Self {
+            path,
+            kdf,
+            salt,
+            key,
+            stored,
+        })
+    }
+
+    /// Opens the wallet file at the path with the password, restoring the Actor,
+    /// and returns it along with the transfers which were in flight.
+    pub fn open<P: AsRef<Path>>(path: P, password: &str) -> Result<(Self, Vec<InFlight>)> {
+        let path = path.as_ref().to_path_buf();
+        let bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        let sealed = Sealed::from_bytes(&bytes)?;
+        let kdf = sealed.header.kdf;
+        let key = derive_key(password.as_bytes(), &sealed.header.salt, kdf)?;
+        let plaintext = sealed.open(&key)?;
+        let contents: Contents<S> = bincode::deserialize(&plaintext)
+            .map_err(|_| Error::Serialisation("Could not deserialise wallet contents".into()))?;
+        let (stored, in_flight) = StoredActor::restore(contents.signing, contents.snapshot)?;
+        let wallet = Self {
+            path,
+            kdf,
+            salt: sealed.header.salt,
+            key,
+            stored,
+        };
+        Ok((wallet, in_flight))
+    }
+
+    /// Locks the wallet, erasing the key from memory.
+    /// The file is to be opened with the password again.
+    pub fn lock(self) {}
+
+    /// Encrypts the wallet file with a new password, and KDF parameters.
+    /// Returns an error, and leaves the file as it was, if the current password is wrong.
+    pub fn change_password(&mut self, current: &str, new: &str, kdf: KdfParams) -> Result<()> {
+        kdf.validate()?;
+        let current_key = derive_key(current.as_bytes(), &self.salt, self.kdf)?;
+        if !constant_time_eq(&current_key[..], &self.key[..]) {
+            return Err(Error::DecryptionFailed);
+        }
+        let salt: [u8; SALT_LEN] = rand::random();
+        let key = derive_key(new.as_bytes(), &salt, kdf)?;
+        let actor = self.stored.actor();
+        write_sealed(
+            &self.path,
+            &key,
+            kdf,
+            salt,
+            actor,
+            &actor.snapshot().to_bytes()?,
+        )?;
+        self.kdf = kdf;
+        self.salt = salt;
+        self.key = key;
+        Ok(())
+    }
+
+    /// The Actor, for its queries and cmds.
+    pub fn actor(&self) -> &Actor<S> {
+        self.stored.actor()
+    }
+
+    /// The path of the wallet file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The parameters of the derivation of the key.
+    pub fn kdf(&self) -> KdfParams {
+        self.kdf
+    }
+
+    /// Sets the max number of initiated transfers that can await registration at the same time.
+    pub fn set_max_pending_debits(&mut self, max: u64) -> Result<()> {
+        self.stored.set_max_pending_debits(max)
+    }
+
+    /// Sets the fee policy of the Replicas of the Actor.
+    pub fn set_fee_policy(&mut self, fee_policy: FeePolicy) {
+        self.stored.set_fee_policy(fee_policy)
+    }
+
+    /// Subscribes to the notifications of the Actor.
+    pub fn subscribe(&mut self) -> Receiver<Notification> {
+        self.stored.subscribe()
+    }
+
+    /// Applies the event to the Actor, and writes the resulting state to the file.
+    /// If the state cannot be written, the event is not applied,
+    /// and the subscribers are not notified of it.
+    pub fn apply(&mut self, event: ActorEvent) -> Result<()> {
+        let (path, key, kdf, salt) = (&self.path, &self.key, self.kdf, self.salt);
+        self.stored.apply(event, |actor, snapshot| {
+            write_sealed(path, key, kdf, salt, actor, snapshot)
+        })
+    }
+}
+
+impl<S: Signing> fmt::Debug for WalletFile<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "WalletFile {{ path: {:?}, kdf: {:?}, actor: {:?} }}",
+            self.path,
+            self.kdf,
+            self.stored.actor().id()
+        )
+    }
+}
+
+/// What is encrypted: the signing, and the bytes of the snapshot of the Actor.
+#[derive(Serialize, Deserialize)]
+struct Contents<S> {
+    signing: S,
+    snapshot: Vec<u8>,
+}
+
+/// What is authenticated, but not encrypted.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Header {
+    kdf: KdfParams,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    header: Header,
+    /// The encrypted contents, followed by the authentication tag.
+    ciphertext: Vec<u8>,
+}
+
+impl Sealed {
+    fn seal(key: &[u8; KEY_LEN], header: Header, plaintext: &[u8]) -> Result<Self> {
+        let ciphertext = encrypt(key, &header.nonce, &header_bytes(&header)?, plaintext)?;
+        Ok(Self { header, ciphertext })
+    }
+
+    /// The plaintext, if the tag is valid.
+    fn open(&self, key: &[u8; KEY_LEN]) -> Result<Zeroizing<Vec<u8>>> {
+        decrypt(
+            key,
+            &self.header.nonce,
+            &header_bytes(&self.header)?,
+            &self.ciphertext,
+        )
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        to_versioned_bytes(WALLET_FILE_VERSION, self)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match split_version(bytes)? {
+            (WALLET_FILE_VERSION, sealed) => deserialise(sealed),
+            (version, _) => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Encrypts the signing of the Actor, and the bytes of its snapshot, to the file.
+fn write_sealed<S: Signing + Serialize>(
+    path: &Path,
+    key: &[u8; KEY_LEN],
+    kdf: KdfParams,
+    salt: [u8; SALT_LEN],
+    actor: &Actor<S>,
+    snapshot: &[u8],
+) -> Result<()> {
+    let contents = Contents {
+        signing: actor.signing(),
+        snapshot: snapshot.to_vec(),
+    };
+    let plaintext = Zeroizing::new(
+        bincode::serialize(&contents)
+            .map_err(|_| Error::Serialisation("Could not serialise wallet contents".into()))?,
+    );
+    let header = Header {
+        kdf,
+        salt,
+        nonce: rand::random(),
+    };
+    write_atomic_private(path, &Sealed::seal(key, header, &plaintext)?.to_bytes()?)
+}
+
+/// The header, prefixed by the format version, as authenticated.
+fn header_bytes(header: &Header) -> Result<Vec<u8>> {
+    to_versioned_bytes(WALLET_FILE_VERSION, header)
+}
+
+/// XChaCha20-Poly1305: the ciphertext, followed by the tag.
+fn encrypt(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Serialisation("Could not encrypt wallet contents".into()))
+}
+
+/// XChaCha20-Poly1305: the plaintext, if the tag at the end of the ciphertext is valid.
+fn decrypt(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Argon2id, version 0x13, without secret nor associated data.
+fn derive_key(password: &[u8], salt: &[u8], kdf: KdfParams) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    let mut key = Zeroizing::new([0; KEY_LEN]);
+    kdf.argon2()?
+        .hash_password_into(password, salt, &mut key[..])
+        .map_err(|_| Error::InvalidOperation)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        decrypt, derive_key, encrypt, KdfParams, WalletFile, MAX_MEMORY_KIB, WALLET_FILE_VERSION,
+    };
+    use crate::{actor::Actor, Error, Result};
+    use sn_data_types::{Keypair, SectionElders};
+    use threshold_crypto::SecretKeySet;
+    use xor_name::Prefix;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("hex"))
+            .collect()
+    }
+
+    #[test]
+    fn key_derivation_matches_argon2id_vectors() -> Result<()> {
+        // from the test suite of the reference implementation (phc-winner-argon2, test.c)
+        let vectors = [
+            (
+                1 << 16,
+                "09316115d5cf24ed5a15a31a3ba326e5cf32edc24702987c02b6566f61913cf7",
+            ),
+            (
+                1 << 8,
+                "9dfeb910e80bad0311fee20f9c0e2b12c17987b4cac90c2ef54d5b3021c68bfe",
+            ),
+        ];
+        for (memory_kib, key) in vectors.iter() {
+            let kdf = KdfParams {
+                memory_kib: *memory_kib,
+                time_cost: 2,
+                parallelism: 1,
+            };
+            let derived = derive_key(b"password", b"somesalt", kdf)?;
+            assert_eq!(&derived[..], &from_hex(key)[..]);
+        }
+
+        // a tampered header cannot make the derivation exhaust the memory
+        let kdf = KdfParams {
+            memory_kib: MAX_MEMORY_KIB + 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            derive_key(b"password", b"somesalt", kdf).map(|_| ()),
+            Err(Error::InvalidOperation)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encryption_matches_xchacha20_poly1305_vector() -> Result<()> {
+        // draft-irtf-cfrg-xchacha-03, A.3.1
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+            only one tip for the future, sunscreen would be it.";
+        let aad = from_hex("50515253c0c1c2c3c4c5c6c7");
+        let mut key = [0; 32];
+        key.copy_from_slice(&from_hex(
+            "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+        ));
+        let mut nonce = [0; 24];
+        nonce.copy_from_slice(&from_hex(
+            "404142434445464748494a4b4c4d4e4f5051525354555657",
+        ));
+        let sealed = from_hex(concat!(
+            "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb",
+            "731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452",
+            "2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9",
+            "21f9664c97637da9768812f615c68b13b52e",
+            "c0875924c1c7987947deafd8780acf49",
+        ));
+        assert_eq!(encrypt(&key, &nonce, &aad, plaintext)?, sealed);
+        assert_eq!(&decrypt(&key, &nonce, &aad, &sealed)?[..], &plaintext[..]);
+        assert_eq!(
+            decrypt(&key, &nonce, b"other", &sealed).map(|_| ()),
+            Err(Error::DecryptionFailed)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wallet_file_is_opened_by_password_only() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let kdf = KdfParams {
+            memory_kib: 64,
+            time_cost: 1,
+            parallelism: 1,
+        };
+
+        let keypair = Keypair::new_ed25519(&mut rng);
+        let replicas = SectionElders {
+            prefix: Prefix::default(),
+            names: Default::default(),
+            key_set: SecretKeySet::random(0, &mut rng).public_keys(),
+        };
+        let actor = Actor::new(keypair.clone(), replicas);
+        let id = actor.id();
+        let dir = tempfile::tempdir().map_err(|e| Error::Io(e.to_string()))?;
+        let path = dir.path().join("wallet");
+        WalletFile::create(&path, actor, "correct horse", kdf)?.lock();
+
+        // only the owner can read the file
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(&path).map_err(|e| Error::Io(e.to_string()))?;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        // the signing keys are not stored in plaintext
+        let bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        let plaintext =
+            bincode::serialize(&keypair).map_err(|e| Error::Serialisation(e.to_string()))?;
+        assert!(!bytes.windows(plaintext.len()).any(|w| w == &plaintext[..]));
+
+        assert_eq!(
+            WalletFile::<Keypair>::open(&path, "battery staple").map(|_| ()),
+            Err(Error::DecryptionFailed)
+        );
+        let (mut wallet, in_flight) = WalletFile::<Keypair>::open(&path, "correct horse")?;
+        assert_eq!(wallet.actor().id(), id);
+        assert!(in_flight.is_empty());
+
+        assert_eq!(
+            wallet.change_password("battery staple", "staple", kdf),
+            Err(Error::DecryptionFailed)
+        );
+        wallet.change_password("correct horse", "battery staple", kdf)?;
+        wallet.lock();
+        assert!(WalletFile::<Keypair>::open(&path, "correct horse").is_err());
+        let _ = WalletFile::<Keypair>::open(&path, "battery staple")?;
+
+        // any change to the file is detected
+        let mut bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        let last = bytes.len() - 40;
+        bytes[last] ^= 1;
+        std::fs::write(&path, bytes).map_err(|e| Error::Io(e.to_string()))?;
+        assert_eq!(
+            WalletFile::<Keypair>::open(&path, "battery staple").map(|_| ()),
+            Err(Error::DecryptionFailed)
+        );
+
+        // the version is read before the file is decrypted
+        let mut bytes = std::fs::read(&path).map_err(|e| Error::Io(e.to_string()))?;
+        bytes[..2].copy_from_slice(&(WALLET_FILE_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).map_err(|e| Error::Io(e.to_string()))?;
+        assert_eq!(
+            WalletFile::<Keypair>::open(&path, "battery staple").map(|_| ()),
+            Err(Error::UnsupportedVersion(WALLET_FILE_VERSION + 1))
+        );
+        Ok(())
+    }
+}
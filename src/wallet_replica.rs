@@ -7,20 +7,37 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    batch::{
+        batch_credit_id, sum_amounts, BatchTransferAgreementProof, BatchTransferRegistered,
+        BatchTransferValidated, SignedBatchTransfer,
+    },
+    equivocation::{conflicts, EquivocationProof},
+    escrow::{escrow_refund, CreditCondition, EscrowRefunded, EscrowRelease, EscrowReleased},
+    fees::{fee_credit_id, paid_fee, FeePolicy},
+    htlc::{hash_lock, HtlcClaim, HtlcClaimed},
+    known_sections::{section_key, KnownSections},
+    multisig_actor::MultisigTransfer,
+    replica_signing::ReplicaSigning,
+    replica_snapshot::WalletReplicaSnapshot,
+    spending_policy::{
+        MultisigBatchValidated, MultisigTransferValidated, Spend, SpendingPolicy,
+        SpendingPolicyDeclared,
+    },
+    subscribers::{Notification, Subscribers},
     wallet::{Wallet, WalletSnapshot},
-    Outcome, TernaryResult,
+    EquivocationDetected, FeeCredited, Outcome, TernaryResult, WalletReplicaEvent,
 };
 use crate::{Error, Result};
 use log::{debug, error};
-#[cfg(feature = "simulated-payouts")]
-use sn_data_types::Credit;
+use serde::Serialize;
 use sn_data_types::{
-    CreditAgreementProof, Debit, OwnerType, ReplicaEvent, Signature, SignedCredit, SignedDebit,
-    SignedTransfer, SignedTransferShare, Token, TransferAgreementProof, TransferRegistered,
-    TransferValidationProposed,
+    Credit, CreditAgreementProof, CreditId, Debit, OwnerType, ReplicaEvent, Signature,
+    SignatureShare, SignedCredit, SignedDebit, SignedTransfer, SignedTransferShare, Token,
+    TransferAgreementProof, TransferRegistered, TransferValidated, TransferValidationProposed,
 };
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
+use std::sync::mpsc::Receiver;
 use threshold_crypto::{PublicKeySet, PublicKeyShare};
 
 macro_rules! hashmap {
@@ -55,6 +72,25 @@ pub struct WalletReplica {
     /// Ensures that invidual wallet's debit
     /// initiations (ValidateTransfer cmd) are sequential.
     pending_debit: Option<u64>,
+    /// Validated transfers not yet registered, by debit counter.
+    pending_transfers: BTreeMap<u64, SignedTransfer>,
+    /// Validated batch transfers not yet registered, by debit counter.
+    pending_batches: BTreeMap<u64, SignedBatchTransfer>,
+    /// The registered transfers, and batches, by debit counter,
+    /// against which transfers are checked for equivocation.
+    registered_transfers: BTreeMap<u64, SignedBatchTransfer>,
+    /// The evidence of equivocation by the Actor, if the wallet is frozen.
+    equivocation: Option<EquivocationProof>,
+    /// The spending policy of a multisig wallet, if declared by its owners.
+    spending_policy: Option<SpendingPolicy>,
+    /// The validated debits of a wallet with a spending policy, by debit counter.
+    spends: BTreeMap<u64, Spend>,
+    /// The fee policy of the section.
+    fee_policy: FeePolicy,
+    /// The section time, by which conditional credits are unlocked.
+    epoch: u64,
+    /// The subscribers to notifications of changes to the wallet.
+    subscribers: Subscribers,
 }
 
 impl WalletReplica {
@@ -102,13 +138,108 @@ impl WalletReplica {
             wallet,
             pending_proposals,
             pending_debit,
+            pending_transfers: Default::default(),
+            pending_batches: Default::default(),
+            registered_transfers: Default::default(),
+            equivocation: None,
+            spending_policy: None,
+            spends: Default::default(),
+            fee_policy: Default::default(),
+            epoch: 0,
+            subscribers: Default::default(),
         }
     }
 
+    /// Sets the fee policy of the section, which debits must pay according to.
+    /// This is configuration, and not part of the state of the Replica,
+    /// so it is to be set again on a Replica restored from history or snapshot.
+    pub fn set_fee_policy(&mut self, fee_policy: FeePolicy) {
+        self.fee_policy = fee_policy;
+    }
+
+    /// Sets the section time, by which time-locked credits
+    /// are unlocked, and escrowed credits can be refunded.
+    /// Like the fee policy, it is set by the section, as time progresses,
+    /// and is to be set again on a restored Replica.
+    pub fn set_epoch(&mut self, epoch: u64) {
+        self.epoch = epoch;
+    }
+
+    /// Subscribes to notifications of incoming credits, completed debits,
+    /// balance changes and rejected validations. Drop the receiver to unsubscribe.
+    /// Like the fee policy, subscribers are not part of the state of the Replica.
+    pub fn subscribe(&mut self) -> Receiver<Notification> {
+        self.subscribers.subscribe()
+    }
+
+    /// A Replica instance restored from a full snapshot of its state.
+    pub fn from_replica_snapshot(snapshot: WalletReplicaSnapshot) -> Result<Self> {
+        let id: OwnerType = snapshot.id.into();
+        Ok(Self {
+            wallet: Wallet::from(
+                id.clone(),
+                snapshot.balance,
+                snapshot.debit_version,
+                snapshot.credit_ids,
+            )
+            .with_locked(snapshot.locked),
+            id,
+            replica_id: snapshot.replica_id,
+            key_index: snapshot.key_index,
+            peer_replicas: snapshot.peer_replicas,
+            pending_proposals: snapshot.pending_proposals,
+            pending_debit: snapshot.pending_debit,
+            pending_transfers: snapshot.pending_transfers,
+            pending_batches: snapshot.pending_batches,
+            registered_transfers: snapshot.registered_transfers,
+            equivocation: snapshot.equivocation,
+            spending_policy: snapshot.spending_policy,
+            spends: snapshot.spends,
+            fee_policy: Default::default(),
+            epoch: 0,
+            subscribers: Default::default(),
+        })
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Queries ----------------------------------
     /// -----------------------------------------------------------------
 
+    /// The owner of the Wallet.
+    pub fn id(&self) -> &OwnerType {
+        &self.id
+    }
+
+    /// The fee policy of the section.
+    pub fn fee_policy(&self) -> FeePolicy {
+        self.fee_policy
+    }
+
+    /// Whether the Actor has equivocated, so that no further debits are validated.
+    pub fn is_frozen(&self) -> bool {
+        self.equivocation.is_some()
+    }
+
+    /// The evidence of equivocation by the Actor, if any.
+    pub fn equivocation(&self) -> Option<&EquivocationProof> {
+        self.equivocation.as_ref()
+    }
+
+    /// The spending policy of a multisig wallet, if declared by its owners.
+    pub fn spending_policy(&self) -> Option<&SpendingPolicy> {
+        self.spending_policy.as_ref()
+    }
+
+    /// The section time.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// The part of the balance which cannot be debited, at the current epoch.
+    pub fn locked_balance(&self) -> Token {
+        self.wallet.locked_balance(self.epoch)
+    }
+
     ///
     pub fn balance(&self) -> Token {
         self.wallet.balance()
@@ -120,18 +251,46 @@ impl WalletReplica {
         Some(wallet.into())
     }
 
+    /// A full, serialisable, snapshot of the state of this Replica.
+    pub fn snapshot(&self) -> WalletReplicaSnapshot {
+        let wallet: WalletSnapshot = self.wallet.to_owned().into();
+        WalletReplicaSnapshot {
+            id: self.id.clone().into(),
+            replica_id: self.replica_id,
+            key_index: self.key_index,
+            peer_replicas: self.peer_replicas.clone(),
+            balance: wallet.balance,
+            debit_version: wallet.debit_version,
+            credit_ids: wallet.credit_ids,
+            locked: wallet.locked,
+            pending_proposals: self.pending_proposals.clone(),
+            pending_debit: self.pending_debit,
+            pending_transfers: self.pending_transfers.clone(),
+            pending_batches: self.pending_batches.clone(),
+            registered_transfers: self.registered_transfers.clone(),
+            equivocation: self.equivocation.clone(),
+            spending_policy: self.spending_policy.clone(),
+            spends: self.spends.clone(),
+        }
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Cmds -------------------------------------
     /// -----------------------------------------------------------------
 
     /// This is the one and only infusion of money to the system. Ever.
     /// It is carried out by the first node in the network.
-    pub fn genesis(&self, credit_proof: &CreditAgreementProof) -> Outcome<()> {
+    /// The key that signed the genesis credit must be a known section key.
+    pub fn genesis<K: KnownSections>(
+        &self,
+        credit_proof: &CreditAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<()> {
         // Genesis must be the first credit.
         if self.balance() != Token::zero() || self.pending_debit.is_some() {
             return Err(Error::InvalidOperation);
         }
-        self.receive_propagated(credit_proof)
+        self.receive_propagated(credit_proof, known_sections)
     }
 
     /// For now, with test token there is no from wallet.., token is created from thin air.
@@ -152,10 +311,37 @@ impl WalletReplica {
     }
 
     /// Step 1. Main business logic validation of a debit.
+    /// Subscribers are notified of a rejected validation.
     pub fn validate(
         &self,
         signed_debit: &SignedDebit,
         signed_credit: &SignedCredit,
+    ) -> Outcome<()> {
+        let outcome = self.validate_transfer(signed_debit, signed_credit, &[]);
+        self.notify_rejected(signed_debit, &outcome);
+        outcome
+    }
+
+    /// Step 1. Main business logic validation of a debit of a multisig wallet,
+    /// the spending policy of which is met by the owners whose debit shares
+    /// were combined into the wallet signature (see [MultisigTransfer]).
+    /// Subscribers are notified of a rejected validation.
+    pub fn validate_multisig(&self, transfer: &MultisigTransfer) -> Outcome<()> {
+        let signed_debit = &transfer.signed_transfer.debit;
+        let outcome = self.validate_transfer(
+            signed_debit,
+            &transfer.signed_transfer.credit,
+            &transfer.debit_shares,
+        );
+        self.notify_rejected(signed_debit, &outcome);
+        outcome
+    }
+
+    fn validate_transfer(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        debit_shares: &[SignatureShare],
     ) -> Outcome<()> {
         let debit = &signed_debit.debit;
         let credit = &signed_credit.credit;
@@ -166,17 +352,200 @@ impl WalletReplica {
             .is_err()
         {
             return Outcome::rejected(Error::InvalidSignature);
-        } else if debit.sender() == credit.recipient() {
-            return Outcome::rejected(Error::SameSenderAndRecipient);
+        } else if self.is_frozen() {
+            return Outcome::rejected(Error::WalletFrozen);
+        } else if self.wallet.id().public_key() != debit.sender() {
+            return Outcome::rejected(Error::NoSuchSender);
         } else if credit.id() != &debit.credit_id()? {
             return Outcome::rejected(Error::CreditDebitIdMismatch);
-        } else if credit.amount() != debit.amount() {
-            return Outcome::rejected(Error::CreditDebitValueMismatch);
-        } else if debit.amount() == Token::zero() {
+        } else if self
+            .conflicting_transfer(signed_debit, std::slice::from_ref(signed_credit))?
+            .is_some()
+        {
+            // See `detect_equivocation`.
+            return Outcome::rejected(Error::DebitEquivocation);
+        } else if debit.sender() == credit.recipient() {
+            return Outcome::rejected(Error::SameSenderAndRecipient);
+        } else if credit.amount() == Token::zero() {
             return Outcome::rejected(Error::ZeroValueTransfer);
+        } else if CreditCondition::decode(&credit.msg).is_err() {
+            // A malformed condition would not lock the credit at the recipient.
+            return Outcome::rejected(Error::InvalidOperation);
+        } else if let Err(error) = self
+            .fee_policy
+            .validate(debit.amount(), vec![(credit.amount(), credit.msg.as_str())])
+        {
+            return Outcome::rejected(error);
+        } else if !self.meets_spending_policy(debit, &self.debit_signers(debit, debit_shares)?) {
+            return Outcome::rejected(Error::SpendingPolicyNotMet);
+        } else if let Some(pending) = self.pending_transfers.get(&debit.id.counter) {
+            // A re-sent transfer, which we have already validated, is validated again.
+            if &pending.debit == signed_debit && &pending.credit == signed_credit {
+                return Outcome::success(());
+            }
+        }
+
+        self.validate_debit(debit)
+    }
+
+    /// Step 1. Main business logic validation of a batch debit,
+    /// paying the credits, in order.
+    /// Subscribers are notified of a rejected validation.
+    pub fn validate_batch(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+    ) -> Outcome<()> {
+        let outcome = self.validate_batch_transfer(signed_debit, signed_credits, &[]);
+        self.notify_rejected(signed_debit, &outcome);
+        outcome
+    }
+
+    /// Step 1. Main business logic validation of a batch debit of a multisig wallet,
+    /// the spending policy of which is met by the owners of the debit shares
+    /// combined into the wallet signature.
+    /// Subscribers are notified of a rejected validation.
+    pub fn validate_multisig_batch(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        debit_shares: &[SignatureShare],
+    ) -> Outcome<()> {
+        let outcome = self.validate_batch_transfer(signed_debit, signed_credits, debit_shares);
+        self.notify_rejected(signed_debit, &outcome);
+        outcome
+    }
+
+    fn validate_batch_transfer(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        debit_shares: &[SignatureShare],
+    ) -> Outcome<()> {
+        let debit = &signed_debit.debit;
+
+        // Always verify signature first! (as to not leak any information).
+        if self
+            .verify_batch_actor_signature(signed_debit, signed_credits)
+            .is_err()
+        {
+            return Outcome::rejected(Error::InvalidSignature);
+        } else if self.is_frozen() {
+            return Outcome::rejected(Error::WalletFrozen);
+        } else if signed_credits.is_empty() {
+            return Outcome::rejected(Error::InvalidOperation);
         } else if self.wallet.id().public_key() != debit.sender() {
             return Outcome::rejected(Error::NoSuchSender);
-        } else if self.pending_debit.is_none() && debit.id.counter != 0 {
+        }
+        for (index, signed_credit) in signed_credits.iter().enumerate() {
+            if signed_credit.id() != &batch_credit_id(&debit.id, index)? {
+                return Outcome::rejected(Error::CreditDebitIdMismatch);
+            }
+        }
+        if self
+            .conflicting_transfer(signed_debit, signed_credits)?
+            .is_some()
+        {
+            // See `detect_equivocation`.
+            return Outcome::rejected(Error::DebitEquivocation);
+        } else if signed_credits
+            .iter()
+            .any(|c| c.recipient() == debit.sender())
+        {
+            return Outcome::rejected(Error::SameSenderAndRecipient);
+        } else if signed_credits.iter().any(|c| c.amount() == Token::zero()) {
+            return Outcome::rejected(Error::ZeroValueTransfer);
+        } else if signed_credits
+            .iter()
+            .any(|c| CreditCondition::decode(&c.credit.msg).is_err())
+        {
+            // A malformed condition would not lock the credit at the recipient.
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        let credits = signed_credits
+            .iter()
+            .map(|c| (c.amount(), c.credit.msg.as_str()));
+        if let Err(error) = self.fee_policy.validate(debit.amount(), credits) {
+            return Outcome::rejected(error);
+        } else if !self.meets_spending_policy(debit, &self.debit_signers(debit, debit_shares)?) {
+            return Outcome::rejected(Error::SpendingPolicyNotMet);
+        } else if let Some(pending) = self.pending_batches.get(&debit.id.counter) {
+            // A re-sent batch, which we have already validated, is validated again.
+            if &pending.debit == signed_debit && pending.credits.as_slice() == signed_credits {
+                return Outcome::success(());
+            }
+        }
+
+        self.validate_debit(debit)
+    }
+
+    /// Step 1, followed by the signing of the validated batch
+    /// with the key share of this Replica.
+    /// The resulting event is to be applied, and then sent to the Actor.
+    pub fn validate_and_sign_batch<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        signing: &R,
+    ) -> Outcome<BatchTransferValidated> {
+        if signing.id() != self.replica_id || signing.replicas_pk_set() != &self.peer_replicas {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        if self.validate_batch(signed_debit, signed_credits)?.is_none() {
+            return Outcome::no_change();
+        }
+        Outcome::success(self.sign_batch(signed_debit, signed_credits, signing)?)
+    }
+
+    /// Step 1, for a batch of a multisig wallet, followed by the signing
+    /// of the validated batch with the key share of this Replica.
+    /// The resulting event is to be applied, and its validation then sent to the Actor.
+    pub fn validate_and_sign_multisig_batch<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        debit_shares: &[SignatureShare],
+        signing: &R,
+    ) -> Outcome<MultisigBatchValidated> {
+        if signing.id() != self.replica_id || signing.replicas_pk_set() != &self.peer_replicas {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        if self
+            .validate_multisig_batch(signed_debit, signed_credits, debit_shares)?
+            .is_none()
+        {
+            return Outcome::no_change();
+        }
+        Outcome::success(MultisigBatchValidated {
+            validated: self.sign_batch(signed_debit, signed_credits, signing)?,
+            signers: self.debit_signers(&signed_debit.debit, debit_shares)?,
+        })
+    }
+
+    fn sign_batch<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+        signing: &R,
+    ) -> Result<BatchTransferValidated> {
+        let replica_debit_sig = signing.sign_validated_debit(signed_debit)?;
+        let replica_credit_sigs = signed_credits
+            .iter()
+            .map(|c| signing.sign_validated_credit(c))
+            .collect::<Result<_>>()?;
+
+        Ok(BatchTransferValidated {
+            signed_debit: signed_debit.clone(),
+            signed_credits: signed_credits.to_vec(),
+            replica_debit_sig,
+            replica_credit_sigs,
+            replicas: self.peer_replicas.clone(),
+        })
+    }
+
+    /// The checks of order and balance, common to all debits.
+    fn validate_debit(&self, debit: &Debit) -> Outcome<()> {
+        if self.pending_debit.is_none() && debit.id.counter != 0 {
             return Outcome::rejected(Error::ShouldBeInitialOperation);
         } else if let Some(counter) = self.pending_debit {
             if debit.id.counter != (counter + 1) {
@@ -185,13 +554,79 @@ impl WalletReplica {
                     counter + 1,
                 ));
             }
-        } else if debit.amount() > self.balance() {
+        }
+        // Debits validated but not yet registered are reserved from the balance.
+        if debit.amount() > self.available_balance() {
             return Outcome::rejected(Error::InsufficientBalance);
         }
 
         Outcome::success(())
     }
 
+    /// Step 1, followed by the signing of the validated transfer
+    /// with the key share of this Replica.
+    /// The resulting event is to be applied, and then sent to the Actor.
+    pub fn validate_and_sign<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        signing: &R,
+    ) -> Outcome<TransferValidated> {
+        if signing.id() != self.replica_id || signing.replicas_pk_set() != &self.peer_replicas {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        if self.validate(signed_debit, signed_credit)?.is_none() {
+            return Outcome::no_change();
+        }
+        Outcome::success(self.sign_transfer(signed_debit, signed_credit, signing)?)
+    }
+
+    /// Step 1, for a transfer of a multisig wallet, followed by the signing
+    /// of the validated transfer with the key share of this Replica.
+    /// The resulting event is to be applied, and its validation then sent to the Actor.
+    pub fn validate_and_sign_multisig<R: ReplicaSigning>(
+        &self,
+        transfer: &MultisigTransfer,
+        signing: &R,
+    ) -> Outcome<MultisigTransferValidated> {
+        if signing.id() != self.replica_id || signing.replicas_pk_set() != &self.peer_replicas {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        if self.validate_multisig(transfer)?.is_none() {
+            return Outcome::no_change();
+        }
+        let signed_debit = &transfer.signed_transfer.debit;
+        Outcome::success(MultisigTransferValidated {
+            validated: self.sign_transfer(
+                signed_debit,
+                &transfer.signed_transfer.credit,
+                signing,
+            )?,
+            signers: self.debit_signers(&signed_debit.debit, &transfer.debit_shares)?,
+        })
+    }
+
+    fn sign_transfer<R: ReplicaSigning>(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        signing: &R,
+    ) -> Result<TransferValidated> {
+        let signed_transfer = SignedTransfer {
+            debit: signed_debit.clone(),
+            credit: signed_credit.clone(),
+        };
+        let (replica_debit_sig, replica_credit_sig) = signing.sign_transfer(&signed_transfer)?;
+
+        Ok(TransferValidated {
+            signed_debit: signed_transfer.debit,
+            signed_credit: signed_transfer.credit,
+            replica_debit_sig,
+            replica_credit_sig,
+            replicas: self.peer_replicas.clone(),
+        })
+    }
+
     /// Step 2. Validation of agreement, and order at debit source.
     pub fn register(&self, transfer_proof: &TransferAgreementProof) -> Outcome<TransferRegistered> {
         debug!("Checking registered transfer");
@@ -215,11 +650,248 @@ impl WalletReplica {
         }
     }
 
+    /// Step 2. Validation of agreement, and order at debit source, of a batch.
+    pub fn register_batch(
+        &self,
+        transfer_proof: &BatchTransferAgreementProof,
+    ) -> Outcome<BatchTransferRegistered> {
+        // Always verify signature first! (as to not leak any information).
+        if self.verify_registered_batch_proof(transfer_proof).is_err() {
+            return Err(Error::InvalidSignature);
+        }
+
+        let debit = &transfer_proof.signed_debit.debit;
+        if self.wallet.next_debit() == debit.id().counter {
+            Outcome::success(BatchTransferRegistered {
+                transfer_proof: transfer_proof.clone(),
+            })
+        } else {
+            Outcome::rejected(Error::OperationOutOfOrder(
+                debit.id().counter,
+                self.wallet.next_debit(),
+            ))
+        }
+    }
+
+    /// Detection of equivocation, i.e. of the Actor having signed the transfer
+    /// while it has already signed a different one with the same debit id,
+    /// which this Replica has validated, or registered. Such a transfer is rejected
+    /// by validation with `DebitEquivocation`, and can then be passed here, for the evidence.
+    /// The credits are those of a batch, or the one credit of a single transfer.
+    /// The resulting event freezes the wallet, and its proof is to be sent to the other Replicas.
+    pub fn detect_equivocation(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+    ) -> Outcome<EquivocationDetected> {
+        let first = match self.conflicting_transfer(signed_debit, signed_credits)? {
+            Some(first) => first,
+            None => return Outcome::no_change(),
+        };
+        let proof = EquivocationProof {
+            first,
+            second: SignedBatchTransfer {
+                debit: signed_debit.clone(),
+                credits: signed_credits.to_vec(),
+            },
+        };
+        // Always verify signature first! (as to not leak any information).
+        proof.verify()?;
+        if self.is_frozen() {
+            return Outcome::no_change();
+        }
+        Outcome::success(EquivocationDetected { proof })
+    }
+
+    /// Receipt of evidence of equivocation by the Actor,
+    /// such as detected by another Replica, or an auditor.
+    pub fn receive_equivocation_proof(
+        &self,
+        proof: &EquivocationProof,
+    ) -> Outcome<EquivocationDetected> {
+        // Always verify signature first! (as to not leak any information).
+        proof.verify()?;
+        if proof.sender() != self.id.public_key() {
+            return Outcome::rejected(Error::NoSuchSender);
+        } else if self.is_frozen() {
+            return Outcome::no_change();
+        }
+        Outcome::success(EquivocationDetected {
+            proof: proof.clone(),
+        })
+    }
+
+    /// Release of an escrowed credit, by its arbiter,
+    /// who has signed the [EscrowRelease] of the credit.
+    pub fn release_escrow(
+        &self,
+        credit_id: &CreditId,
+        arbiter_signature: &Signature,
+    ) -> Outcome<EscrowReleased> {
+        let arbiter = match self.wallet.locked(credit_id).map(|c| &c.condition) {
+            Some(CreditCondition::Escrow {
+                arbiter,
+                refund_epoch,
+                ..
+            }) => {
+                // From the refund epoch on, the credit can only be refunded.
+                if self.epoch >= *refund_epoch {
+                    return Outcome::rejected(Error::InvalidOperation);
+                }
+                arbiter
+            }
+            _ => return Outcome::rejected(Error::InvalidOperation),
+        };
+        let release = EscrowRelease {
+            credit_id: *credit_id,
+        };
+        verify_signature(arbiter, arbiter_signature, &release)?;
+        Outcome::success(EscrowReleased {
+            wallet: self.id.public_key(),
+            credit_id: *credit_id,
+        })
+    }
+
+    /// Claim of a hash-time-locked credit, by revealing the preimage
+    /// of its hash before the deadline. The claim is signed by this Replica,
+    /// with its key share. The resulting event is to be applied, and then sent
+    /// to the Actor of the wallet, which makes a proof of the claim from a quorum
+    /// of them (see [htlc_claim_proof]), and to the sender, which learns the preimage from it.
+    ///
+    /// [htlc_claim_proof]: crate::htlc_claim_proof
+    pub fn claim_htlc<R: ReplicaSigning>(
+        &self,
+        credit_id: &CreditId,
+        preimage: &[u8],
+        signing: &R,
+    ) -> Outcome<HtlcClaimed> {
+        if signing.id() != self.replica_id || signing.replicas_pk_set() != &self.peer_replicas {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        let (hash, deadline_epoch) = match self.wallet.locked(credit_id).map(|c| &c.condition) {
+            Some(CreditCondition::HashLock {
+                hash,
+                deadline_epoch,
+                ..
+            }) => (hash, *deadline_epoch),
+            _ => return Outcome::rejected(Error::InvalidOperation),
+        };
+        if &hash_lock(preimage) != hash {
+            return Outcome::rejected(Error::InvalidPreimage);
+        } else if self.epoch >= deadline_epoch {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        let claim = HtlcClaim {
+            wallet: self.id.public_key(),
+            credit_id: *credit_id,
+            preimage: preimage.to_vec(),
+        };
+        let replica_sig = signing.sign_htlc_claim(&claim)?;
+        Outcome::success(HtlcClaimed {
+            claim,
+            replica_sig,
+            replicas: self.peer_replicas.clone(),
+        })
+    }
+
+    /// Refund of an escrowed, or hash-time-locked, credit,
+    /// which has not been released or claimed by the refund epoch.
+    /// The refund credit (see [escrow_refund]) is signed by the key it is refunded to,
+    /// and then by this Replica, with its key share. The resulting event is to be applied,
+    /// and then sent to the refunded Actor, which makes a proof of the refund credit
+    /// from a quorum of them (see [refund_proof]), for propagation to its Replicas.
+    ///
+    /// [refund_proof]: crate::refund_proof
+    pub fn refund_escrow<R: ReplicaSigning>(
+        &self,
+        credit_id: &CreditId,
+        refund_signature: &Signature,
+        signing: &R,
+    ) -> Outcome<EscrowRefunded> {
+        if signing.id() != self.replica_id || signing.replicas_pk_set() != &self.peer_replicas {
+            return Outcome::rejected(Error::InvalidOperation);
+        }
+        let locked = match self.wallet.locked(credit_id) {
+            Some(locked) => locked,
+            None => return Outcome::rejected(Error::InvalidOperation),
+        };
+        let refund = escrow_refund(credit_id, locked)?;
+        // Always verify signature first! (as to not leak any information).
+        verify_signature(&refund.recipient, refund_signature, &refund)?;
+        match locked.condition.refund() {
+            Some((_, refund_epoch)) if refund_epoch <= self.epoch => (),
+            _ => return Outcome::rejected(Error::InvalidOperation),
+        }
+        let signed_refund = SignedCredit {
+            credit: refund,
+            actor_signature: refund_signature.clone(),
+        };
+        let replica_sig = signing.sign_validated_credit(&signed_refund)?;
+        Outcome::success(EscrowRefunded {
+            wallet: self.id.public_key(),
+            credit_id: *credit_id,
+            signed_refund,
+            replica_sig,
+            replicas: self.peer_replicas.clone(),
+        })
+    }
+
+    /// Declaration of the spending policy of a multisig wallet, by its owners.
+    /// The policy must be signed with the key shares of a quorum of the owners,
+    /// and of as many of them as the highest tier of both the declared
+    /// and the current policy requires.
+    pub fn declare_spending_policy(
+        &self,
+        policy: &SpendingPolicy,
+        signatures: &[SignatureShare],
+    ) -> Outcome<SpendingPolicyDeclared> {
+        let owners = match &self.id {
+            OwnerType::Multi(owners) => owners,
+            OwnerType::Single(_) => return Outcome::rejected(Error::InvalidOwner),
+        };
+        let policy_bytes = match bincode::serialize(policy) {
+            Err(_) => return Err(Error::Serialisation("Could not serialise policy".into())),
+            Ok(bytes) => bytes,
+        };
+        let signers: BTreeSet<_> = signatures
+            .iter()
+            .filter(|s| {
+                owners
+                    .public_key_share(s.index)
+                    .verify(&s.share, &policy_bytes)
+            })
+            .map(|s| s.index)
+            .collect();
+        if signers.len() != signatures.len() {
+            return Outcome::rejected(Error::InvalidSignature);
+        }
+        let required = self
+            .spending_policy
+            .iter()
+            .chain(Some(policy))
+            .map(|p| p.max_signers())
+            .fold(owners.threshold() + 1, usize::max);
+        if signers.len() < required {
+            return Outcome::rejected(Error::SpendingPolicyNotMet);
+        } else if self.spending_policy.as_ref() == Some(policy) {
+            return Outcome::no_change();
+        }
+        Outcome::success(SpendingPolicyDeclared {
+            wallet: self.id.public_key(),
+            policy: policy.clone(),
+        })
+    }
+
     /// Step 3. Validation of TransferAgreementProof, and credit idempotency at credit destination.
     /// (Since this leads to a credit, there is no requirement on order.)
-    pub fn receive_propagated(&self, credit_proof: &CreditAgreementProof) -> Outcome<()> {
+    /// The debiting Replicas must be those of a known section.
+    pub fn receive_propagated<K: KnownSections>(
+        &self,
+        credit_proof: &CreditAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<()> {
         // Always verify signature first! (as to not leak any information).
-        self.verify_propagated_proof(credit_proof)?;
+        self.verify_propagated_proof(credit_proof, known_sections)?;
         if self.wallet.contains(&credit_proof.id()) {
             Outcome::no_change()
         } else {
@@ -227,6 +899,64 @@ impl WalletReplica {
         }
     }
 
+    /// Crediting of the fee paid by a registered transfer, at the fee wallet of the section.
+    /// The proof must be signed by a known section, and the fee
+    /// is what was debited in excess of the credited amount.
+    pub fn receive_fee<K: KnownSections>(
+        &self,
+        transfer_proof: &TransferAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<FeeCredited> {
+        // Always verify signature first! (as to not leak any information).
+        let key_set = &transfer_proof.debiting_replicas_keys;
+        verify_section_signature(
+            key_set,
+            &transfer_proof.debit_sig,
+            &transfer_proof.signed_debit,
+            known_sections,
+        )?;
+        verify_section_signature(
+            key_set,
+            &transfer_proof.credit_sig,
+            &transfer_proof.signed_credit,
+            known_sections,
+        )?;
+        self.fee_credit(
+            &transfer_proof.signed_debit.debit,
+            transfer_proof.signed_credit.amount(),
+        )
+    }
+
+    /// Crediting of the fee paid by a registered batch, at the fee wallet of the section.
+    /// See [receive_fee](WalletReplica::receive_fee).
+    pub fn receive_batch_fee<K: KnownSections>(
+        &self,
+        transfer_proof: &BatchTransferAgreementProof,
+        known_sections: &K,
+    ) -> Outcome<FeeCredited> {
+        // Always verify signature first! (as to not leak any information).
+        let key_set = &transfer_proof.debiting_replicas_keys;
+        verify_section_signature(
+            key_set,
+            &transfer_proof.debit_sig,
+            &transfer_proof.signed_debit,
+            known_sections,
+        )?;
+        if transfer_proof.signed_credits.len() != transfer_proof.credit_sigs.len() {
+            return Err(Error::InvalidSignature);
+        }
+        for (signed_credit, sig) in transfer_proof
+            .signed_credits
+            .iter()
+            .zip(&transfer_proof.credit_sigs)
+        {
+            verify_section_signature(key_set, sig, signed_credit, known_sections)?;
+        }
+        let credited = sum_amounts(transfer_proof.signed_credits.iter().map(|c| c.amount()))
+            .ok_or(Error::CreditDebitValueMismatch)?;
+        self.fee_credit(&transfer_proof.signed_debit.debit, credited)
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Mutation ---------------------------------
     /// -----------------------------------------------------------------
@@ -236,7 +966,8 @@ impl WalletReplica {
     /// been properly validated before the fact is established (event raised),
     /// and thus anything that breaks here, is a bug in the validation..
     pub fn apply(&mut self, event: ReplicaEvent) -> Result<()> {
-        match event {
+        let balance = self.balance();
+        let applied = match event {
             ReplicaEvent::TransferValidationProposed(e) => {
                 let debit = &e.signed_debit.debit;
                 let index = e.signed_debit.actor_signature.index;
@@ -250,22 +981,79 @@ impl WalletReplica {
                 Ok(())
             }
             ReplicaEvent::TransferValidated(e) => {
-                let debit = e.signed_debit.debit;
-                self.pending_debit = Some(debit.id.counter);
+                let signers = self.proposal_signers(&e.signed_debit.debit);
+                self.apply_validated(e, signers);
                 Ok(())
             }
             ReplicaEvent::TransferRegistered(e) => {
-                let debit = e.transfer_proof.signed_debit.debit;
-                self.wallet.apply_debit(Debit {
-                    id: debit.id(),
-                    amount: debit.amount(),
-                })
+                let debit = e.transfer_proof.signed_debit.debit.clone();
+                let _ = self.pending_transfers.remove(&debit.id.counter);
+                let _ = self.registered_transfers.insert(
+                    debit.id.counter,
+                    SignedBatchTransfer {
+                        debit: e.transfer_proof.signed_debit,
+                        credits: vec![e.transfer_proof.signed_credit],
+                    },
+                );
+                self.apply_debit(debit)
             }
             ReplicaEvent::TransferPropagated(e) => {
                 let credit = e.credit_proof.signed_credit.credit;
-                self.wallet.apply_credit(credit)
+                self.apply_credit(credit)
             }
-        }
+        };
+        self.notify_balance(balance, &applied);
+        applied
+    }
+
+    /// Mutation of state, by the events of this crate.
+    /// Same as for [apply](WalletReplica::apply), the events are assumed
+    /// to have been raised by a successful validation.
+    pub fn apply_wallet_event(&mut self, event: WalletReplicaEvent) -> Result<()> {
+        let balance = self.balance();
+        let applied = match event {
+            WalletReplicaEvent::BatchTransferValidated(e) => {
+                self.apply_batch_validated(e, BTreeSet::new());
+                Ok(())
+            }
+            WalletReplicaEvent::MultisigTransferValidated(e) => {
+                self.apply_validated(e.validated, e.signers);
+                Ok(())
+            }
+            WalletReplicaEvent::MultisigBatchValidated(e) => {
+                self.apply_batch_validated(e.validated, e.signers);
+                Ok(())
+            }
+            WalletReplicaEvent::BatchTransferRegistered(e) => {
+                let debit = e.transfer_proof.signed_debit.debit.clone();
+                let _ = self.pending_batches.remove(&debit.id.counter);
+                let _ = self.registered_transfers.insert(
+                    debit.id.counter,
+                    SignedBatchTransfer {
+                        debit: e.transfer_proof.signed_debit,
+                        credits: e.transfer_proof.signed_credits,
+                    },
+                );
+                self.apply_debit(debit)
+            }
+            WalletReplicaEvent::FeeCredited(e) => self.apply_credit(e.credit),
+            WalletReplicaEvent::EscrowReleased(e) => self.wallet.apply_release(&e.credit_id),
+            WalletReplicaEvent::EscrowRefunded(e) => self.wallet.apply_refund(&e.credit_id),
+            WalletReplicaEvent::HtlcClaimed(e) => self.wallet.apply_release(&e.claim.credit_id),
+            WalletReplicaEvent::SpendingPolicyDeclared(e) => {
+                self.spending_policy = Some(e.policy);
+                Ok(())
+            }
+            WalletReplicaEvent::EquivocationDetected(e) => {
+                // the first evidence is kept
+                if self.equivocation.is_none() {
+                    self.equivocation = Some(e.proof);
+                }
+                Ok(())
+            }
+        };
+        self.notify_balance(balance, &applied);
+        applied
     }
 
     /// Test-helper API to simulate Client CREDIT Transfers.
@@ -284,6 +1072,199 @@ impl WalletReplica {
     /// ---------------------- Private methods --------------------------
     /// -----------------------------------------------------------------
 
+    fn apply_debit(&mut self, debit: Debit) -> Result<()> {
+        let (debit_id, amount) = (debit.id(), debit.amount());
+        self.wallet.apply_debit(debit)?;
+        self.subscribers.notify(Notification::DebitCompleted {
+            wallet: self.wallet.id().public_key(),
+            debit_id,
+            amount,
+        });
+        Ok(())
+    }
+
+    fn apply_credit(&mut self, credit: Credit) -> Result<()> {
+        let (credit_id, amount) = (credit.id, credit.amount);
+        self.wallet.apply_credit(credit)?;
+        self.subscribers.notify(Notification::CreditReceived {
+            wallet: self.wallet.id().public_key(),
+            credit_id,
+            amount,
+        });
+        Ok(())
+    }
+
+    fn notify_balance(&self, before: Token, applied: &Result<()>) {
+        if applied.is_ok() && self.balance() != before {
+            self.subscribers.notify(Notification::BalanceChanged {
+                wallet: self.wallet.id().public_key(),
+                balance: self.balance(),
+            });
+        }
+    }
+
+    fn notify_rejected(&self, signed_debit: &SignedDebit, outcome: &Outcome<()>) {
+        if let Err(error) = outcome {
+            self.subscribers.notify(Notification::ValidationRejected {
+                wallet: self.wallet.id().public_key(),
+                debit_id: signed_debit.id(),
+                error: error.clone(),
+            });
+        }
+    }
+
+    /// The unlocked balance, minus the amounts of all validated but not yet registered debits.
+    fn available_balance(&self) -> Token {
+        let pending = self
+            .pending_transfers
+            .values()
+            .map(|t| t.amount().as_nano())
+            .chain(
+                self.pending_batches
+                    .values()
+                    .map(|b| b.debit.amount().as_nano()),
+            )
+            .sum();
+        self.wallet
+            .unlocked_balance(self.epoch)
+            .checked_sub(Token::from_nano(pending))
+            .unwrap_or_else(Token::zero)
+    }
+
+    fn apply_validated(&mut self, e: TransferValidated, signers: BTreeSet<usize>) {
+        let counter = e.signed_debit.debit.id.counter;
+        // a re-validated transfer does not move the pending debit back
+        self.pending_debit = Some(self.pending_debit.map_or(counter, |c| c.max(counter)));
+        self.record_spend(&e.signed_debit.debit, signers);
+        let _ = self.pending_transfers.insert(
+            counter,
+            SignedTransfer {
+                debit: e.signed_debit,
+                credit: e.signed_credit,
+            },
+        );
+    }
+
+    fn apply_batch_validated(&mut self, e: BatchTransferValidated, signers: BTreeSet<usize>) {
+        let counter = e.signed_debit.debit.id.counter;
+        // a re-validated batch does not move the pending debit back
+        self.pending_debit = Some(self.pending_debit.map_or(counter, |c| c.max(counter)));
+        self.record_spend(&e.signed_debit.debit, signers);
+        let _ = self.pending_batches.insert(
+            counter,
+            SignedBatchTransfer {
+                debit: e.signed_debit,
+                credits: e.signed_credits,
+            },
+        );
+    }
+
+    /// Counts a validated debit against its signers, if the wallet has a spending policy.
+    fn record_spend(&mut self, debit: &Debit, signers: BTreeSet<usize>) {
+        if self.spending_policy.is_some() {
+            let spend = Spend {
+                epoch: self.epoch,
+                amount: debit.amount(),
+                signers,
+            };
+            let _ = self.spends.insert(debit.id.counter, spend);
+        }
+    }
+
+    /// The indices of the owners which have signed the debit: those of
+    /// the debit shares, combined into the wallet signature, and those
+    /// which have proposed it. A share not of the debit is rejected.
+    fn debit_signers(
+        &self,
+        debit: &Debit,
+        debit_shares: &[SignatureShare],
+    ) -> Result<BTreeSet<usize>> {
+        let mut signers = self.proposal_signers(debit);
+        if debit_shares.is_empty() {
+            return Ok(signers);
+        }
+        let owners = match &self.id {
+            OwnerType::Multi(owners) => owners,
+            OwnerType::Single(_) => return Err(Error::InvalidOwner),
+        };
+        let debit_bytes = match bincode::serialize(debit) {
+            Err(_) => return Err(Error::Serialisation("Could not serialise debit".into())),
+            Ok(bytes) => bytes,
+        };
+        for share in debit_shares {
+            if !owners
+                .public_key_share(share.index)
+                .verify(&share.share, &debit_bytes)
+            {
+                return Err(Error::InvalidSignature);
+            }
+            let _ = signers.insert(share.index);
+        }
+        Ok(signers)
+    }
+
+    /// The indices of the owners which have proposed the debit.
+    fn proposal_signers(&self, debit: &Debit) -> BTreeSet<usize> {
+        self.pending_proposals
+            .get(&debit.id.counter)
+            .into_iter()
+            .flat_map(|proposals| proposals.iter())
+            .filter(|(_, proposal)| &proposal.signed_debit.debit == debit)
+            .map(|(index, _)| *index)
+            .collect()
+    }
+
+    /// Whether the debit, signed by the owners of the indices, meets the
+    /// spending policy of the wallet, if any. A re-validated debit
+    /// is not counted against itself.
+    fn meets_spending_policy(&self, debit: &Debit, signers: &BTreeSet<usize>) -> bool {
+        match &self.spending_policy {
+            None => true,
+            Some(policy) => {
+                let spends = self
+                    .spends
+                    .iter()
+                    .filter(|(counter, _)| **counter != debit.id.counter)
+                    .map(|(_, spend)| spend);
+                policy.allows(debit.amount(), signers, spends, self.epoch)
+            }
+        }
+    }
+
+    /// The validated, or registered, transfer with the same debit id
+    /// as the signed transfer, if the two are in conflict.
+    /// See [EquivocationProof](EquivocationProof::verify).
+    fn conflicting_transfer(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+    ) -> Result<Option<SignedBatchTransfer>> {
+        let counter = signed_debit.id().counter;
+        let seen = match self.pending_transfers.get(&counter) {
+            Some(transfer) => SignedBatchTransfer {
+                debit: transfer.debit.clone(),
+                credits: vec![transfer.credit.clone()],
+            },
+            None => match self
+                .pending_batches
+                .get(&counter)
+                .or_else(|| self.registered_transfers.get(&counter))
+            {
+                Some(seen) => seen.clone(),
+                None => return Ok(None),
+            },
+        };
+        let transfer = SignedBatchTransfer {
+            debit: signed_debit.clone(),
+            credits: signed_credits.to_vec(),
+        };
+        if conflicts(&seen, &transfer)? {
+            Ok(Some(seen))
+        } else {
+            Ok(None)
+        }
+    }
+
     ///
     fn verify_actor_signature(
         &self,
@@ -321,6 +1302,61 @@ impl WalletReplica {
         }
     }
 
+    /// The credit of the fee paid by the debit, to this wallet, unless already credited.
+    fn fee_credit(&self, debit: &Debit, credited: Token) -> Outcome<FeeCredited> {
+        let fee = paid_fee(debit, credited)?;
+        let id = fee_credit_id(&debit.id)?;
+        if fee == Token::zero() || self.wallet.contains(&id) {
+            return Outcome::no_change();
+        }
+        Outcome::success(FeeCredited {
+            credit: Credit {
+                id,
+                amount: fee,
+                recipient: self.wallet.id().public_key(),
+                msg: "fee".to_string(),
+            },
+        })
+    }
+
+    /// Verify that the sender signed the debit and all the credits of the batch.
+    fn verify_batch_actor_signature(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credits: &[SignedCredit],
+    ) -> Result<()> {
+        let sender = signed_debit.sender();
+        verify_signature(&sender, &signed_debit.actor_signature, &signed_debit.debit)?;
+        for signed_credit in signed_credits {
+            verify_signature(
+                &sender,
+                &signed_credit.actor_signature,
+                &signed_credit.credit,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Verify that this is a valid _registered_
+    /// BatchTransferAgreementProof, i.e. signed by our peers.
+    fn verify_registered_batch_proof(&self, proof: &BatchTransferAgreementProof) -> Result<()> {
+        if proof.signed_credits.len() != proof.credit_sigs.len() {
+            return Err(Error::InvalidSignature);
+        }
+        for (index, signed_credit) in proof.signed_credits.iter().enumerate() {
+            if signed_credit.id() != &batch_credit_id(&proof.id(), index)? {
+                return Err(Error::CreditDebitIdMismatch);
+            }
+        }
+        // Check if proof is signed by our peers.
+        let public_key = sn_data_types::PublicKey::Bls(self.peer_replicas.public_key());
+        verify_signature(&public_key, &proof.debit_sig, &proof.signed_debit)?;
+        for (signed_credit, sig) in proof.signed_credits.iter().zip(&proof.credit_sigs) {
+            verify_signature(&public_key, sig, signed_credit)?;
+        }
+        Ok(())
+    }
+
     /// Verify that this is a valid _registered_
     /// TransferAgreementProof, i.e. signed by our peers.
     fn verify_registered_proof(&self, proof: &TransferAgreementProof) -> Result<()> {
@@ -348,15 +1384,24 @@ impl WalletReplica {
         Err(Error::InvalidSignature)
     }
 
-    /// Verify the sig over the CreditAgreementProof.
-    fn verify_propagated_proof(&self, proof: &CreditAgreementProof) -> Result<()> {
+    /// Verify the sig over the CreditAgreementProof,
+    /// and that it was signed by a known section.
+    fn verify_propagated_proof<K: KnownSections>(
+        &self,
+        proof: &CreditAgreementProof,
+        known_sections: &K,
+    ) -> Result<()> {
+        let section_key = section_key(&proof.debiting_replicas_keys)?;
         match bincode::serialize(&proof.signed_credit) {
-            Err(_) => Err(Error::Serialisation("Could not serialise transfer".into())),
-            Ok(credit_bytes) => {
-                let key = sn_data_types::PublicKey::Bls(proof.debiting_replicas_keys.public_key());
-                key.verify(&proof.debiting_replicas_sig, &credit_bytes)
-                    .map_err(|_| Error::InvalidSignature)
-            }
+            Err(_) => return Err(Error::Serialisation("Could not serialise transfer".into())),
+            Ok(credit_bytes) => sn_data_types::PublicKey::Bls(section_key)
+                .verify(&proof.debiting_replicas_sig, &credit_bytes)
+                .map_err(|_| Error::InvalidSignature)?,
+        }
+        if known_sections.is_known(&section_key) {
+            Ok(())
+        } else {
+            Err(Error::SectionKeyNeverExisted)
         }
     }
 }
@@ -383,14 +1428,22 @@ impl WalletReplica {
         if let Err(e) = self.verify_actor_signature_share(signed_transfer) {
             println!("Failed verification of actor sig!");
             return Outcome::rejected(e);
+        } else if self.is_frozen() {
+            return Outcome::rejected(Error::WalletFrozen);
         } else if debit.sender() == credit.recipient() {
             return Outcome::rejected(Error::SameSenderAndRecipient);
         } else if credit.id() != &debit.credit_id()? {
             return Outcome::rejected(Error::CreditDebitIdMismatch);
-        } else if credit.amount() != debit.amount() {
-            return Outcome::rejected(Error::CreditDebitValueMismatch);
-        } else if debit.amount() == Token::zero() {
+        } else if credit.amount() == Token::zero() {
             return Outcome::rejected(Error::ZeroValueTransfer);
+        } else if CreditCondition::decode(&credit.msg).is_err() {
+            // A malformed condition would not lock the credit at the recipient.
+            return Outcome::rejected(Error::InvalidOperation);
+        } else if let Err(error) = self
+            .fee_policy
+            .validate(debit.amount(), vec![(credit.amount(), credit.msg.as_str())])
+        {
+            return Outcome::rejected(error);
         } else if self.id.public_key() != debit.sender() {
             return Outcome::rejected(Error::NoSuchSender);
         } else if self.pending_debit.is_none() && debit.id.counter != 0 {
@@ -439,7 +1492,11 @@ impl WalletReplica {
         // If the previous count of accumulated + current proposal coming in here,
         // is greater than the threshold, then we have reached the numbers needed
         // to build the agreed_transfer (= threshold + 1).
-        let agreed = map.len() + 1 > actors.threshold() && self.id.public_key() == id.actor;
+        // With a spending policy, the shares must also be of enough signers for the amount.
+        let signers = map.keys().chain(Some(&share_index)).copied().collect();
+        let agreed = map.len() + 1 > actors.threshold()
+            && self.id.public_key() == id.actor
+            && self.meets_spending_policy(&signed_debit.debit, &signers);
 
         if !agreed {
             debug!("No agreement reached yet for proposal.");
@@ -561,18 +1618,52 @@ impl WalletReplica {
     }
 }
 
+/// Verify the signature by a known section, over the serialised item.
+fn verify_section_signature<T: Serialize, K: KnownSections>(
+    key_set: &PublicKeySet,
+    signature: &Signature,
+    item: &T,
+    known_sections: &K,
+) -> Result<()> {
+    let section_key = section_key(key_set)?;
+    verify_signature(&sn_data_types::PublicKey::Bls(section_key), signature, item)?;
+    if known_sections.is_known(&section_key) {
+        Ok(())
+    } else {
+        Err(Error::SectionKeyNeverExisted)
+    }
+}
+
+/// Verify the signature by the key, over the serialised item.
+fn verify_signature<T: Serialize>(
+    key: &sn_data_types::PublicKey,
+    signature: &Signature,
+    item: &T,
+) -> Result<()> {
+    match bincode::serialize(item) {
+        Err(_) => Err(Error::Serialisation("Could not serialise item".into())),
+        Ok(bytes) => key
+            .verify(signature, bytes)
+            .map_err(|_| Error::InvalidSignature),
+    }
+}
+
 impl fmt::Debug for WalletReplica {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "WalletReplica {{ id: {:?}, replica_id: {:?}, key_index: {:?}, peer_replicas: PkSet {{ public_key: {:?} }}, wallet: {:?}, pending_proposals: {:?}, pending_debit: {:?} }}",
+            "WalletReplica {{ id: {:?}, replica_id: {:?}, key_index: {:?}, peer_replicas: PkSet {{ public_key: {:?} }}, wallet: {:?}, pending_proposals: {:?}, pending_debit: {:?}, pending_transfers: {:?}, pending_batches: {:?}, equivocation: {:?}, spending_policy: {:?} }}",
             self.id,
             self.replica_id,
             self.key_index,
             self.peer_replicas.public_key(),
             self.wallet,
             self.pending_proposals,
-            self.pending_debit
+            self.pending_debit,
+            self.pending_transfers,
+            self.pending_batches,
+            self.equivocation,
+            self.spending_policy
         )
     }
 }